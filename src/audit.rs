@@ -0,0 +1,339 @@
+use crate::auth::{AuthMethod, Capability, RoleGuard};
+use crate::entities::audit_event::{self, AuditAction};
+use crate::entities::prelude::AuditEvent;
+use async_graphql::InputObject;
+use axum::extract::{self, ConnectInfo, Request};
+use axum::http::{header, HeaderValue};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use chrono::NaiveDateTime;
+use http::StatusCode;
+use sea_orm::entity::ActiveValue;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, Select,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::instrument;
+
+const REQUEST_ID: &str = "x-request-id";
+
+/// where a request came from and the id tying together everything it changed, added to every
+/// request by `request_info`
+#[derive(Clone, Debug, Default)]
+pub struct RequestInfo {
+    pub source_ip: Option<String>,
+    pub correlation_id: String,
+    /// the caller's `X-Request-Id`, only ever recorded next to `correlation_id` since a client
+    /// can send anything
+    pub client_request_id: Option<String>,
+}
+
+fn new_correlation_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// tag each request with a new correlation id, sent back as `X-Request-Id`, keeping the one the
+/// caller sent alongside it
+pub async fn request_info(mut request: Request, next: Next) -> Response {
+    let correlation_id = new_correlation_id();
+    let client_request_id = request
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 64)
+        .map(str::to_string);
+    let source_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|c| c.0.ip().to_string());
+    request.extensions_mut().insert(RequestInfo {
+        source_ip,
+        correlation_id: correlation_id.clone(),
+        client_request_id,
+    });
+    let mut response = next.run(request).await;
+    if let Ok(v) = HeaderValue::from_str(&correlation_id) {
+        response.headers_mut().insert(REQUEST_ID, v);
+    }
+    response
+}
+
+/// who is making a change, as recorded in the audit log
+#[derive(Clone, Debug)]
+pub struct Actor {
+    pub user: String,
+    pub method: String,
+    pub source_ip: Option<String>,
+    pub correlation_id: String,
+    pub client_request_id: Option<String>,
+}
+
+impl Actor {
+    pub fn new(user: &RoleGuard, request: Option<&RequestInfo>) -> Self {
+        Self::login(&user.user, user.method, request)
+    }
+
+    /// someone who hasn't got a token yet
+    pub fn login(user: &str, method: AuthMethod, request: Option<&RequestInfo>) -> Self {
+        Self {
+            user: user.to_string(),
+            method: format!("{:?}", method),
+            source_ip: request.and_then(|r| r.source_ip.clone()),
+            correlation_id: request
+                .map(|r| r.correlation_id.clone())
+                .unwrap_or_else(new_correlation_id),
+            client_request_id: request.and_then(|r| r.client_request_id.clone()),
+        }
+    }
+
+    /// the sync loop, a new one is made for each pass
+    pub fn sync() -> Self {
+        Self {
+            user: "ctt".to_string(),
+            method: "Sync".to_string(),
+            source_ip: None,
+            correlation_id: new_correlation_id(),
+            client_request_id: None,
+        }
+    }
+}
+
+/// `value` as it's stored in an event's before and after
+pub fn json(value: &impl Serialize) -> Option<Value> {
+    serde_json::to_value(value).ok()
+}
+
+/// append an event to the audit log, `db` should be the connection or transaction the change
+/// was made with so the event is rolled back with it
+#[instrument(skip(db, entity_id, before, after), fields(entity_id = %entity_id))]
+pub async fn record(
+    db: &impl ConnectionTrait,
+    actor: &Actor,
+    action: AuditAction,
+    entity: &str,
+    entity_id: impl fmt::Display,
+    before: Option<Value>,
+    after: Option<Value>,
+) {
+    audit_event::ActiveModel {
+        actor: ActiveValue::Set(actor.user.clone()),
+        method: ActiveValue::Set(actor.method.clone()),
+        source_ip: ActiveValue::Set(actor.source_ip.clone()),
+        action: ActiveValue::Set(action),
+        entity: ActiveValue::Set(entity.to_string()),
+        entity_id: ActiveValue::Set(entity_id.to_string()),
+        before: ActiveValue::Set(before),
+        after: ActiveValue::Set(after),
+        correlation_id: ActiveValue::Set(actor.correlation_id.clone()),
+        client_request_id: ActiveValue::Set(actor.client_request_id.clone()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .unwrap();
+}
+
+/// audit log filters shared by the auditLog query and `/audit.jsonl`, unset fields match
+/// everything
+#[derive(InputObject, Deserialize, Clone, Debug, Default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub entity: Option<String>,
+    pub entity_id: Option<String>,
+    pub correlation_id: Option<String>,
+    pub client_request_id: Option<String>,
+    /// events at or after
+    pub since: Option<NaiveDateTime>,
+    /// events before
+    pub until: Option<NaiveDateTime>,
+}
+
+impl AuditFilter {
+    pub fn apply(&self, mut select: Select<AuditEvent>) -> Select<AuditEvent> {
+        if let Some(a) = &self.actor {
+            select = select.filter(audit_event::Column::Actor.eq(a));
+        }
+        if let Some(a) = self.action {
+            select = select.filter(audit_event::Column::Action.eq(a));
+        }
+        if let Some(e) = &self.entity {
+            select = select.filter(audit_event::Column::Entity.eq(e));
+        }
+        if let Some(e) = &self.entity_id {
+            select = select.filter(audit_event::Column::EntityId.eq(e));
+        }
+        if let Some(c) = &self.correlation_id {
+            select = select.filter(audit_event::Column::CorrelationId.eq(c));
+        }
+        if let Some(c) = &self.client_request_id {
+            select = select.filter(audit_event::Column::ClientRequestId.eq(c));
+        }
+        if let Some(s) = self.since {
+            select = select.filter(audit_event::Column::At.gte(s));
+        }
+        if let Some(u) = self.until {
+            select = select.filter(audit_event::Column::At.lt(u));
+        }
+        select
+    }
+}
+
+/// the whole filtered audit log, oldest first, one json event per line
+pub async fn export_handler(
+    Extension(user): Extension<RoleGuard>,
+    Extension(db): Extension<Arc<DatabaseConnection>>,
+    extract::Query(filter): extract::Query<AuditFilter>,
+) -> Result<Response, (StatusCode, String)> {
+    user.require(Capability::AdminAudit)
+        .map_err(|e| (StatusCode::FORBIDDEN, e))?;
+    let events = filter
+        .apply(AuditEvent::find())
+        .order_by_asc(audit_event::Column::Id)
+        .all(db.as_ref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut body = String::new();
+    for e in events {
+        body.push_str(&serde_json::to_string(&e).unwrap());
+        body.push('\n');
+    }
+    Ok(([(header::CONTENT_TYPE, "application/jsonl")], body).into_response())
+}
+
+#[tokio::test]
+async fn mutations_are_audited_and_exported() {
+    use crate::auth::Role;
+    use crate::model::{test_execute, test_schema};
+    use axum::routing::get;
+    use tower::Service;
+
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let schema = test_schema(db.clone(), cluster);
+    let run = |role, correlation_id: &str, q: &str| {
+        let request = async_graphql::Request::new(q).data(RequestInfo {
+            source_ip: Some("10.0.0.7".to_string()),
+            correlation_id: correlation_id.to_string(),
+            client_request_id: None,
+        });
+        let schema = schema.clone();
+        async move { test_execute(&schema, role, "alice", request).await }
+    };
+
+    let resp = run(
+        Role::Admin,
+        "req-1",
+        r#"mutation { openMany(issue: {target: "gu[0001-0002],gu0009", title: "fw", description: "fw"}) { error } }"#,
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    // gu0009 failed and was rolled back along with its event
+    let opened = AuditEvent::find()
+        .filter(audit_event::Column::CorrelationId.eq("req-1"))
+        .all(&db)
+        .await
+        .unwrap();
+    assert_eq!(opened.len(), 2);
+    assert!(opened.iter().all(|e| e.action == AuditAction::Open
+        && e.actor == "alice"
+        && e.method == "Munge"
+        && e.source_ip.as_deref() == Some("10.0.0.7")
+        && e.before.is_none()));
+
+    let resp = run(
+        Role::Admin,
+        "req-2",
+        &format!(
+            r#"mutation {{ close(issue: {}, comment: "fixed") }}"#,
+            opened[0].entity_id
+        ),
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+
+    let query = r#"query { auditLog(filter: {correlationId: "req-2"}) { action, entityId, before, after } }"#;
+    let resp = run(Role::Admin, "req-3", query).await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    let log = resp.data.into_json().unwrap()["auditLog"].clone();
+    assert_eq!(log.as_array().unwrap().len(), 1);
+    assert_eq!(log[0]["action"], "CLOSE");
+    assert_eq!(log[0]["entityId"], opened[0].entity_id);
+    assert_eq!(log[0]["before"]["status"], "Opening");
+    assert_eq!(log[0]["after"]["status"], "Closing");
+    assert_eq!(run(Role::Guest, "req-4", query).await.errors.len(), 1);
+    let resp = run(
+        Role::Admin,
+        "req-4",
+        "query { auditLog(limit: 1001) { action } }",
+    )
+    .await;
+    assert_eq!(resp.errors[0].message, "limit can be at most 1000");
+
+    // reading the log isn't an action, and nothing can change what's in it
+    assert_eq!(AuditEvent::find().all(&db).await.unwrap().len(), 3);
+    assert!(AuditEvent::delete_many().exec(&db).await.is_err());
+
+    let admin = RoleGuard::new(
+        Role::Admin,
+        "alice".to_string(),
+        chrono::Utc::now().naive_utc() + chrono::Duration::minutes(60),
+    );
+    let mut app = axum::Router::new()
+        .route("/audit.jsonl", get(export_handler))
+        .route(
+            "/request",
+            get(|Extension(info): Extension<RequestInfo>| async move {
+                format!("{} {:?}", info.correlation_id, info.client_request_id)
+            }),
+        )
+        .layer(Extension(admin))
+        .layer(Extension(Arc::new(db)))
+        .layer(axum::middleware::from_fn(request_info));
+    let resp = app
+        .call(
+            axum::http::Request::get("/audit.jsonl?action=open")
+                .header(REQUEST_ID, "req-5")
+                .body(axum::body::Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    // the server picks the correlation id, the client's is only kept next to it
+    let correlation_id = resp.headers()[REQUEST_ID].to_str().unwrap().to_string();
+    assert_ne!(correlation_id, "req-5");
+    let body = axum::body::to_bytes(resp.into_body(), 1 << 20)
+        .await
+        .unwrap();
+    let events: Vec<audit_event::Model> = std::str::from_utf8(&body)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(events, opened);
+
+    let resp = app
+        .call(
+            axum::http::Request::get("/request")
+                .header(REQUEST_ID, "req-6")
+                .body(axum::body::Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let correlation_id = resp.headers()[REQUEST_ID].to_str().unwrap().to_string();
+    let body = axum::body::to_bytes(resp.into_body(), 1 << 20)
+        .await
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(&body).unwrap(),
+        format!("{} Some(\"req-6\")", correlation_id)
+    );
+}
@@ -1,28 +1,288 @@
-use crate::conf::{Auth, Conf};
-use async_graphql::{Context, Guard, Result};
+use crate::audit::{self, Actor, RequestInfo};
+use crate::conf::{Auth, Conf, NodeType};
+use crate::entities::api_key;
+use crate::entities::audit_event::AuditAction;
+use crate::entities::issue::ToOffline;
+use crate::entities::prelude::ApiKey;
+use crate::hostlist;
+use crate::login::{self, AuthRequest, LoginError};
+use crate::tls::{self, PeerCert};
+use async_graphql::{Context, Data, Guard, Result};
 use axum::body::Body;
-use axum::extract;
+use axum::extract::{self, ConnectInfo};
 use axum::http::header;
+use axum::response::IntoResponse;
 use axum::Extension;
 use chrono::{NaiveDateTime, Utc};
 use http::StatusCode;
-#[allow(unused_imports)]
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
-use lazy_static::lazy_static;
+use jsonwebtoken::{
+    decode, decode_header, encode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header,
+    Validation,
+};
 use rand::{distributions::Alphanumeric, Rng};
+use sea_orm::{DatabaseConnection, DbErr, DeriveActiveEnum, EntityTrait, EnumIter, Iterable};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
 use tower_http::validate_request::ValidateRequest;
-use tracing::{debug, info, warn};
+use tracing::{info, warn};
 
-lazy_static! {
-    static ref SECRET: String = {
-        rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(64)
-            .map(char::from)
-            .collect::<String>()
+/// file in `certs_dir` holding the jwt signing keys
+const KEY_FILE: &str = "jwt_keys.json";
+
+static KEYS: OnceLock<KeyRing> = OnceLock::new();
+
+/// marks a bearer token as an api key rather than a jwt
+pub const API_KEY_PREFIX: &str = "ctt_";
+
+/// api keys by hash, `validate` can't wait on the db so it checks this copy
+static API_KEYS: RwLock<BTreeMap<String, api_key::Model>> = RwLock::new(BTreeMap::new());
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SigningKey {
+    kid: String,
+    secret: String,
+    /// unix timestamp
+    created: i64,
+}
+
+impl SigningKey {
+    fn generate() -> Self {
+        Self {
+            kid: random_string(16),
+            secret: random_string(64),
+            created: Utc::now().timestamp(),
+        }
+    }
+}
+
+/// jwt signing keys, the newest signs new tokens and every key still verifies the tokens it
+/// signed, so restarts don't log anyone out and rotation doesn't either
+#[derive(Debug)]
+pub struct KeyRing {
+    path: PathBuf,
+    keys: RwLock<Vec<SigningKey>>,
+}
+
+impl KeyRing {
+    /// read the keys from `path`, generating the first one if the file doesn't exist
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let keys: Vec<SigningKey> = match fs::read(path) {
+            Ok(b) => serde_json::from_slice(&b).map_err(io::Error::other)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        let ring = Self {
+            path: path.to_path_buf(),
+            keys: RwLock::new(keys),
+        };
+        if ring.keys.read().unwrap().is_empty() {
+            info!("generating jwt signing key in {}", path.display());
+            let mut keys = ring.keys.write().unwrap();
+            keys.push(SigningKey::generate());
+            ring.save(&keys)?;
+        }
+        Ok(ring)
+    }
+
+    fn save(&self, keys: &[SigningKey]) -> io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut f = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)?;
+        serde_json::to_writer_pretty(&mut f, keys).map_err(io::Error::other)?;
+        f.sync_all()?;
+        fs::rename(tmp, &self.path)
+    }
+
+    /// start signing with a new key once the current one is `max_age` old, and drop keys that
+    /// stopped signing more than `token_lifetime` ago since every token they signed has expired
+    pub fn rotate(
+        &self,
+        max_age: chrono::Duration,
+        token_lifetime: chrono::Duration,
+    ) -> io::Result<bool> {
+        let now = Utc::now().timestamp();
+        let mut keys = self.keys.write().unwrap();
+        let mut changed = false;
+        if keys
+            .last()
+            .is_none_or(|k| k.created <= now - max_age.num_seconds())
+        {
+            info!("rotating jwt signing key");
+            keys.push(SigningKey::generate());
+            changed = true;
+        }
+        while keys.len() > 1 && keys[1].created <= now - token_lifetime.num_seconds() {
+            info!("retiring jwt signing key {}", keys[0].kid);
+            keys.remove(0);
+            changed = true;
+        }
+        if changed {
+            self.save(&keys)?;
+        }
+        Ok(changed)
+    }
+
+    pub fn sign(&self, claims: &RoleGuard) -> String {
+        let keys = self.keys.read().unwrap();
+        let key = keys.last().unwrap();
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(key.kid.clone());
+        encode(
+            &header,
+            claims,
+            &EncodingKey::from_secret(key.secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    pub fn verify(&self, token: &str) -> jsonwebtoken::errors::Result<RoleGuard> {
+        let kid = decode_header(token)?
+            .kid
+            .ok_or(jsonwebtoken::errors::Error::from(ErrorKind::InvalidToken))?;
+        let keys = self.keys.read().unwrap();
+        let key = keys
+            .iter()
+            .find(|k| k.kid == kid)
+            .ok_or(jsonwebtoken::errors::Error::from(
+                ErrorKind::InvalidSignature,
+            ))?;
+        decode::<RoleGuard>(
+            token,
+            &DecodingKey::from_secret(key.secret.as_bytes()),
+            &Validation::new(Algorithm::HS256),
+        )
+        .map(|t| t.claims)
+    }
+}
+
+/// load the signing keys from `certs_dir`, must be called before serving any requests
+pub fn init_keys(conf: &Conf) -> io::Result<()> {
+    let ring = KeyRing::load(&PathBuf::from(&conf.certs_dir).join(KEY_FILE))?;
+    KEYS.set(ring).expect("jwt keys already loaded");
+    Ok(())
+}
+
+fn keys() -> &'static KeyRing {
+    KEYS.get().expect("jwt keys not loaded")
+}
+
+#[cfg(test)]
+fn test_keys() -> &'static KeyRing {
+    KEYS.get_or_init(|| {
+        let path = std::env::temp_dir().join(format!("cttd-keys-{}.json", rand::random::<u64>()));
+        KeyRing::load(&path).unwrap()
+    })
+}
+
+pub fn generate_api_key() -> String {
+    format!("{}{}", API_KEY_PREFIX, random_string(40))
+}
+
+pub fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// reread the api keys `validate` accepts, needed after every change to the api_key table
+pub async fn load_api_keys(db: &DatabaseConnection) -> Result<(), DbErr> {
+    let keys = ApiKey::find().all(db).await?;
+    *API_KEYS.write().unwrap() = keys.into_iter().map(|k| (k.key_hash.clone(), k)).collect();
+    Ok(())
+}
+
+/// why a request was turned away, sent back to the client as `{"error": "<reason>"}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    Missing,
+    Malformed,
+    Expired,
+    InvalidSignature,
+    UnknownApiKey,
+    UnknownCert,
+}
+
+impl AuthError {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            AuthError::Missing => "missing",
+            AuthError::Malformed => "malformed",
+            AuthError::Expired => "expired",
+            AuthError::InvalidSignature => "invalid signature",
+            AuthError::UnknownApiKey => "unknown api key",
+            AuthError::UnknownCert => "unknown client certificate",
+        }
+    }
+
+    /// count and log a rejected request
+    fn record(self, addr: Option<SocketAddr>) -> Self {
+        metrics::counter!("ctt_auth_failures_total", "reason" => self.reason()).increment(1);
+        match addr {
+            Some(addr) => info!("rejected auth from {}: {}", addr, self.reason()),
+            None => info!("rejected auth: {}", self.reason()),
+        }
+        self
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for AuthError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        match e.kind() {
+            ErrorKind::ExpiredSignature => AuthError::Expired,
+            ErrorKind::InvalidSignature => AuthError::InvalidSignature,
+            _ => AuthError::Malformed,
+        }
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> axum::response::Response {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            axum::Json(serde_json::json!({ "error": self.reason() })),
+        )
+            .into_response()
+    }
+}
+
+/// the token from a `Bearer <token>` value
+fn bearer(value: &str) -> Result<&str, AuthError> {
+    value
+        .strip_prefix("Bearer ")
+        .filter(|t| !t.is_empty())
+        .ok_or(AuthError::Malformed)
+}
+
+/// replace the signing key every `key_rotation_days`
+pub async fn rotate_keys(auth: Auth) {
+    let Some(days) = auth.key_rotation_days else {
+        return;
     };
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+    loop {
+        interval.tick().await;
+        if let Err(e) = keys().rotate(chrono::Duration::days(days as i64), auth.token_lifetime()) {
+            warn!("unable to rotate jwt signing key: {}", e);
+        }
+    }
 }
 
 impl<B> ValidateRequest<B> for Auth {
@@ -32,128 +292,303 @@ impl<B> ValidateRequest<B> for Auth {
         &mut self,
         request: &mut axum::http::Request<B>,
     ) -> axum::response::Result<(), axum::response::Response> {
-        if let Some(user) = self.check_auth(request) {
-            // Set `user_id` as a request extension so it can be accessed by other
-            // services down the stack.
-            info!("Request validated for user {}", &user.user);
-            request.extensions_mut().insert(user);
-
-            Ok(())
-        } else {
-            let unauthorized_response: axum::response::Response =
-                axum::response::Response::builder()
-                    .status(StatusCode::UNAUTHORIZED)
-                    .body(Body::default())
-                    .unwrap();
-            info!("Invalid request");
-
-            Err(unauthorized_response)
+        match self.check_auth(request) {
+            Ok(user) => {
+                // Set `user_id` as a request extension so it can be accessed by other
+                // services down the stack.
+                info!("Request validated for user {}", &user.user);
+                request.extensions_mut().insert(user);
+                Ok(())
+            }
+            Err(e) => {
+                let addr = request
+                    .extensions()
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|c| c.0);
+                Err(e.record(addr).into_response())
+            }
         }
     }
 }
 
 impl Auth {
-    fn check_auth<B>(&self, request: &axum::http::Request<B>) -> Option<RoleGuard> {
+    fn check_auth<B>(&self, request: &axum::http::Request<B>) -> Result<RoleGuard, AuthError> {
         info!("checking auth");
-        request
-            .headers()
-            .get(header::AUTHORIZATION)
-            .and_then(|auth_header| auth_header.to_str().ok())
-            .and_then(|auth_value| {
-                auth_value
-                    .strip_prefix("Bearer ")
-                    .map(|stripped| stripped.to_owned())
-            })
-            .map(|t| {
-                decode::<RoleGuard>(
-                    &t,
-                    &DecodingKey::from_base64_secret(&SECRET).unwrap(),
-                    &Validation::new(Algorithm::HS256),
-                )
-                .unwrap()
-            })
-            .map(|c| c.claims)
-    }
-
-    async fn check_role(&self, usr: &str, uid: u32) -> Option<Role> {
-        let user = users::get_user_by_name(usr)?;
-        if user.uid() != uid {
-            debug!(
-                "UID does not match expected user: {:?} expected uid: {}",
-                usr, uid
+        let Some(value) = request.headers().get(header::AUTHORIZATION) else {
+            return self.check_client_cert(request.extensions().get::<PeerCert>());
+        };
+        self.check_token(bearer(value.to_str().map_err(|_| AuthError::Malformed)?)?)
+    }
+
+    /// an api key, which gets the capabilities its role has in the config now
+    fn check_api_key(&self, key: &str) -> Result<RoleGuard, AuthError> {
+        let keys = API_KEYS.read().unwrap();
+        let k = keys
+            .get(&hash_api_key(key))
+            .ok_or(AuthError::UnknownApiKey)?;
+        if let Some(exp) = k.expires_at
+            && exp <= Utc::now().naive_utc()
+        {
+            info!("api key {} expired at {}", k.name, exp);
+            return Err(AuthError::Expired);
+        }
+        let Some((role, capabilities)) = self.named_role(&k.role) else {
+            warn!(
+                "api key {} has role {} which isn't configured",
+                k.name, k.role
             );
-            return None;
+            return Err(AuthError::UnknownApiKey);
+        };
+        Ok(RoleGuard {
+            exp: k
+                .expires_at
+                .map_or(usize::MAX, |e| e.and_utc().timestamp() as usize),
+            method: AuthMethod::ApiKey,
+            nodes: k
+                .prefixes()
+                .map(|p| p.into_iter().map(NodeScope::Prefix).collect()),
+            ..RoleGuard::named(role, capabilities, k.name.clone(), NaiveDateTime::MAX)
+        })
+    }
+
+    /// a jwt from /login or an api key
+    fn check_token(&self, token: &str) -> Result<RoleGuard, AuthError> {
+        if token.starts_with(API_KEY_PREFIX) {
+            self.check_api_key(token)
+        } else {
+            Ok(keys().verify(token)?)
         }
-        let groups: HashSet<String> = user
-            .groups()?
+    }
+
+    /// a client certificate rustls has already verified against `client_ca`
+    fn check_client_cert(&self, cert: Option<&PeerCert>) -> Result<RoleGuard, AuthError> {
+        let Some(PeerCert(Some(cert))) = cert else {
+            return Err(AuthError::Missing);
+        };
+        let id = tls::identity(cert).map_err(|_| AuthError::Malformed)?;
+        let c = self
+            .client_certs
             .iter()
-            .map(|g| g.name().to_os_string().into_string())
-            .filter_map(|x| x.ok())
-            .collect();
-        for g in &self.admin {
-            if groups.contains(g) {
-                info!("admin!");
-                return Some(Role::Admin);
+            .find(|c| id.names.contains(&c.name))
+            .ok_or(AuthError::UnknownCert)?;
+        let (role, capabilities) = self.named_role(&c.role).ok_or(AuthError::UnknownCert)?;
+        Ok(RoleGuard {
+            exp: id.not_after as usize,
+            method: AuthMethod::ClientCert,
+            ..RoleGuard::named(
+                role,
+                capabilities,
+                c.user.clone().unwrap_or(c.name.clone()),
+                NaiveDateTime::MAX,
+            )
+        })
+    }
+
+    /// websocket clients can't always set headers, so subscriptions authenticate with the
+    /// connection_init payload instead, `{"Authorization": "Bearer <token>"}`, or the client
+    /// certificate the connection was made with
+    pub async fn ws_connection_init(
+        &self,
+        payload: serde_json::Value,
+        addr: SocketAddr,
+        cert: Option<PeerCert>,
+    ) -> Result<Data> {
+        let user = match payload.get("Authorization") {
+            None => self.check_client_cert(cert.as_ref()),
+            Some(v) => v
+                .as_str()
+                .ok_or(AuthError::Malformed)
+                .and_then(bearer)
+                .and_then(|t| self.check_token(t)),
+        };
+        match user {
+            Ok(user) => {
+                info!("Subscription validated for user {}", &user.user);
+                let mut data = Data::default();
+                data.insert(user);
+                Ok(data)
             }
+            Err(e) => Err(e.record(Some(addr)).reason().into()),
         }
-        for g in &self.guest {
-            if groups.contains(g) {
-                info!("guest!");
-                return Some(Role::Guest);
+    }
+
+    /// `admin`, `guest` or one of `roles` by name
+    pub fn named_role(&self, name: &str) -> Option<(String, Vec<Capability>)> {
+        for role in Role::iter() {
+            if format!("{:?}", role).eq_ignore_ascii_case(name) {
+                return Some((format!("{:?}", role), role.capabilities()));
             }
         }
+        self.roles
+            .iter()
+            .find(|r| r.name == name)
+            .map(|r| (r.name.clone(), r.capabilities.clone()))
+    }
+
+    /// admin wins, then the configured roles in order, then guest
+    pub fn role_for(&self, groups: &HashSet<String>) -> Option<(String, Vec<Capability>)> {
+        let builtin = |role: Role| Some((format!("{:?}", role), role.capabilities()));
+        if self.admin.iter().any(|g| groups.contains(g)) {
+            info!("admin!");
+            return builtin(Role::Admin);
+        }
+        if let Some(r) = self
+            .roles
+            .iter()
+            .find(|r| r.groups.iter().any(|g| groups.contains(g)))
+        {
+            info!("{}!", r.name);
+            return Some((r.name.clone(), r.capabilities.clone()));
+        }
+        if self.guest.iter().any(|g| groups.contains(g)) {
+            info!("guest!");
+            return builtin(Role::Guest);
+        }
         None
     }
 }
+/// log in with any of the configured backends, see `login::backends`
 pub async fn login_handler(
     Extension(conf): Extension<Conf>,
-    extract::Json(raw_payload): extract::Json<AuthRequest>,
+    Extension(db): Extension<Arc<DatabaseConnection>>,
+    info: Option<Extension<RequestInfo>>,
+    extract::Json(request): extract::Json<AuthRequest>,
 ) -> Result<axum::Json<Token>, (StatusCode, String)> {
-    match raw_payload {
-        // currently munge is the only supported auth method
-        AuthRequest::Munge(payload) => {
-            let payload = munge_auth::unmunge(payload.to_string());
-            if let Err(e) = payload {
-                warn!("unable to unmunge payload: {}", e);
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    "Unable to deserialize request".to_string(),
-                ));
-            }
-            let payload: munge_auth::Message = payload.unwrap();
-            info!("Login request: {:?}", payload);
-            let uid = payload.uid;
-            let payload = serde_json::from_str(&payload.msg);
-            if payload.is_err() {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    "Unable to deserialize request".to_string(),
-                ));
-            }
-            let payload: UserLogin = payload.unwrap();
-            info!("Login request: {:?}", payload);
-            let role = conf.auth.check_role(&payload.user, uid).await;
-            if role.is_none() {
-                info!("bad user");
-                return Err((StatusCode::FORBIDDEN, "User not authorized".to_string()));
-            }
-            let role = role.unwrap();
-            let key = EncodingKey::from_base64_secret(&SECRET).unwrap();
-            let claims = RoleGuard::new(
-                role,
-                // using payload.user is fine since its been authenticated via munge
-                payload.user,
-                Utc::now().naive_utc() + chrono::Duration::minutes(60),
-            );
-            let token = encode(&Header::default(), &claims, &key).unwrap();
-            Ok(axum::Json(Token { token }))
+    let info = info.map(|i| i.0);
+    let failed = |user: &str, method: AuthMethod| {
+        let actor = Actor::login(user, method, info.as_ref());
+        let db = db.clone();
+        async move {
+            audit::record(
+                db.as_ref(),
+                &actor,
+                AuditAction::LoginFailed,
+                "user",
+                &actor.user,
+                None,
+                None,
+            )
+            .await
+        }
+    };
+    let id = match login::login(&conf.auth, &request).await {
+        Ok(id) => id,
+        Err(LoginError::BadRequest(e)) => return Err((StatusCode::BAD_REQUEST, e)),
+        Err(LoginError::Denied) => {
+            failed(request.user().unwrap_or("unknown"), request.method()).await;
+            return Err((StatusCode::UNAUTHORIZED, "Invalid credentials".to_string()));
         }
+        Err(LoginError::Unavailable(e)) => return Err((StatusCode::SERVICE_UNAVAILABLE, e)),
+    };
+    let Some((role, capabilities)) = conf.auth.role_for(&id.groups) else {
+        info!("bad user");
+        failed(&id.user, id.method).await;
+        return Err((StatusCode::FORBIDDEN, "User not authorized".to_string()));
+    };
+    let claims = RoleGuard {
+        nodes: conf.node_scope(&id.groups),
+        method: id.method,
+        ..RoleGuard::named(
+            role,
+            capabilities,
+            id.user,
+            Utc::now().naive_utc() + conf.auth.token_lifetime(),
+        )
+    };
+    let actor = Actor::new(&claims, info.as_ref());
+    audit::record(
+        db.as_ref(),
+        &actor,
+        AuditAction::Login,
+        "user",
+        &claims.user,
+        None,
+        audit::json(&claims),
+    )
+    .await;
+    let token = keys().sign(&claims);
+    Ok(axum::Json(Token { token }))
+}
+
+/// swap a still valid token for one with a new expiry, until the session is
+/// `auth.session_hours` old
+pub async fn refresh_handler(
+    Extension(conf): Extension<Conf>,
+    Extension(db): Extension<Arc<DatabaseConnection>>,
+    Extension(user): Extension<RoleGuard>,
+    info: Option<Extension<RequestInfo>>,
+) -> Result<axum::Json<Token>, (StatusCode, String)> {
+    // a token would outlive the key being deleted
+    if user.method == AuthMethod::ApiKey {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "api keys can't be exchanged for tokens".to_string(),
+        ));
+    }
+    let logged_in = chrono::DateTime::from_timestamp(user.auth_time as i64, 0)
+        .unwrap_or_default()
+        .naive_utc();
+    if logged_in + conf.auth.max_session_age() <= Utc::now().naive_utc() {
+        info!("Session for {} is too old to refresh", user.user);
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "session expired, log in again".to_string(),
+        ));
     }
+    info!("Refreshing token for {}", user.user);
+    let before = audit::json(&user);
+    let claims = RoleGuard {
+        exp: (Utc::now().naive_utc() + conf.auth.token_lifetime())
+            .and_utc()
+            .timestamp() as usize,
+        ..reauthorize(&conf, user).await?
+    };
+    audit::record(
+        db.as_ref(),
+        &Actor::new(&claims, info.as_ref().map(|i| &i.0)),
+        AuditAction::Refresh,
+        "user",
+        &claims.user,
+        before,
+        audit::json(&claims),
+    )
+    .await;
+    Ok(axum::Json(Token {
+        token: keys().sign(&claims),
+    }))
 }
 
-#[derive(Deserialize, Debug)]
-pub struct UserLogin {
-    user: String,
+/// `user` with the role and nodes the current config gives them, for the login methods that
+/// can look them up again without the user's credentials
+async fn reauthorize(conf: &Conf, user: RoleGuard) -> Result<RoleGuard, (StatusCode, String)> {
+    let denied = || (StatusCode::FORBIDDEN, "User not authorized".to_string());
+    if user.method == AuthMethod::ClientCert {
+        let c = conf
+            .auth
+            .client_certs
+            .iter()
+            .find(|c| c.user.as_ref().unwrap_or(&c.name) == &user.user)
+            .ok_or_else(denied)?;
+        let (role, capabilities) = conf.auth.named_role(&c.role).ok_or_else(denied)?;
+        return Ok(RoleGuard {
+            role,
+            capabilities,
+            ..user
+        });
+    }
+    let groups = match login::groups(&conf.auth, &user.user, user.method).await {
+        Ok(Some(groups)) => groups,
+        Ok(None) => return Ok(user),
+        Err(LoginError::Unavailable(e)) => return Err((StatusCode::SERVICE_UNAVAILABLE, e)),
+        Err(_) => return Err(denied()),
+    };
+    let (role, capabilities) = conf.auth.role_for(&groups).ok_or_else(denied)?;
+    Ok(RoleGuard {
+        role,
+        capabilities,
+        nodes: conf.node_scope(&groups),
+        ..user
+    })
 }
 
 #[derive(Serialize)]
@@ -161,51 +596,678 @@ pub struct Token {
     token: String,
 }
 
-#[derive(Deserialize, Debug, Clone)]
-pub enum AuthRequest {
-    // munge encrypted Json<UserLogin>
-    Munge(String),
-}
-
-#[derive(Eq, PartialEq, Copy, Clone, Serialize, Deserialize, Debug)]
+#[derive(
+    Eq,
+    PartialEq,
+    Copy,
+    Clone,
+    Serialize,
+    Deserialize,
+    Debug,
+    EnumIter,
+    DeriveActiveEnum,
+    async_graphql::Enum,
+)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "role")]
 pub enum Role {
+    #[sea_orm(string_value = "Admin")]
     Admin,
+    #[sea_orm(string_value = "Guest")]
     Guest,
 }
 
+impl Role {
+    /// what the built in roles grant, admins can do everything
+    pub fn capabilities(&self) -> Vec<Capability> {
+        match self {
+            Role::Admin => Capability::iter().collect(),
+            Role::Guest => vec![Capability::IssueRead],
+        }
+    }
+}
+
+/// something a role lets its members do, written as `issue:read` etc in the config
+#[derive(
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Copy,
+    Clone,
+    Serialize,
+    Deserialize,
+    Debug,
+    EnumIter,
+    async_graphql::Enum,
+)]
+pub enum Capability {
+    #[serde(rename = "issue:read")]
+    IssueRead,
+    #[serde(rename = "issue:comment")]
+    IssueComment,
+    #[serde(rename = "issue:open")]
+    IssueOpen,
+    #[serde(rename = "issue:close")]
+    IssueClose,
+    /// open or update issues that take down a whole blade
+    #[serde(rename = "issue:set_to_offline_blade")]
+    IssueSetToOfflineBlade,
+    /// see the sync plan and acknowledge its alerts
+    #[serde(rename = "admin:sync")]
+    AdminSync,
+    /// create and delete api keys
+    #[serde(rename = "admin:keys")]
+    AdminKeys,
+    /// read the audit log
+    #[serde(rename = "admin:audit")]
+    AdminAudit,
+}
+
+impl Capability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Capability::IssueRead => "issue:read",
+            Capability::IssueComment => "issue:comment",
+            Capability::IssueOpen => "issue:open",
+            Capability::IssueClose => "issue:close",
+            Capability::IssueSetToOfflineBlade => "issue:set_to_offline_blade",
+            Capability::AdminSync => "admin:sync",
+            Capability::AdminKeys => "admin:keys",
+            Capability::AdminAudit => "admin:audit",
+        }
+    }
+}
+
+/// how the caller proved who they are
+#[derive(Eq, PartialEq, Copy, Clone, Serialize, Deserialize, Debug, Default)]
+pub enum AuthMethod {
+    #[default]
+    Munge,
+    ApiKey,
+    ClientCert,
+    Password,
+    Ldap,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RoleGuard {
-    role: Role,
+    role: String,
+    capabilities: Vec<Capability>,
     pub user: String,
     pub exp: usize,
+    #[serde(default)]
+    pub method: AuthMethod,
+    /// when the caller logged in, unix timestamp, kept across refreshes
+    #[serde(default)]
+    pub auth_time: usize,
+    /// nodes the caller may act on, any node if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nodes: Option<Vec<NodeScope>>,
+}
+
+/// a set of nodes an api key or acl rule limits a caller to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeScope {
+    /// every node whose name starts with this
+    Prefix(String),
+    /// every node of a configured node type
+    NodeType(NodeType),
+    /// a hostlist, eg `gu[0001-0010]`
+    Hosts(String),
+}
+
+impl NodeScope {
+    pub fn contains(&self, node: &str) -> bool {
+        match self {
+            NodeScope::Prefix(p) => node.starts_with(p.as_str()),
+            NodeScope::NodeType(t) => t.matches(node),
+            NodeScope::Hosts(h) => hostlist::expand(h).is_ok_and(|h| h.iter().any(|n| n == node)),
+        }
+    }
+}
+
+impl std::fmt::Display for NodeScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeScope::Prefix(p) => write!(f, "{}*", p),
+            NodeScope::NodeType(t) => match t.digits {
+                Some(d) => write!(f, "{}{}", t.prefix, "#".repeat(d)),
+                None => write!(f, "{}#", t.prefix),
+            },
+            NodeScope::Hosts(h) => write!(f, "{}", h),
+        }
+    }
 }
 
 impl RoleGuard {
+    #[cfg(test)]
     pub fn new(role: Role, user: String, exp: NaiveDateTime) -> Self {
+        Self::named(format!("{:?}", role), role.capabilities(), user, exp)
+    }
+
+    /// a role from the config rather than one of the built in ones
+    pub fn named(
+        role: String,
+        capabilities: Vec<Capability>,
+        user: String,
+        exp: NaiveDateTime,
+    ) -> Self {
         Self {
             role,
+            capabilities,
             user,
             exp: exp.and_utc().timestamp() as usize,
+            method: AuthMethod::Munge,
+            auth_time: Utc::now().timestamp() as usize,
+            nodes: None,
+        }
+    }
+
+    pub fn role(&self) -> &str {
+        &self.role
+    }
+
+    pub fn capabilities(&self) -> &[Capability] {
+        &self.capabilities
+    }
+
+    pub fn can(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    pub fn require(&self, capability: Capability) -> Result<(), String> {
+        if self.can(capability) {
+            Ok(())
+        } else {
+            Err(format!("{} is missing {}", self.user, capability.as_str()))
+        }
+    }
+
+    /// taking down a whole blade needs its own capability
+    pub fn check_to_offline(&self, to_offline: Option<ToOffline>) -> Result<(), String> {
+        match to_offline {
+            Some(ToOffline::Blade) => self.require(Capability::IssueSetToOfflineBlade),
+            _ => Ok(()),
+        }
+    }
+
+    /// error naming the first node the caller may not act on
+    pub fn check_nodes<S: AsRef<str>>(&self, nodes: &[S]) -> Result<(), String> {
+        let Some(scopes) = &self.nodes else {
+            return Ok(());
+        };
+        match nodes
+            .iter()
+            .map(AsRef::as_ref)
+            .find(|n| !scopes.iter().any(|s| s.contains(n)))
+        {
+            Some(n) => Err(format!("{} may not act on {}", self.user, n)),
+            None => Ok(()),
         }
     }
 }
 
-pub struct RoleChecker {
-    role: Role,
+pub struct CapabilityChecker {
+    capability: Capability,
 }
 
-impl RoleChecker {
-    pub fn new(role: Role) -> Self {
-        Self { role }
+impl CapabilityChecker {
+    pub fn new(capability: Capability) -> Self {
+        Self { capability }
     }
 }
 
-impl Guard for RoleChecker {
+impl Guard for CapabilityChecker {
     async fn check(&self, ctx: &Context<'_>) -> Result<()> {
-        if ctx.data_opt::<RoleGuard>().ok_or("no role")?.role == self.role {
+        if ctx
+            .data_opt::<RoleGuard>()
+            .ok_or("no role")?
+            .can(self.capability)
+        {
             Ok(())
         } else {
             Err("Insufficient Permission".into())
         }
     }
 }
+
+#[test]
+fn key_ring_persists_and_rotates() {
+    let path = std::env::temp_dir().join(format!("cttd-keys-{}.json", rand::random::<u64>()));
+    let ring = KeyRing::load(&path).unwrap();
+    let claims = RoleGuard::new(
+        Role::Admin,
+        "alice".to_string(),
+        Utc::now().naive_utc() + chrono::Duration::minutes(60),
+    );
+    let token = ring.sign(&claims);
+    assert_eq!(ring.verify(&token).unwrap().user, "alice");
+
+    // a restart reads the same key back
+    let ring = KeyRing::load(&path).unwrap();
+    assert_eq!(ring.verify(&token).unwrap().user, "alice");
+
+    // the old key verifies until every token it signed has expired
+    assert!(ring
+        .rotate(chrono::Duration::zero(), chrono::Duration::minutes(60))
+        .unwrap());
+    let new_token = ring.sign(&claims);
+    assert_ne!(
+        decode_header(&token).unwrap().kid,
+        decode_header(&new_token).unwrap().kid
+    );
+    assert!(ring.verify(&token).is_ok());
+    assert!(ring
+        .rotate(chrono::Duration::minutes(60), chrono::Duration::zero())
+        .unwrap());
+    assert!(ring.verify(&token).is_err());
+    assert!(ring.verify(&new_token).is_ok());
+
+    // tokens from another ring don't verify
+    let other = std::env::temp_dir().join(format!("cttd-keys-{}.json", rand::random::<u64>()));
+    assert!(KeyRing::load(&other).unwrap().verify(&new_token).is_err());
+    let _ = fs::remove_file(path);
+    let _ = fs::remove_file(other);
+}
+
+#[tokio::test]
+async fn api_keys_act_as_their_name() {
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let schema = crate::model::test_schema(db, cluster);
+    let conf: Conf = serde_json::from_value(serde_json::json!({
+        "poll_interval": 60,
+        "slack": {"channel": "", "token": ""},
+        "db": "",
+        "certs_dir": "",
+        "server_addr": "",
+        "node_types": [],
+        "auth": {
+            "admin": [],
+            "guest": [],
+            "roles": [{"name": "triage", "groups": [], "capabilities": ["issue:read"]}],
+        },
+    }))
+    .unwrap();
+    let auth = conf.auth.clone();
+    let create = |q: &str| {
+        crate::model::test_execute(
+            &schema,
+            Role::Admin,
+            "alice",
+            async_graphql::Request::new(q).data(conf.clone()),
+        )
+    };
+    let request = |key: &str| {
+        axum::http::Request::builder()
+            .header(header::AUTHORIZATION, format!("Bearer {}", key))
+            .body(())
+            .unwrap()
+    };
+
+    let resp = create(
+        "mutation { createApiKey(name: \"healthcheck\", role: \"admin\", nodes: [\"gu0001\"]) { key, apiKey { name, role, createdBy, nodes } } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    let data = resp.data.into_json().unwrap();
+    let key = data["createApiKey"]["key"].as_str().unwrap().to_string();
+    assert!(key.starts_with(API_KEY_PREFIX));
+    assert_eq!(
+        data["createApiKey"]["apiKey"],
+        serde_json::json!({"name": "healthcheck", "role": "Admin", "createdBy": "alice", "nodes": ["gu0001"]})
+    );
+
+    // configured roles work too, unknown ones are turned away
+    let resp =
+        create("mutation { createApiKey(name: \"triage-bot\", role: \"triage\") { key } }").await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    let data = resp.data.into_json().unwrap();
+    let triage = auth
+        .check_auth(&request(data["createApiKey"]["key"].as_str().unwrap()))
+        .unwrap();
+    assert_eq!(triage.role(), "triage");
+    assert_eq!(triage.capabilities, vec![Capability::IssueRead]);
+    let resp = create("mutation { createApiKey(name: \"other\", role: \"nope\") { key } }").await;
+    assert_eq!(resp.errors[0].message, "no role named nope");
+
+    let guard = auth.check_auth(&request(&key)).unwrap();
+    assert_eq!(guard.user, "healthcheck");
+    assert_eq!(guard.method, AuthMethod::ApiKey);
+    assert_eq!(
+        auth.check_auth(&request("ctt_notakey")).err(),
+        Some(AuthError::UnknownApiKey)
+    );
+
+    let open = |target: &str| {
+        async_graphql::Request::new(format!(
+            "mutation {{ open(issue: {{title: \"hc\", description: \"hc\", target: \"{}\"}}) {{ createdBy }} }}",
+            target
+        ))
+        .data(guard.clone())
+    };
+    let resp = schema.execute(open("gu0001")).await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"open": {"createdBy": "healthcheck"}})
+    );
+    // outside the key's node prefixes
+    assert_eq!(schema.execute(open("gu0002")).await.errors.len(), 1);
+    // keys can't mint more keys
+    let resp = schema
+        .execute(
+            async_graphql::Request::new(
+                "mutation { createApiKey(name: \"other\", role: \"admin\") { key } }",
+            )
+            .data(guard.clone())
+            .data(conf.clone()),
+        )
+        .await;
+    assert_eq!(resp.errors.len(), 1);
+
+    let resp = crate::model::test_execute(
+        &schema,
+        Role::Admin,
+        "alice",
+        "mutation { deleteApiKey(name: \"healthcheck\") }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        auth.check_auth(&request(&key)).err(),
+        Some(AuthError::UnknownApiKey)
+    );
+}
+
+#[cfg(test)]
+fn bearer_request(value: &[u8]) -> axum::http::Request<()> {
+    let mut req = axum::http::Request::new(());
+    if let Ok(v) = header::HeaderValue::from_bytes(value) {
+        req.headers_mut().insert(header::AUTHORIZATION, v);
+    }
+    req
+}
+
+#[cfg(test)]
+pub(crate) fn test_auth() -> Auth {
+    Auth {
+        admin: vec![],
+        guest: vec![],
+        token_minutes: 60,
+        session_hours: 24,
+        key_rotation_days: None,
+        roles: vec![],
+        client_ca: None,
+        client_certs: vec![],
+        password_file: None,
+        ldap: None,
+    }
+}
+
+#[tokio::test]
+async fn bad_tokens_get_401_with_a_reason() {
+    let ring = test_keys();
+    let auth = test_auth();
+    let alice = |minutes| {
+        RoleGuard::new(
+            Role::Guest,
+            "alice".to_string(),
+            Utc::now().naive_utc() + chrono::Duration::minutes(minutes),
+        )
+    };
+    let check = |value: &str| {
+        auth.check_auth(&bearer_request(value.as_bytes()))
+            .map(|u| u.user)
+    };
+
+    assert_eq!(
+        check(&format!("Bearer {}", ring.sign(&alice(10)))),
+        Ok("alice".to_string())
+    );
+    assert_eq!(
+        check(&format!("Bearer {}", ring.sign(&alice(-10)))),
+        Err(AuthError::Expired)
+    );
+    let other = std::env::temp_dir().join(format!("cttd-keys-{}.json", rand::random::<u64>()));
+    let forged = KeyRing::load(&other).unwrap().sign(&alice(10));
+    assert_eq!(
+        check(&format!("Bearer {}", forged)),
+        Err(AuthError::InvalidSignature)
+    );
+    // right kid, wrong secret
+    let mut token = ring.sign(&alice(10));
+    let sig = token.rfind('.').unwrap();
+    token.replace_range(sig + 1.., "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA");
+    assert_eq!(
+        check(&format!("Bearer {}", token)),
+        Err(AuthError::InvalidSignature)
+    );
+    assert_eq!(check("Bearer not.a.jwt"), Err(AuthError::Malformed));
+    assert_eq!(check("Bearer "), Err(AuthError::Malformed));
+    assert_eq!(
+        check("Basic YWxpY2U6aHVudGVyMg=="),
+        Err(AuthError::Malformed)
+    );
+    assert_eq!(
+        auth.check_auth(&axum::http::Request::new(())).err(),
+        Some(AuthError::Missing)
+    );
+
+    let mut req = bearer_request(format!("Bearer {}", ring.sign(&alice(-10))).as_bytes());
+    let resp = ValidateRequest::<()>::validate(&mut test_auth(), &mut req).unwrap_err();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let body = axum::body::to_bytes(resp.into_body(), 1024).await.unwrap();
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+        serde_json::json!({"error": "expired"})
+    );
+
+    let err = test_auth()
+        .ws_connection_init(
+            serde_json::json!({"Authorization": "Bearer not.a.jwt"}),
+            "127.0.0.1:1234".parse().unwrap(),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.message, "malformed");
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn arbitrary_headers_never_authenticate(value in proptest::collection::vec(proptest::num::u8::ANY, 0..256)) {
+        test_keys();
+        proptest::prop_assert!(test_auth().check_auth(&bearer_request(&value)).is_err());
+    }
+
+    #[test]
+    fn jwt_shaped_tokens_are_rejected(
+        token in "[A-Za-z0-9_=+/-]{0,64}(\\.[A-Za-z0-9_=+/-]{0,64}){0,3}",
+        prefix in "(Bearer |bearer |Bearer|)",
+    ) {
+        test_keys();
+        let value = format!("{}{}", prefix, token);
+        proptest::prop_assert!(test_auth().check_auth(&bearer_request(value.as_bytes())).is_err());
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected(i in 0usize..512, c in "[A-Za-z0-9_.-]") {
+        let claims = RoleGuard::new(
+            Role::Admin,
+            "alice".to_string(),
+            Utc::now().naive_utc() + chrono::Duration::minutes(10),
+        );
+        let mut token = test_keys().sign(&claims);
+        let i = i % token.len();
+        proptest::prop_assume!(token[i..i + 1] != c);
+        token.replace_range(i..i + 1, &c);
+        let value = format!("Bearer {}", token);
+        // base64 padding bits can absorb a change to the last character of the signature
+        if let Ok(user) = test_auth().check_auth(&bearer_request(value.as_bytes())) {
+            proptest::prop_assert_eq!(user.user, "alice");
+            proptest::prop_assert_eq!(i, token.len() - 1);
+        }
+    }
+}
+
+#[test]
+fn configured_roles_come_between_admin_and_guest() {
+    let auth = Auth {
+        admin: vec!["ssg".to_string()],
+        guest: vec!["users".to_string()],
+        roles: vec![crate::conf::RoleConf {
+            name: "triage".to_string(),
+            groups: vec!["hsg".to_string()],
+            capabilities: vec![Capability::IssueRead, Capability::IssueComment],
+        }],
+        ..test_auth()
+    };
+    let role = |groups: &[&str]| {
+        auth.role_for(&groups.iter().map(|g| g.to_string()).collect())
+            .map(|(r, _)| r)
+    };
+    assert_eq!(role(&["users", "hsg", "ssg"]).as_deref(), Some("Admin"));
+    assert_eq!(role(&["users", "hsg"]).as_deref(), Some("triage"));
+    assert_eq!(role(&["users"]).as_deref(), Some("Guest"));
+    assert_eq!(role(&["other"]), None);
+    assert_eq!(
+        auth.role_for(&HashSet::from(["hsg".to_string()]))
+            .unwrap()
+            .1,
+        vec![Capability::IssueRead, Capability::IssueComment]
+    );
+}
+
+#[test]
+fn client_certs_map_to_a_role() {
+    let key = rcgen::KeyPair::generate().unwrap();
+    let mut params = rcgen::CertificateParams::new(vec!["auto01.example.com".to_string()]).unwrap();
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, "healthcheck");
+    let cert = params.self_signed(&key).unwrap();
+    let auth = Auth {
+        client_certs: vec![
+            crate::conf::ClientCert {
+                name: "auto01.example.com".to_string(),
+                user: Some("automation".to_string()),
+                role: "guest".to_string(),
+            },
+            crate::conf::ClientCert {
+                name: "someone else".to_string(),
+                user: None,
+                role: "admin".to_string(),
+            },
+        ],
+        ..test_auth()
+    };
+    let request = |cert: &rcgen::Certificate| {
+        let mut req = axum::http::Request::new(());
+        req.extensions_mut()
+            .insert(PeerCert(Some(cert.der().clone())));
+        req
+    };
+
+    let user = auth.check_auth(&request(&cert)).unwrap();
+    assert_eq!(user.user, "automation");
+    assert_eq!(user.role(), "Guest");
+    assert_eq!(user.capabilities(), &[Capability::IssueRead]);
+    assert_eq!(user.method, AuthMethod::ClientCert);
+
+    // a token wins over the certificate
+    let mut req = request(&cert);
+    req.headers_mut()
+        .insert(header::AUTHORIZATION, "Bearer ctt_nope".parse().unwrap());
+    assert_eq!(auth.check_auth(&req).err(), Some(AuthError::UnknownApiKey));
+
+    let other = rcgen::generate_simple_self_signed(vec!["nobody.example.com".to_string()]).unwrap();
+    assert_eq!(
+        auth.check_auth(&request(&other.cert)).err(),
+        Some(AuthError::UnknownCert)
+    );
+    assert_eq!(
+        auth.check_auth(&axum::http::Request::new(())).err(),
+        Some(AuthError::Missing)
+    );
+}
+
+#[tokio::test]
+async fn refresh_rechecks_role_and_session_age() {
+    test_keys();
+    let path = std::env::temp_dir().join(format!("cttd-passwd-{}", rand::random::<u64>()));
+    std::fs::write(&path, "alice:x:ssg\n").unwrap();
+    let conf: Conf = serde_json::from_value(serde_json::json!({
+        "poll_interval": 60,
+        "slack": {"channel": "", "token": ""},
+        "db": "",
+        "certs_dir": "",
+        "server_addr": "",
+        "node_types": [],
+        "auth": {"admin": ["ssg"], "guest": ["users"], "password_file": path},
+    }))
+    .unwrap();
+    let db = Arc::new(crate::setup::test_db().await);
+    let refresh = |user: RoleGuard| {
+        let conf = conf.clone();
+        let db = db.clone();
+        async move {
+            refresh_handler(Extension(conf), Extension(db), Extension(user), None)
+                .await
+                .map(|t| keys().verify(&t.0.token).unwrap())
+        }
+    };
+    let exp = Utc::now().naive_utc() + chrono::Duration::minutes(10);
+    let alice = RoleGuard {
+        method: AuthMethod::Password,
+        ..RoleGuard::new(Role::Guest, "alice".to_string(), exp)
+    };
+
+    // the role comes from alice's groups now, not the token, and the login time carries over
+    let refreshed = refresh(alice.clone()).await.unwrap();
+    assert_eq!(refreshed.role(), "Admin");
+    assert_eq!(refreshed.auth_time, alice.auth_time);
+    std::fs::write(&path, "alice:x:users\n").unwrap();
+    assert_eq!(refresh(alice.clone()).await.unwrap().role(), "Guest");
+    std::fs::write(&path, "").unwrap();
+    assert_eq!(
+        refresh(alice.clone()).await.unwrap_err().0,
+        StatusCode::FORBIDDEN
+    );
+
+    // ldap groups can't be looked up without a password, so the token's role stands
+    let bob = RoleGuard {
+        method: AuthMethod::Ldap,
+        ..RoleGuard::new(Role::Admin, "bob".to_string(), exp)
+    };
+    assert_eq!(refresh(bob.clone()).await.unwrap().role(), "Admin");
+    let stale = RoleGuard {
+        auth_time: (Utc::now() - chrono::Duration::hours(25)).timestamp() as usize,
+        ..bob
+    };
+    assert_eq!(
+        refresh(stale).await.unwrap_err().0,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // only refreshes that hand out a token are recorded
+    let events = crate::entities::prelude::AuditEvent::find()
+        .all(db.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(
+        events
+            .iter()
+            .map(|e| (e.action, e.entity_id.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (AuditAction::Refresh, "alice"),
+            (AuditAction::Refresh, "alice"),
+            (AuditAction::Refresh, "bob")
+        ]
+    );
+    assert_eq!(events[0].before.as_ref().unwrap()["role"], "Guest");
+    assert_eq!(events[0].after.as_ref().unwrap()["role"], "Admin");
+}
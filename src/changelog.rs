@@ -1,4 +1,7 @@
 use crate::conf::Conf;
+use crate::entities::target::TargetStatus;
+#[cfg(feature = "slack")]
+use crate::hostlist;
 #[cfg(feature = "slack")]
 use slack_morphism::{
     prelude::SlackApiChatPostMessageRequest, prelude::SlackClientHyperConnector, SlackApiToken,
@@ -6,7 +9,7 @@ use slack_morphism::{
 };
 #[cfg(feature = "slack")]
 use std::collections::{BTreeMap, BTreeSet};
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
 use tokio::time::{self, Duration};
 #[allow(unused_imports)]
 use tracing::{info, instrument, warn, Level};
@@ -47,11 +50,63 @@ pub enum ChangeLogMsg {
         title: String,
         operator: String,
     },
+    Reopen {
+        issue: i32,
+        title: String,
+        comment: String,
+        operator: String,
+    },
     Update {
         issue: i32,
         title: String,
         operator: String,
     },
+    Alert {
+        alert: i32,
+        title: String,
+    },
+    AcknowledgeAlert {
+        alert: i32,
+        operator: String,
+    },
+    /// ctt's view of a node changed, only used by subscriptions
+    TargetStatus {
+        target: String,
+        status: TargetStatus,
+    },
+    /// everything one bulk mutation changed, sent once its transaction commits
+    Batch {
+        operator: String,
+        messages: Vec<ChangeLogMsg>,
+    },
+}
+
+impl ChangeLogMsg {
+    /// the messages in a Batch, or just this message
+    pub fn flatten(self) -> Vec<ChangeLogMsg> {
+        match self {
+            ChangeLogMsg::Batch { messages, .. } => {
+                messages.into_iter().flat_map(Self::flatten).collect()
+            }
+            m => vec![m],
+        }
+    }
+}
+
+/// forward every changelog message to the slack updater and to graphql subscribers
+#[instrument(skip_all)]
+pub async fn fan_out(
+    mut rx: mpsc::Receiver<ChangeLogMsg>,
+    slack: mpsc::Sender<ChangeLogMsg>,
+    subscribers: broadcast::Sender<ChangeLogMsg>,
+) {
+    while let Some(m) = rx.recv().await {
+        // having no subscribers isn't an error
+        let _ = subscribers.send(m.clone());
+        if slack.send(m).await.is_err() {
+            warn!("slack updater stopped");
+        }
+    }
 }
 
 #[cfg(feature = "slack")]
@@ -68,62 +123,88 @@ pub async fn slack_updater(mut rx: mpsc::Receiver<ChangeLogMsg>, conf: Conf) {
     let mut close_issues: BTreeMap<String, BTreeSet<i32>> = BTreeMap::new();
     let mut update_issues: BTreeMap<String, BTreeSet<i32>> = BTreeMap::new();
     let mut open_issues: BTreeSet<i32> = BTreeSet::new();
+    let mut reopen_issues: BTreeSet<i32> = BTreeSet::new();
     let mut operators: BTreeSet<String> = BTreeSet::new();
     let mut offline_nodes: BTreeSet<String> = BTreeSet::new();
     let mut resume_nodes: BTreeSet<String> = BTreeSet::new();
+    let mut alerts: BTreeMap<i32, String> = BTreeMap::new();
+    let mut acked_alerts: BTreeSet<i32> = BTreeSet::new();
 
     loop {
         tokio::select! {
             Some(u) = rx.recv() => {
-                match u {
-                    ChangeLogMsg::Offline { target: t, operator: o } => {
-                        offline_nodes.insert(t);
-                        operators.insert(o);
-                    }
-                    ChangeLogMsg::Resume { target: t, operator: o } => {
-                        resume_nodes.insert(t);
-                        operators.insert(o);
-                    }
-                    ChangeLogMsg::Close {
-                        issue: i,
-                        title: t,
-                        comment: _c,
-                        operator: o,
-                    } => {
-                        if o != "ctt" {
-                            if let Some(key) = close_issues.get_mut(&t) {
+                for u in u.flatten() {
+                    match u {
+                        ChangeLogMsg::Offline { target: t, operator: o } => {
+                            offline_nodes.insert(t);
+                            operators.insert(o);
+                        }
+                        ChangeLogMsg::Resume { target: t, operator: o } => {
+                            resume_nodes.insert(t);
+                            operators.insert(o);
+                        }
+                        ChangeLogMsg::Close {
+                            issue: i,
+                            title: t,
+                            comment: _c,
+                            operator: o,
+                        } => {
+                            if o != "ctt" {
+                                if let Some(key) = close_issues.get_mut(&t) {
+                                    key.insert(i);
+                                } else {
+                                    let mut tmp = BTreeSet::new();
+                                    tmp.insert(i);
+                                    close_issues.insert(t, tmp);
+                                }
+                                operators.insert(o);
+                            }
+                        }
+                        ChangeLogMsg::Open {
+                            issue: i,
+                            title: _t,
+                            operator: o,
+                        } => {
+                            if o != "ctt" {
+                                open_issues.insert(i);
+                                operators.insert(o);
+                            }
+                        }
+                        ChangeLogMsg::Reopen {
+                            issue: i,
+                            title: _t,
+                            comment: _c,
+                            operator: o,
+                        } => {
+                            if o != "ctt" {
+                                reopen_issues.insert(i);
+                                operators.insert(o);
+                            }
+                        }
+                        ChangeLogMsg::Update {
+                            issue: i,
+                            operator: o,
+                            title: t,
+                        } => {
+                            if let Some(key) = update_issues.get_mut(&t) {
                                 key.insert(i);
                             } else {
                                 let mut tmp = BTreeSet::new();
                                 tmp.insert(i);
-                                close_issues.insert(t, tmp);
+                                update_issues.insert(t, tmp);
                             }
                             operators.insert(o);
                         }
-                    }
-                    ChangeLogMsg::Open {
-                        issue: i,
-                        title: _t,
-                        operator: o,
-                    } => {
-                        if o != "ctt" {
-                            open_issues.insert(i);
-                            operators.insert(o);
+                        ChangeLogMsg::Alert { alert: a, title: t } => {
+                            // always worth telling someone about
+                            alerts.insert(a, t);
+                            operators.insert("ctt".to_string());
                         }
-                    }
-                    ChangeLogMsg::Update {
-                        issue: i,
-                        operator: o,
-                        title: t,
-                    } => {
-                        if let Some(key) = update_issues.get_mut(&t) {
-                            key.insert(i);
-                        } else {
-                            let mut tmp = BTreeSet::new();
-                            tmp.insert(i);
-                            update_issues.insert(t, tmp);
+                        ChangeLogMsg::AcknowledgeAlert { alert: a, operator: o } => {
+                            acked_alerts.insert(a);
+                            operators.insert(o);
                         }
-                        operators.insert(o);
+                        ChangeLogMsg::TargetStatus { .. } | ChangeLogMsg::Batch { .. } => (),
                     }
                 }
             }
@@ -138,9 +219,18 @@ pub async fn slack_updater(mut rx: mpsc::Receiver<ChangeLogMsg>, conf: Conf) {
                 let session = client.open_session(&token);
 
                 let mut msg  = format!("{:?}", operators);
+                if !alerts.is_empty() {
+                    msg.push_str(&format!("\nALERT: {:?}", alerts));
+                }
+                if !acked_alerts.is_empty() {
+                    msg.push_str(&format!("\nAcknowledged alerts: {:?}", acked_alerts));
+                }
                 if !open_issues.is_empty() {
                     msg.push_str(&format!("\nOpened: {:?}", open_issues));
                 }
+                if !reopen_issues.is_empty() {
+                    msg.push_str(&format!("\nReopened: {:?}", reopen_issues));
+                }
                 if !update_issues.is_empty() {
                     msg.push_str(&format!("\nUpdated: {:?}", update_issues));
                 }
@@ -148,10 +238,10 @@ pub async fn slack_updater(mut rx: mpsc::Receiver<ChangeLogMsg>, conf: Conf) {
                     msg.push_str(&format!("\nClosed: {:?}", close_issues));
                 }
                 if !offline_nodes.is_empty() {
-                    msg.push_str(&format!("\nOfflined: {:?}", offline_nodes));
+                    msg.push_str(&format!("\nOfflined: {}", hostlist::compress(&offline_nodes)));
                 }
                 if !resume_nodes.is_empty() {
-                    msg.push_str(&format!("\nResumed: {:?}", resume_nodes));
+                    msg.push_str(&format!("\nResumed: {}", hostlist::compress(&resume_nodes)));
                 }
 
                 let post_chat_req = SlackApiChatPostMessageRequest::new(
@@ -165,9 +255,12 @@ pub async fn slack_updater(mut rx: mpsc::Receiver<ChangeLogMsg>, conf: Conf) {
                 close_issues = BTreeMap::new();
                 update_issues = BTreeMap::new();
                 open_issues = BTreeSet::new();
+                reopen_issues = BTreeSet::new();
                 operators = BTreeSet::new();
                 offline_nodes = BTreeSet::new();
                 resume_nodes = BTreeSet::new();
+                alerts = BTreeMap::new();
+                acked_alerts = BTreeSet::new();
             }
         }
     }
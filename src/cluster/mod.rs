@@ -5,11 +5,34 @@ pub trait ClusterTrait {
     fn siblings(&self, target: &str) -> Vec<String>;
     fn cousins(&self, target: &str) -> Vec<String>;
     fn real_node(&self, target: &str) -> bool;
-    fn nodes_status(&mut self) -> Result<HashMap<String, (TargetStatus, String)>, String>;
-    fn release_node(&mut self, target: &str) -> Result<(), ()>;
-    fn offline_node(&mut self, target: &str, comment: &str) -> Result<(), ()>;
+    fn nodes_status(&self) -> Result<HashMap<String, (TargetStatus, String)>, String>;
+    /// nodes_status for dry runs, leaves the scheduler and any cached state alone
+    fn peek_status(&self) -> Result<HashMap<String, (TargetStatus, String)>, String>;
+    fn release_node(&self, target: &str) -> Result<(), ()>;
+    fn offline_node(&self, target: &str, comment: &str) -> Result<(), ()>;
 }
 
 mod regex_cluster;
 pub mod scheduler;
 pub use regex_cluster::RegexCluster;
+
+/// four node cluster (gu0001-gu0004, two nodes per card) backed by a mock scheduler
+#[cfg(test)]
+pub fn test_cluster() -> (RegexCluster, scheduler::MockScheduler) {
+    let sched = scheduler::MockScheduler::new();
+    for n in ["gu0001", "gu0002", "gu0003", "gu0004"] {
+        sched.set_node(n, TargetStatus::Online, "");
+    }
+    let cluster = RegexCluster::new(
+        vec![crate::conf::NodeType {
+            prefix: "gu".to_string(),
+            digits: Some(4),
+            first_num: None,
+            last_num: Some(4),
+            board: Some(2),
+            slot: Some(4),
+        }],
+        Box::new(sched.clone()),
+    );
+    (cluster, sched)
+}
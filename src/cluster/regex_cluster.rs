@@ -1,52 +1,42 @@
 #![allow(unused_variables)]
-use super::scheduler::{PbsScheduler, SchedulerTrait};
+use super::scheduler::SchedulerTrait;
 use crate::cluster::ClusterTrait;
 use crate::conf::NodeType;
 use crate::entities::target::TargetStatus;
-use regex::Regex;
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::Mutex;
 use tracing::instrument;
 use tracing::warn;
 
 #[derive(Debug)]
 pub struct RegexCluster {
     node_types: Vec<NodeType>,
-    //TODO have sched be of type SchedulerTrait instead
-    sched: PbsScheduler,
+    // one instance is shared by the api and the sync loop, the lock keeps them from talking to
+    // the scheduler concurrently
+    sched: Mutex<Box<dyn SchedulerTrait>>,
+    // result of the last successful nodes_status call
+    last_status: Mutex<HashMap<String, (TargetStatus, String)>>,
 }
 
 impl RegexCluster {
     #[instrument]
-    //TODO have sched be of type SchedulerTrait instead
-    pub fn new(node_types: Vec<NodeType>, sched: PbsScheduler) -> Self {
-        Self { sched, node_types }
+    pub fn new(node_types: Vec<NodeType>, sched: Box<dyn SchedulerTrait>) -> Self {
+        Self {
+            sched: Mutex::new(sched),
+            last_status: Mutex::new(HashMap::new()),
+            node_types,
+        }
+    }
+
+    /// scheduler state and comment for a node as of the last sync, without asking the scheduler
+    pub fn last_status(&self, target: &str) -> Option<(TargetStatus, String)> {
+        self.last_status.lock().unwrap().get(target).cloned()
     }
 
     #[instrument]
     fn get_node_type(&self, target: &str) -> Option<NodeType> {
-        for ntype in self.node_types.clone() {
-            //let re = Regex::new(&ntype.names).unwrap();
-            let re = if let Some(digits) = ntype.digits {
-                Regex::new(&format!(r"^{}\d{{{}}}$", ntype.prefix, digits)).unwrap()
-            } else {
-                Regex::new(&format!(r"^{}\d+$", ntype.prefix)).unwrap()
-            };
-            if re.is_match(target) {
-                let val = target.strip_prefix(&ntype.prefix).unwrap();
-                let num: u32 = FromStr::from_str(val).unwrap();
-                if ntype.first_num.unwrap_or(1) <= num {
-                    if let Some(last) = ntype.last_num {
-                        if num <= last {
-                            return Some(ntype);
-                        }
-                    } else {
-                        return Some(ntype);
-                    }
-                }
-            }
-        }
-        None
+        self.node_types.iter().find(|t| t.matches(target)).cloned()
     }
     #[instrument]
     fn get_related(&self, target: &str, nodetype: NodeType, size: u32) -> Vec<String> {
@@ -98,31 +88,40 @@ impl ClusterTrait for RegexCluster {
     }
 
     #[instrument]
-    fn nodes_status(&mut self) -> Result<HashMap<String, (TargetStatus, String)>, String> {
-        self.sched.nodes_status()
+    fn nodes_status(&self) -> Result<HashMap<String, (TargetStatus, String)>, String> {
+        let status = self.sched.lock().unwrap().nodes_status();
+        if let Ok(s) = &status {
+            *self.last_status.lock().unwrap() = s.clone();
+        }
+        status
+    }
+    #[instrument]
+    fn peek_status(&self) -> Result<HashMap<String, (TargetStatus, String)>, String> {
+        self.sched.lock().unwrap().peek_status()
     }
     #[instrument]
-    fn release_node(&mut self, target: &str) -> Result<(), ()> {
-        self.sched.release_node(target)
+    fn release_node(&self, target: &str) -> Result<(), ()> {
+        self.sched.lock().unwrap().release_node(target)
     }
     #[instrument]
-    fn offline_node(&mut self, target: &str, comment: &str) -> Result<(), ()> {
-        self.sched.offline_node(target, comment)
+    fn offline_node(&self, target: &str, comment: &str) -> Result<(), ()> {
+        self.sched.lock().unwrap().offline_node(target, comment)
     }
 }
 
-/*
-// TODO FIXME fix tests need to mock out PbsScheduler
 #[test]
 fn siblings() {
-    let gust = RegexCluster::new(vec![NodeType {
-        prefix: "gu".to_string(),
-        digits: Some(4),
-        first_num: None,
-        last_num: Some(18),
-        board: Some(2),
-        slot: Some(4),
-    }]);
+    let gust = RegexCluster::new(
+        vec![NodeType {
+            prefix: "gu".to_string(),
+            digits: Some(4),
+            first_num: None,
+            last_num: Some(18),
+            board: Some(2),
+            slot: Some(4),
+        }],
+        Box::new(super::scheduler::MockScheduler::new()),
+    );
     let expected = vec![
         vec!["gu0001", "gu0002"],
         vec!["gu0003", "gu0004"],
@@ -131,7 +130,7 @@ fn siblings() {
     for e in &expected {
         for s in e.iter() {
             let actual = gust.siblings(s);
-            println!("expected: {:?} actual: {:?}", &e, &actual);
+            println!("expected: {:?} actual: {:?}", e, actual);
             assert!(e.eq(&actual));
         }
     }
@@ -139,14 +138,17 @@ fn siblings() {
 
 #[test]
 fn cousins() {
-    let gust = RegexCluster::new(vec![NodeType {
-        prefix: "gu".to_string(),
-        digits: Some(4),
-        first_num: None,
-        last_num: Some(18),
-        board: Some(2),
-        slot: Some(4),
-    }]);
+    let gust = RegexCluster::new(
+        vec![NodeType {
+            prefix: "gu".to_string(),
+            digits: Some(4),
+            first_num: None,
+            last_num: Some(18),
+            board: Some(2),
+            slot: Some(4),
+        }],
+        Box::new(super::scheduler::MockScheduler::new()),
+    );
     let expected = vec![
         vec!["gu0001", "gu0002", "gu0003", "gu0004"],
         vec!["gu0005", "gu0006", "gu0007", "gu0008"],
@@ -154,7 +156,7 @@ fn cousins() {
     for e in &expected {
         for s in e.iter() {
             let actual = gust.cousins(s);
-            println!("expected: {:?} actual: {:?}", &e, &actual);
+            println!("expected: {:?} actual: {:?}", e, actual);
             assert!(e.eq(&actual));
         }
     }
@@ -162,14 +164,17 @@ fn cousins() {
 
 #[test]
 fn real_node() {
-    let gust = RegexCluster::new(vec![NodeType {
-        prefix: "gu".to_string(),
-        digits: Some(4),
-        first_num: None,
-        last_num: Some(18),
-        board: Some(2),
-        slot: Some(4),
-    }]);
+    let gust = RegexCluster::new(
+        vec![NodeType {
+            prefix: "gu".to_string(),
+            digits: Some(4),
+            first_num: None,
+            last_num: Some(18),
+            board: Some(2),
+            slot: Some(4),
+        }],
+        Box::new(super::scheduler::MockScheduler::new()),
+    );
     let expected_true = vec!["gu0001", "gu0002", "gu0015", "gu0016", "gu0017", "gu0018"];
     let expected_false = vec!["gu1", "gu0000", "NotANode", "gu-001", "gu0019", "gu00017"];
     for n in &expected_true {
@@ -183,4 +188,3 @@ fn real_node() {
         assert!(!actual);
     }
 }
-*/
@@ -0,0 +1,246 @@
+use crate::entities::target::TargetStatus;
+use config::{Config, ConfigError, File};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::instrument;
+use tracing::{info, warn};
+
+use super::SchedulerTrait;
+
+/// saved cluster state to seed a MockScheduler with, readable from json or yaml
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct Snapshot {
+    #[serde(default)]
+    pub nodes: HashMap<String, MockNode>,
+    #[serde(default)]
+    pub script: Vec<ScriptedChange>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MockNode {
+    pub status: TargetStatus,
+    #[serde(default)]
+    pub comment: String,
+}
+
+/// change a node's state at the start of the given tick, ticks count calls to nodes_status
+/// starting from 1
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ScriptedChange {
+    pub tick: u64,
+    pub node: String,
+    pub status: TargetStatus,
+    #[serde(default)]
+    pub comment: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchedulerCall {
+    Offline { target: String, comment: String },
+    Release { target: String },
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    tick: u64,
+    nodes: HashMap<String, MockNode>,
+    script: Vec<ScriptedChange>,
+    calls: Vec<SchedulerCall>,
+}
+
+/// in memory scheduler, nothing is sent to a real scheduler
+///
+/// clones share state, so a handle can be kept to inspect calls after the scheduler is handed to
+/// a RegexCluster
+#[derive(Clone, Debug, Default)]
+pub struct MockScheduler {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_snapshot(snapshot: Snapshot) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState {
+                nodes: snapshot.nodes,
+                script: snapshot.script,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        let snapshot = Config::builder()
+            .add_source(File::with_name(path))
+            .build()?
+            .try_deserialize()?;
+        Ok(Self::from_snapshot(snapshot))
+    }
+
+    #[cfg(test)]
+    pub fn set_node(&self, target: &str, status: TargetStatus, comment: &str) {
+        self.state.lock().unwrap().nodes.insert(
+            target.to_string(),
+            MockNode {
+                status,
+                comment: comment.to_string(),
+            },
+        );
+    }
+
+    #[cfg(test)]
+    pub fn calls(&self) -> Vec<SchedulerCall> {
+        self.state.lock().unwrap().calls.clone()
+    }
+}
+
+impl SchedulerTrait for MockScheduler {
+    #[instrument]
+    fn nodes_status(&mut self) -> Result<HashMap<String, (TargetStatus, String)>, String> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let changes: Vec<ScriptedChange> = state
+            .script
+            .iter()
+            .filter(|c| c.tick == tick)
+            .cloned()
+            .collect();
+        for c in changes {
+            info!("tick {}: {} -> {:?}", tick, c.node, c.status);
+            state.nodes.insert(
+                c.node,
+                MockNode {
+                    status: c.status,
+                    comment: c.comment,
+                },
+            );
+        }
+        Ok(state
+            .nodes
+            .iter()
+            .map(|(n, s)| (n.clone(), (s.status, s.comment.clone())))
+            .collect())
+    }
+
+    /// the state the next nodes_status call will see, without moving the script on
+    #[instrument]
+    fn peek_status(&mut self) -> Result<HashMap<String, (TargetStatus, String)>, String> {
+        let state = self.state.lock().unwrap();
+        let mut nodes = state.nodes.clone();
+        for c in state.script.iter().filter(|c| c.tick == state.tick + 1) {
+            nodes.insert(
+                c.node.clone(),
+                MockNode {
+                    status: c.status,
+                    comment: c.comment.clone(),
+                },
+            );
+        }
+        Ok(nodes
+            .into_iter()
+            .map(|(n, s)| (n, (s.status, s.comment)))
+            .collect())
+    }
+
+    #[instrument]
+    fn release_node(&mut self, target: &str) -> Result<(), ()> {
+        info!("resuming node {}", target);
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(SchedulerCall::Release {
+            target: target.to_string(),
+        });
+        if let Some(n) = state.nodes.get_mut(target) {
+            n.status = TargetStatus::Online;
+            n.comment = "".to_string();
+            Ok(())
+        } else {
+            warn!("{} not found in mock scheduler", target);
+            Err(())
+        }
+    }
+
+    #[instrument]
+    fn offline_node(&mut self, target: &str, comment: &str) -> Result<(), ()> {
+        info!("offlining: {}, {}", target, comment);
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(SchedulerCall::Offline {
+            target: target.to_string(),
+            comment: comment.to_string(),
+        });
+        if let Some(n) = state.nodes.get_mut(target) {
+            // no jobs run on mock nodes, so they never sit in Draining
+            n.status = TargetStatus::Offline;
+            n.comment = comment.to_string();
+            Ok(())
+        } else {
+            warn!("{} not found in mock scheduler", target);
+            Err(())
+        }
+    }
+}
+
+#[test]
+fn scripted_changes() {
+    let path = std::env::temp_dir().join(format!("cttd-snapshot-{}.yaml", rand::random::<u64>()));
+    std::fs::write(
+        &path,
+        "nodes:
+  gu0001: { status: Online }
+  gu0002: { status: Down, comment: node down }
+script:
+  - { tick: 2, node: gu0001, status: Down, comment: bad dimm }
+",
+    )
+    .unwrap();
+    let mut sched = MockScheduler::load(path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // peeking never moves the script on
+    for _ in 0..2 {
+        let peek = sched.peek_status().unwrap();
+        assert_eq!(peek["gu0001"], (TargetStatus::Online, "".to_string()));
+    }
+    let tick1 = sched.nodes_status().unwrap();
+    assert_eq!(tick1["gu0001"], (TargetStatus::Online, "".to_string()));
+    assert_eq!(
+        tick1["gu0002"],
+        (TargetStatus::Down, "node down".to_string())
+    );
+    let peek = sched.peek_status().unwrap();
+    assert_eq!(peek["gu0001"], (TargetStatus::Down, "bad dimm".to_string()));
+    let tick2 = sched.nodes_status().unwrap();
+    assert_eq!(
+        tick2["gu0001"],
+        (TargetStatus::Down, "bad dimm".to_string())
+    );
+
+    assert!(sched.offline_node("gu0001", "bad dimm").is_ok());
+    assert!(sched.release_node("gu0002").is_ok());
+    assert!(sched.release_node("gu9999").is_err());
+    let tick3 = sched.nodes_status().unwrap();
+    assert_eq!(
+        tick3["gu0001"],
+        (TargetStatus::Offline, "bad dimm".to_string())
+    );
+    assert_eq!(tick3["gu0002"], (TargetStatus::Online, "".to_string()));
+    assert_eq!(
+        sched.calls(),
+        vec![
+            SchedulerCall::Offline {
+                target: "gu0001".to_string(),
+                comment: "bad dimm".to_string()
+            },
+            SchedulerCall::Release {
+                target: "gu0002".to_string()
+            },
+            SchedulerCall::Release {
+                target: "gu9999".to_string()
+            },
+        ]
+    );
+}
@@ -1,11 +1,48 @@
+use crate::conf::{Conf, Scheduler};
 use crate::entities::target::TargetStatus;
 use std::collections::HashMap;
+use std::fmt::Debug;
 
-pub trait SchedulerTrait {
+pub trait SchedulerTrait: Debug + Send + Sync {
     fn nodes_status(&mut self) -> Result<HashMap<String, (TargetStatus, String)>, String>;
+    /// what nodes_status would return, without changing anything, for dry runs. schedulers
+    /// whose queries have no side effects can leave this as is
+    fn peek_status(&mut self) -> Result<HashMap<String, (TargetStatus, String)>, String> {
+        self.nodes_status()
+    }
     fn release_node(&mut self, target: &str) -> Result<(), ()>;
     fn offline_node(&mut self, target: &str, comment: &str) -> Result<(), ()>;
 }
 
+mod mock_scheduler;
+#[cfg(feature = "pbs")]
 mod pbs_scheduler;
+#[cfg(feature = "slurm")]
+mod slurm_scheduler;
+pub use mock_scheduler::MockScheduler;
+#[cfg(test)]
+pub use mock_scheduler::SchedulerCall;
+#[cfg(feature = "pbs")]
 pub use pbs_scheduler::PbsScheduler;
+#[cfg(feature = "slurm")]
+pub use slurm_scheduler::SlurmScheduler;
+
+/// build the scheduler backend selected in the config
+///
+/// conf::get_config refuses schedulers that weren't compiled in, so the fallback arm is never hit
+pub fn new(conf: &Conf) -> Box<dyn SchedulerTrait> {
+    match conf.scheduler {
+        #[cfg(feature = "pbs")]
+        Scheduler::Pbs => Box::new(PbsScheduler::new()),
+        #[cfg(feature = "slurm")]
+        Scheduler::Slurm => Box::new(SlurmScheduler::new()),
+        Scheduler::Mock => match &conf.mock_snapshot {
+            Some(path) => {
+                Box::new(MockScheduler::load(path).expect("Error reading mock scheduler snapshot"))
+            }
+            None => Box::new(MockScheduler::new()),
+        },
+        #[allow(unreachable_patterns)]
+        s => unreachable!("cttd was built without support for the {:?} scheduler", s),
+    }
+}
@@ -0,0 +1,206 @@
+use crate::entities::target::TargetStatus;
+use core::fmt;
+use std::collections::HashMap;
+use std::process::Command;
+use tracing::instrument;
+use tracing::{info, warn};
+
+use super::SchedulerTrait;
+
+// node name, long state, and reason; '|' since reasons can contain spaces
+const SINFO_FORMAT: &str = "--format=%N|%T|%E";
+
+pub struct SlurmScheduler {}
+
+impl SlurmScheduler {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl fmt::Debug for SlurmScheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlurmScheduler").finish()
+    }
+}
+
+impl Default for SlurmScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// map a slurm node state onto a TargetStatus
+///
+/// handles both the `sinfo %T`/`%t` forms ("drained", "mix", "down*") and the
+/// `scontrol show node` form ("MIXED+DRAIN")
+fn parse_state(state: &str) -> TargetStatus {
+    let state = state.to_lowercase();
+    let mut flags = state
+        .split('+')
+        // strip the power saving/not responding/reboot suffixes, they don't change how ctt
+        // treats the node
+        .map(|f| f.trim_end_matches(['*', '~', '#', '!', '%', '$', '@', '^', '-']));
+    let base = flags.next().unwrap_or_default();
+    let drain = flags.any(|f| f == "drain");
+    let jobs = matches!(
+        base,
+        "allocated" | "alloc" | "mixed" | "mix" | "completing" | "comp"
+    );
+    //order matters, before "down" to capture down+drain nodes
+    if drain {
+        return if jobs {
+            TargetStatus::Draining
+        } else {
+            TargetStatus::Offline
+        };
+    }
+    match base {
+        "drained" | "drain" | "fail" => TargetStatus::Offline,
+        "draining" | "drng" | "failing" | "failg" => TargetStatus::Draining,
+        "down" => TargetStatus::Down,
+        "idle" | "allocated" | "alloc" | "mixed" | "mix" | "completing" | "comp" | "reserved"
+        | "resv" | "planned" | "plnd" => TargetStatus::Online,
+        x => {
+            warn!("unrecognized node state, '{}'", x);
+            TargetStatus::Down
+        }
+    }
+}
+
+/// parse the output of `sinfo --Node --noheader --format=%N|%T|%E`
+///
+/// sinfo prints a node once per partition it is in, duplicates are collapsed
+fn parse_sinfo(output: &str) -> HashMap<String, (TargetStatus, String)> {
+    let mut resp = HashMap::new();
+    for line in output.lines().filter(|l| !l.trim().is_empty()) {
+        let mut fields = line.splitn(3, '|');
+        let (Some(name), Some(state)) = (fields.next(), fields.next()) else {
+            warn!("unable to parse sinfo line, '{}'", line);
+            continue;
+        };
+        let comment = match fields.next().map(|c| c.trim()) {
+            Some("none") | None => "",
+            Some(c) => c,
+        };
+        resp.insert(
+            name.trim().to_string(),
+            (parse_state(state.trim()), comment.to_string()),
+        );
+    }
+    resp
+}
+
+#[instrument]
+fn scontrol_update(args: &[String]) -> Result<(), ()> {
+    match Command::new("scontrol").arg("update").args(args).output() {
+        Ok(o) if o.status.success() => Ok(()),
+        Ok(o) => {
+            warn!(
+                "scontrol update failed: {}",
+                String::from_utf8_lossy(&o.stderr)
+            );
+            Err(())
+        }
+        Err(e) => {
+            warn!("unable to run scontrol: {}", e);
+            Err(())
+        }
+    }
+}
+
+impl SchedulerTrait for SlurmScheduler {
+    #[instrument]
+    fn nodes_status(&mut self) -> Result<HashMap<String, (TargetStatus, String)>, String> {
+        //TODO add a timeout
+        let out = Command::new("sinfo")
+            .args(["--Node", "--noheader", SINFO_FORMAT])
+            .output();
+        let out = match out {
+            Ok(o) => o,
+            Err(e) => {
+                warn!("unable to run sinfo: {}", e);
+                return Err(e.to_string());
+            }
+        };
+        if !out.status.success() {
+            let e = String::from_utf8_lossy(&out.stderr).to_string();
+            warn!("error running sinfo: {}", e);
+            return Err(e);
+        }
+        Ok(parse_sinfo(&String::from_utf8_lossy(&out.stdout)))
+    }
+
+    #[instrument]
+    fn release_node(&mut self, target: &str) -> Result<(), ()> {
+        info!("resuming node {}", target);
+        scontrol_update(&[format!("NodeName={}", target), "State=RESUME".to_string()])
+    }
+
+    #[instrument]
+    fn offline_node(&mut self, target: &str, comment: &str) -> Result<(), ()> {
+        info!("offlining: {}, {}", target, comment);
+        // slurm refuses to drain a node without a reason
+        let reason = if comment.is_empty() { "ctt" } else { comment };
+        scontrol_update(&[
+            format!("NodeName={}", target),
+            "State=DRAIN".to_string(),
+            format!("Reason={}", reason),
+        ])
+    }
+}
+
+#[test]
+fn sinfo_states() {
+    let sinfo = "gu0001|idle|none
+gu0002|allocated|none
+gu0003|mixed|none
+gu0004|draining|bad dimm
+gu0005|drained|bad dimm
+gu0006|down*|Not responding
+gu0006|down*|Not responding
+gu0007|idle+drain|hca flapping
+gu0008|mixed+drain|hca|flapping
+gu0009|completing|none
+gu0010|idle~|none
+
+";
+    let expected = vec![
+        ("gu0001", TargetStatus::Online, ""),
+        ("gu0002", TargetStatus::Online, ""),
+        ("gu0003", TargetStatus::Online, ""),
+        ("gu0004", TargetStatus::Draining, "bad dimm"),
+        ("gu0005", TargetStatus::Offline, "bad dimm"),
+        ("gu0006", TargetStatus::Down, "Not responding"),
+        ("gu0007", TargetStatus::Offline, "hca flapping"),
+        ("gu0008", TargetStatus::Draining, "hca|flapping"),
+        ("gu0009", TargetStatus::Online, ""),
+        ("gu0010", TargetStatus::Online, ""),
+    ];
+    let actual = parse_sinfo(sinfo);
+    assert_eq!(actual.len(), expected.len());
+    for (name, state, comment) in expected {
+        println!("{}: expected: {:?} actual: {:?}", name, state, actual[name]);
+        assert_eq!(actual[name], (state, comment.to_string()));
+    }
+}
+
+#[test]
+fn scontrol_states() {
+    let expected = vec![
+        ("IDLE", TargetStatus::Online),
+        ("ALLOCATED", TargetStatus::Online),
+        ("MIXED", TargetStatus::Online),
+        ("IDLE+DRAIN", TargetStatus::Offline),
+        ("MIXED+DRAIN", TargetStatus::Draining),
+        ("ALLOCATED+DRAIN", TargetStatus::Draining),
+        ("DOWN+DRAIN", TargetStatus::Offline),
+        ("DOWN*+NOT_RESPONDING", TargetStatus::Down),
+        ("IDLE+CLOUD+POWERED_DOWN", TargetStatus::Online),
+        ("FUTURE", TargetStatus::Down),
+    ];
+    for (state, status) in expected {
+        println!("{}: expected: {:?}", state, status);
+        assert_eq!(parse_state(state), status);
+    }
+}
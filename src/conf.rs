@@ -1,5 +1,11 @@
+use crate::auth::{Capability, NodeScope};
+use crate::entities::issue::{Severity, ToOffline};
+use crate::hostlist;
+use async_graphql::SimpleObject;
 use config::{Config, ConfigError, File};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 pub fn get_config(path: Option<String>) -> Result<Conf, ConfigError> {
     let mut conf = Config::builder();
@@ -7,24 +13,305 @@ pub fn get_config(path: Option<String>) -> Result<Conf, ConfigError> {
         conf = conf.add_source(File::with_name(&p));
     }
     let conf = conf.build()?;
-    conf.try_deserialize()
+    let conf: Conf = conf.try_deserialize()?;
+    for rule in &conf.acl {
+        if let Some(t) = rule
+            .node_types
+            .iter()
+            .find(|t| !conf.node_types.iter().any(|n| &n.prefix == *t))
+        {
+            return Err(ConfigError::Message(format!(
+                "acl rule for {:?} names unknown node type {}",
+                rule.groups, t
+            )));
+        }
+        if let Some(h) = &rule.hosts {
+            hostlist::expand(h).map_err(ConfigError::Message)?;
+        }
+    }
+    if let Some(c) = conf
+        .auth
+        .client_certs
+        .iter()
+        .find(|c| conf.auth.named_role(&c.role).is_none())
+    {
+        return Err(ConfigError::Message(format!(
+            "client cert {} has unknown role {}",
+            c.name, c.role
+        )));
+    }
+    if !conf.scheduler.enabled() {
+        return Err(ConfigError::Message(format!(
+            "cttd was built without support for the {:?} scheduler",
+            conf.scheduler
+        )));
+    }
+    Ok(conf)
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Conf {
     pub poll_interval: u64,
+    #[serde(default)]
+    pub scheduler: Scheduler,
+    /// cluster snapshot to replay when using the mock scheduler
+    pub mock_snapshot: Option<String>,
+    /// only log what each sync pass would do, never change the scheduler or issues
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub sync_limit: SyncLimit,
+    /// seconds after an issue is closed during which sync reopens it instead of opening a new
+    /// issue with the same title on the same node, never reopen if unset
+    pub reopen_window: Option<u64>,
+    /// severity of the issues sync opens for nodes the scheduler reports down
+    #[serde(default)]
+    pub auto_open_severity: Severity,
     pub slack: Slack,
     pub db: String,
     pub certs_dir: String,
     pub server_addr: String,
+    /// serve `/metrics` over plain http on this address without authentication, e.g.
+    /// `127.0.0.1:9100`, instead of behind auth on `server_addr`
+    pub metrics_addr: Option<String>,
     pub node_types: Vec<NodeType>,
     pub auth: Auth,
+    /// canned issues offered by the templates query and opened with openFromTemplate
+    #[serde(default)]
+    pub templates: Vec<IssueTemplate>,
+    /// limits members of some groups to some nodes, eg partners to the hardware they own
+    #[serde(default)]
+    pub acl: Vec<AclRule>,
+}
+
+impl Conf {
+    /// nodes members of `groups` may act on, any node if no rule names one of their groups
+    pub fn node_scope(&self, groups: &HashSet<String>) -> Option<Vec<NodeScope>> {
+        let rules: Vec<&AclRule> = self
+            .acl
+            .iter()
+            .filter(|r| r.groups.iter().any(|g| groups.contains(g)))
+            .collect();
+        if rules.is_empty() {
+            return None;
+        }
+        Some(
+            rules
+                .into_iter()
+                .flat_map(|r| {
+                    // get_config made sure every prefix names a node type
+                    r.node_types
+                        .iter()
+                        .filter_map(|p| self.node_types.iter().find(|t| &t.prefix == p))
+                        .map(|t| NodeScope::NodeType(t.clone()))
+                        .chain(r.hosts.iter().map(|h| NodeScope::Hosts(h.clone())))
+                })
+                .collect(),
+        )
+    }
+}
+
+/// members of any of `groups` may only act on nodes of `node_types` or in `hosts`
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct AclRule {
+    pub groups: Vec<String>,
+    /// `prefix` of entries in `node_types`
+    #[serde(default)]
+    pub node_types: Vec<String>,
+    /// hostlist, eg `gu[0001-0010]`
+    pub hosts: Option<String>,
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheduler {
+    #[default]
+    Pbs,
+    Slurm,
+    /// in memory scheduler, for testing and replaying saved cluster snapshots
+    Mock,
+}
+
+impl Scheduler {
+    /// whether support for this scheduler was compiled in
+    pub fn enabled(&self) -> bool {
+        match self {
+            Scheduler::Pbs => cfg!(feature = "pbs"),
+            Scheduler::Slurm => cfg!(feature = "slurm"),
+            Scheduler::Mock => true,
+        }
+    }
+}
+
+/// circuit breaker for a single sync pass, counts nodes that would be offlined or have an issue
+/// opened, the pass is paused if either limit is exceeded
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct SyncLimit {
+    pub max_nodes: Option<usize>,
+    /// percentage of the nodes known to ctt
+    pub max_percent: Option<f64>,
+}
+
+impl SyncLimit {
+    pub fn exceeded(&self, count: usize, total: usize) -> bool {
+        if let Some(max) = self.max_nodes
+            && count > max
+        {
+            return true;
+        }
+        if let Some(max) = self.max_percent
+            && total > 0
+            && (count as f64 / total as f64) * 100.0 > max
+        {
+            return true;
+        }
+        false
+    }
+}
+
+/// an issue that keeps coming up, e.g. "GPU fell off bus"
+///
+/// `{name}` placeholders in the title and description are filled from the vars given to
+/// openFromTemplate, `{target}` is always the node the issue is opened on
+#[derive(Clone, Serialize, Deserialize, Debug, SimpleObject)]
+pub struct IssueTemplate {
+    pub name: String,
+    pub title: String,
+    pub description: String,
+    pub to_offline: Option<ToOffline>,
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    pub assigned_to: Option<String>,
+}
+
+impl IssueTemplate {
+    /// title and description with every placeholder filled in
+    pub fn render(&self, vars: &[(String, String)]) -> Result<(String, String), String> {
+        Ok((
+            self.fill(&self.title, vars)?,
+            self.fill(&self.description, vars)?,
+        ))
+    }
+
+    fn fill(&self, text: &str, vars: &[(String, String)]) -> Result<String, String> {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            // only `{word}` is a placeholder, other braces are left alone
+            let name = rest[1..]
+                .find('}')
+                .map(|end| &rest[1..end + 1])
+                .filter(|n| {
+                    !n.is_empty() && n.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                });
+            match name {
+                Some(name) => {
+                    let Some((_, value)) = vars.iter().find(|(k, _)| k == name) else {
+                        return Err(format!("template {} needs {{{}}}", self.name, name));
+                    };
+                    out.push_str(value);
+                    rest = &rest[name.len() + 2..];
+                }
+                None => {
+                    out.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Auth {
     pub admin: Vec<String>,
     pub guest: Vec<String>,
+    /// minutes a token from /login or /refresh is valid for
+    #[serde(default = "default_token_minutes")]
+    pub token_minutes: u64,
+    /// hours after logging in that /refresh stops handing out new tokens
+    #[serde(default = "default_session_hours")]
+    pub session_hours: u64,
+    /// days between jwt signing key rotations, the key is never rotated if unset
+    pub key_rotation_days: Option<u64>,
+    /// roles between admin and guest, checked in order after `admin`
+    #[serde(default)]
+    pub roles: Vec<RoleConf>,
+    /// pem file of CAs whose client certificates are accepted in place of a token,
+    /// relative to `certs_dir`
+    pub client_ca: Option<String>,
+    #[serde(default)]
+    pub client_certs: Vec<ClientCert>,
+    /// `user:argon2 hash[:group,group]` lines for password logins, checked before `ldap`
+    pub password_file: Option<String>,
+    pub ldap: Option<Ldap>,
+}
+
+/// password logins by simple bind, groups are looked up as the user after binding
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Ldap {
+    /// eg `ldaps://ldap.example.org`
+    pub url: String,
+    /// dn to bind as, `{user}` is replaced by the login name,
+    /// eg `uid={user},ou=people,dc=example,dc=org`
+    pub user_dn: String,
+    pub group_base: String,
+    /// `{user}` is replaced by the login name
+    #[serde(default = "default_group_filter")]
+    pub group_filter: String,
+    /// attribute holding the group name
+    #[serde(default = "default_group_attr")]
+    pub group_attr: String,
+}
+
+fn default_group_filter() -> String {
+    "(memberUid={user})".to_string()
+}
+
+fn default_group_attr() -> String {
+    "cn".to_string()
+}
+
+/// who a client certificate logs in as
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ClientCert {
+    /// subject common name or a dns, email or uri subject alt name
+    pub name: String,
+    /// defaults to `name`
+    pub user: Option<String>,
+    /// `admin`, `guest` or the name of one of `roles`
+    pub role: String,
+}
+
+/// a named set of capabilities granted to members of any of `groups`
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RoleConf {
+    pub name: String,
+    pub groups: Vec<String>,
+    pub capabilities: Vec<Capability>,
+}
+
+fn default_token_minutes() -> u64 {
+    60
+}
+
+fn default_session_hours() -> u64 {
+    24
+}
+
+impl Auth {
+    pub fn token_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.token_minutes as i64)
+    }
+
+    pub fn max_session_age(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.session_hours as i64)
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -33,7 +320,7 @@ pub struct Slack {
     pub token: String,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct NodeType {
     pub prefix: String,
     pub digits: Option<usize>,
@@ -42,3 +329,134 @@ pub struct NodeType {
     pub last_num: Option<u32>,
     pub slot: Option<u32>,
 }
+
+impl NodeType {
+    /// `name` is `prefix` followed by a number in range, zero padded to `digits` if set
+    pub fn matches(&self, name: &str) -> bool {
+        let re = if let Some(digits) = self.digits {
+            Regex::new(&format!(r"^{}\d{{{}}}$", self.prefix, digits)).unwrap()
+        } else {
+            Regex::new(&format!(r"^{}\d+$", self.prefix)).unwrap()
+        };
+        if !re.is_match(name) {
+            return false;
+        }
+        let Ok(num) = name[self.prefix.len()..].parse::<u32>() else {
+            return false;
+        };
+        self.first_num.unwrap_or(1) <= num && self.last_num.is_none_or(|last| num <= last)
+    }
+}
+
+#[test]
+fn sync_limit() {
+    let unlimited = SyncLimit::default();
+    assert!(!unlimited.exceeded(1000, 1000));
+    let limit = SyncLimit {
+        max_nodes: Some(10),
+        max_percent: Some(5.0),
+    };
+    assert!(!limit.exceeded(10, 1000));
+    assert!(limit.exceeded(11, 1000));
+    assert!(!limit.exceeded(5, 100));
+    assert!(limit.exceeded(6, 100));
+    assert!(!limit.exceeded(0, 0));
+}
+
+#[test]
+fn template_render() {
+    let t = IssueTemplate {
+        name: "gpu".to_string(),
+        title: "GPU {gpu} fell off bus".to_string(),
+        description: "nvidia-smi on {target} lost gpu {gpu} {not a var} {}".to_string(),
+        to_offline: Some(ToOffline::Node),
+        severity: Severity::Major,
+        labels: vec!["gpu".to_string()],
+        assigned_to: None,
+    };
+    let vars = vec![
+        ("target".to_string(), "gu0001".to_string()),
+        ("gpu".to_string(), "3".to_string()),
+    ];
+    assert_eq!(
+        t.render(&vars).unwrap(),
+        (
+            "GPU 3 fell off bus".to_string(),
+            "nvidia-smi on gu0001 lost gpu 3 {not a var} {}".to_string()
+        )
+    );
+    assert_eq!(
+        t.render(&vars[..1]).unwrap_err(),
+        "template gpu needs {gpu}"
+    );
+}
+
+#[test]
+fn acl_scope() {
+    let conf: Conf = Config::builder()
+        .add_source(File::from_str(
+            r#"
+poll_interval: 30
+scheduler: mock
+slack: { channel: "c", token: "t" }
+db: ":memory:"
+certs_dir: "/tmp"
+server_addr: "127.0.0.1:8080"
+node_types:
+  - { prefix: "gug", digits: 4, slot: 2 }
+  - { prefix: "guc", digits: 4, board: 2, slot: 4 }
+auth: { admin: ["ssg"], guest: ["ncar"] }
+acl:
+  - { groups: ["partner"], node_types: ["gug"] }
+  - { groups: ["partner", "loaner"], hosts: "guc000[1-2]" }
+"#,
+            config::FileFormat::Yaml,
+        ))
+        .build()
+        .unwrap()
+        .try_deserialize()
+        .unwrap();
+    let groups = |g: &[&str]| g.iter().map(|g| g.to_string()).collect::<HashSet<_>>();
+    assert_eq!(conf.node_scope(&groups(&["ssg", "ncar"])), None);
+    assert_eq!(
+        conf.node_scope(&groups(&["ncar", "partner"])),
+        Some(vec![
+            NodeScope::NodeType(conf.node_types[0].clone()),
+            NodeScope::Hosts("guc000[1-2]".to_string()),
+        ])
+    );
+    // a node type is matched by its pattern, not just its prefix
+    let gug = NodeScope::NodeType(conf.node_types[0].clone());
+    assert!(gug.contains("gug0001"));
+    assert!(!gug.contains("gug00001"));
+    assert!(!gug.contains("gug0001-ib0"));
+    assert_eq!(
+        conf.node_scope(&groups(&["loaner"])),
+        Some(vec![NodeScope::Hosts("guc000[1-2]".to_string())])
+    );
+}
+
+#[test]
+fn acl_node_types_must_be_configured() {
+    let path = std::env::temp_dir().join(format!("cttd-conf-{}.yaml", rand::random::<u64>()));
+    std::fs::write(
+        &path,
+        r#"
+poll_interval: 30
+scheduler: mock
+slack: { channel: "c", token: "t" }
+db: ":memory:"
+certs_dir: "/tmp"
+server_addr: "127.0.0.1:8080"
+node_types:
+  - { prefix: "gug", digits: 4 }
+auth: { admin: ["ssg"], guest: ["ncar"] }
+acl:
+  - { groups: ["partner"], node_types: ["gu"] }
+"#,
+    )
+    .unwrap();
+    let err = get_config(Some(path.to_str().unwrap().to_string())).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(err.to_string().contains("unknown node type gu"), "{}", err);
+}
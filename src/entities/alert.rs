@@ -0,0 +1,41 @@
+use async_graphql::*;
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// cluster wide problem found by the sync loop, sync is paused until it is acknowledged
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, SimpleObject)]
+#[sea_orm(table_name = "alert")]
+#[graphql(concrete(name = "Alert", params()))]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i32,
+    pub title: String,
+    /// for people reading the alert, the nodes it covers are kept in alert_node
+    pub description: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_at: Option<chrono::NaiveDateTime>,
+    pub comment: Option<String>,
+    /// when the sync pass let through by acknowledging the alert ran
+    pub resumed_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::alert_node::Entity")]
+    AlertNode,
+}
+
+impl Related<super::alert_node::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AlertNode.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Entity {
+    pub fn unacknowledged() -> Select<Entity> {
+        Self::find().filter(Column::AcknowledgedBy.is_null())
+    }
+}
@@ -0,0 +1,32 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// a node an alert was raised for, acknowledging the alert lets it through the sync limit
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "alert_node")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub alert_id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub node: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::alert::Entity",
+        from = "Column::AlertId",
+        to = "super::alert::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Alert,
+}
+
+impl Related<super::alert::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Alert.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,48 @@
+use async_graphql::*;
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// credential for scripts and cron jobs, sent as `Authorization: Bearer ctt_...`
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, SimpleObject)]
+#[sea_orm(table_name = "api_key")]
+#[graphql(name = "ApiKey", complex)]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i32,
+    /// operator recorded for everything done with the key
+    #[sea_orm(unique)]
+    pub name: String,
+    /// sha256 of the key, the key itself is only shown when it is created
+    #[graphql(skip)]
+    #[sea_orm(unique)]
+    pub key_hash: String,
+    /// `Admin`, `Guest` or one of `auth.roles`, looked up each time the key is used
+    pub role: String,
+    /// comma separated node name prefixes the key may act on, any node if unset
+    #[graphql(skip)]
+    pub node_prefixes: Option<String>,
+    pub created_by: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub expires_at: Option<chrono::NaiveDateTime>,
+}
+
+#[ComplexObject]
+impl Model {
+    /// node name prefixes the key may act on, any node if null
+    pub async fn nodes(&self) -> Option<Vec<String>> {
+        self.prefixes()
+    }
+}
+
+impl Model {
+    pub fn prefixes(&self) -> Option<Vec<String>> {
+        self.node_prefixes
+            .as_ref()
+            .map(|p| p.split(',').map(str::to_string).collect())
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,95 @@
+use async_graphql::*;
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// one state changing action, rows are never updated or deleted
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize, SimpleObject)]
+#[sea_orm(table_name = "audit_event")]
+#[graphql(name = "AuditEvent")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i32,
+    pub at: chrono::NaiveDateTime,
+    /// user, api key, or client certificate the action was done as, `ctt` for the sync loop
+    pub actor: String,
+    /// how the actor authenticated, `Sync` for the sync loop
+    pub method: String,
+    pub source_ip: Option<String>,
+    pub action: AuditAction,
+    /// kind of thing acted on, e.g. `issue` or `node`
+    pub entity: String,
+    pub entity_id: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    /// generated by the server and shared by everything done by one request, or one sync pass
+    pub correlation_id: String,
+    /// `X-Request-Id` the client sent, if any
+    pub client_request_id: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(
+    Copy,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    EnumIter,
+    DeriveActiveEnum,
+    async_graphql::Enum,
+    Serialize,
+    Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "audit_action")]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    #[sea_orm(string_value = "open")]
+    Open,
+    #[sea_orm(string_value = "update")]
+    Update,
+    #[sea_orm(string_value = "close")]
+    Close,
+    #[sea_orm(string_value = "reopen")]
+    Reopen,
+    #[sea_orm(string_value = "comment")]
+    Comment,
+    #[sea_orm(string_value = "edit_comment")]
+    EditComment,
+    #[sea_orm(string_value = "delete_comment")]
+    DeleteComment,
+    #[sea_orm(string_value = "acknowledge_alert")]
+    AcknowledgeAlert,
+    #[sea_orm(string_value = "create_api_key")]
+    CreateApiKey,
+    #[sea_orm(string_value = "delete_api_key")]
+    DeleteApiKey,
+    #[sea_orm(string_value = "login")]
+    Login,
+    #[sea_orm(string_value = "login_failed")]
+    LoginFailed,
+    /// a token was exchanged for a new one at /refresh
+    #[sea_orm(string_value = "refresh")]
+    Refresh,
+    /// the sync loop offlined a node
+    #[sea_orm(string_value = "offline")]
+    Offline,
+    /// the sync loop resumed a node
+    #[sea_orm(string_value = "resume")]
+    Resume,
+    #[sea_orm(string_value = "auto_open")]
+    AutoOpen,
+    #[sea_orm(string_value = "auto_reopen")]
+    AutoReopen,
+    #[sea_orm(string_value = "auto_close")]
+    AutoClose,
+    /// the sync loop moved an issue from Opening to Open or Closing to Closed
+    #[sea_orm(string_value = "promote")]
+    Promote,
+    /// the sync loop tripped its limit and paused
+    #[sea_orm(string_value = "raise_alert")]
+    RaiseAlert,
+}
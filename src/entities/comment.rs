@@ -2,20 +2,42 @@
 
 use async_graphql::*;
 use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue, QueryOrder};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::comment_edit;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, SimpleObject)]
 #[sea_orm(table_name = "comment")]
-#[graphql(concrete(name = "Comment", params()))]
+#[graphql(name = "Comment", complex)]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = true)]
-    #[graphql(skip)]
     pub id: i32,
     #[graphql(skip)]
     pub issue_id: i32,
     pub created_by: String,
     pub comment: String,
     pub created_at: chrono::NaiveDateTime,
+    pub kind: CommentKind,
+    pub edited_at: Option<chrono::NaiveDateTime>,
+    #[graphql(skip)]
+    pub deleted_at: Option<chrono::NaiveDateTime>,
+    #[graphql(skip)]
+    pub deleted_by: Option<String>,
+}
+
+#[ComplexObject]
+impl Model {
+    /// previous versions of the comment, oldest first
+    pub async fn history(&self, ctx: &Context<'_>) -> Vec<comment_edit::Model> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        self.find_related(comment_edit::Entity)
+            .order_by_asc(comment_edit::Column::Id)
+            .all(db)
+            .await
+            .unwrap()
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -28,6 +50,8 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     Issue,
+    #[sea_orm(has_many = "super::comment_edit::Entity")]
+    CommentEdit,
 }
 
 impl Related<super::issue::Entity> for Entity {
@@ -36,10 +60,62 @@ impl Related<super::issue::Entity> for Entity {
     }
 }
 
+impl Related<super::comment_edit::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CommentEdit.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
+impl ActiveModel {
+    /// comment recording something ctt or an operator did to the issue
+    pub fn system(issue_id: i32, operator: &str, comment: String) -> Self {
+        Self {
+            created_by: ActiveValue::Set(operator.to_string()),
+            comment: ActiveValue::Set(comment),
+            issue_id: ActiveValue::Set(issue_id),
+            kind: ActiveValue::Set(CommentKind::System),
+            ..Default::default()
+        }
+    }
+
+    /// comment written by an operator
+    pub fn note(issue_id: i32, operator: &str, comment: String) -> Self {
+        Self {
+            kind: ActiveValue::Set(CommentKind::Note),
+            ..Self::system(issue_id, operator, comment)
+        }
+    }
+}
+
 impl Entity {
+    /// comments on an issue that haven't been deleted
     pub fn for_issue(id: i32) -> Select<Entity> {
-        Self::find().filter(Column::IssueId.eq(id))
+        Self::find()
+            .filter(Column::IssueId.eq(id))
+            .filter(Column::DeletedAt.is_null())
     }
 }
+
+#[derive(
+    Copy,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    EnumIter,
+    DeriveActiveEnum,
+    async_graphql::Enum,
+    Serialize,
+    Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "comment_kind")]
+pub enum CommentKind {
+    /// audit trail written as a side effect of changing an issue
+    #[sea_orm(string_value = "System")]
+    System,
+    /// note left by an operator, only these can be edited or deleted
+    #[sea_orm(string_value = "Note")]
+    Note,
+}
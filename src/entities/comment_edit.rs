@@ -0,0 +1,38 @@
+use async_graphql::*;
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// a comment's text before it was edited or deleted
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, SimpleObject)]
+#[sea_orm(table_name = "comment_edit")]
+#[graphql(name = "CommentEdit")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    #[graphql(skip)]
+    pub id: i32,
+    #[graphql(skip)]
+    pub comment_id: i32,
+    pub comment: String,
+    pub edited_by: String,
+    pub edited_at: chrono::NaiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::comment::Entity",
+        from = "Column::CommentId",
+        to = "super::comment::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Comment,
+}
+
+impl Related<super::comment::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Comment.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
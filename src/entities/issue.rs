@@ -1,10 +1,10 @@
-use super::{comment, target};
+use super::{comment, issue_label, issue_target, target};
 use crate::cluster::ClusterTrait;
 use crate::cluster::RegexCluster;
-use crate::Conf;
-use crate::PbsScheduler;
 use async_graphql::*;
 use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::Query;
+use sea_orm::{Condition, QueryOrder, QuerySelect};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::warn;
@@ -25,13 +25,18 @@ pub struct Model {
     #[graphql(skip)]
     pub target_id: i32,
     pub title: String,
+    /// times the issue has been reopened after being closed
+    pub reopen_count: i32,
+    pub severity: Severity,
+    /// None for issues opened before categories existed
+    pub category: Option<Category>,
 }
 
 #[ComplexObject]
 impl Model {
     pub async fn comments(&self, ctx: &Context<'_>) -> Vec<comment::Model> {
         let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
-        let t = self.find_related(comment::Entity).all(db).await;
+        let t = comment::Entity::for_issue(self.id).all(db).await;
         if let Err(e) = t {
             warn!("Error getting target for issue {}: {}", self.id, e);
             vec![]
@@ -43,15 +48,28 @@ impl Model {
         let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
         self.get_target(db).await
     }
+    /// every node the issue is against, its target first
+    pub async fn targets(&self, ctx: &Context<'_>) -> Vec<target::Model> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        self.get_targets(db).await
+    }
+    /// every node the issue is against as a hostlist
+    pub async fn hostlist(&self, ctx: &Context<'_>) -> String {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        crate::hostlist::compress(self.get_targets(db).await.iter().map(|t| &t.name))
+    }
+    pub async fn labels(&self, ctx: &Context<'_>) -> Vec<String> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        self.get_labels(db).await
+    }
     pub async fn related(&self, ctx: &Context<'_>) -> Vec<target::Model> {
         let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
-        let conf = ctx.data::<Conf>().unwrap();
-        let cluster = RegexCluster::new(conf.node_types.clone(), PbsScheduler::new());
-        self.get_related(db, &cluster).await
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        target::Entity::find_by_names(&self.related_names(db, cluster).await, db).await
     }
 }
 impl Model {
-    pub async fn get_target(&self, db: &DatabaseConnection) -> Option<target::Model> {
+    pub async fn get_target(&self, db: &impl ConnectionTrait) -> Option<target::Model> {
         let t = self.find_related(target::Entity).one(db).await;
         if let Err(e) = t {
             warn!("Error getting target for issue {}: {}", self.id, e);
@@ -60,36 +78,64 @@ impl Model {
             t.unwrap()
         }
     }
-    pub async fn get_related(
+    /// the issue's target followed by its extra targets
+    pub async fn get_targets(&self, db: &impl ConnectionTrait) -> Vec<target::Model> {
+        let mut targets: Vec<target::Model> = self.get_target(db).await.into_iter().collect();
+        let extra = target::Entity::find()
+            .filter(
+                target::Column::Id.in_subquery(
+                    Query::select()
+                        .column(issue_target::Column::TargetId)
+                        .from(issue_target::Entity)
+                        .and_where(issue_target::Column::IssueId.eq(self.id))
+                        .to_owned(),
+                ),
+            )
+            .filter(target::Column::Id.ne(self.target_id))
+            .order_by_asc(target::Column::Name)
+            .all(db)
+            .await;
+        match extra {
+            Ok(mut extra) => targets.append(&mut extra),
+            Err(e) => warn!("Error getting targets for issue {}: {}", self.id, e),
+        }
+        targets
+    }
+    pub async fn get_labels(&self, db: &impl ConnectionTrait) -> Vec<String> {
+        issue_label::Entity::find()
+            .select_only()
+            .column(issue_label::Column::Label)
+            .filter(issue_label::Column::IssueId.eq(self.id))
+            .order_by_asc(issue_label::Column::Label)
+            .into_tuple()
+            .all(db)
+            .await
+            .unwrap()
+    }
+    /// every node the issue requires to be offline (or down if to_offline isn't set), including
+    /// ones ctt isn't tracking yet
+    pub async fn related_names(
         &self,
-        db: &DatabaseConnection,
+        db: &impl ConnectionTrait,
         cluster: &RegexCluster,
-    ) -> Vec<target::Model> {
-        let mut related: Vec<target::Model> = vec![];
-        let tar = self.get_target(db).await;
-        if tar.is_none() {
+    ) -> Vec<String> {
+        let mut related: Vec<String> = vec![];
+        let targets = self.get_targets(db).await;
+        if targets.is_empty() {
             warn!("Error getting target for issue {}", self.id);
             return related;
         };
-        let tar = tar.unwrap();
-        match self.to_offline {
-            Some(ToOffline::Card) => {
-                for t in cluster.siblings(&tar.name) {
-                    if let Some(tmp) = target::Entity::from_name(&t, db, cluster).await {
-                        related.push(tmp);
-                    }
-                }
-            }
-            Some(ToOffline::Blade) => {
-                for t in cluster.cousins(&tar.name) {
-                    if let Some(tmp) = target::Entity::from_name(&t, db, cluster).await {
-                        related.push(tmp);
-                    }
-                }
-            }
-            _ => {
+        for tar in targets {
+            let group = match self.to_offline {
+                Some(ToOffline::Card) => cluster.siblings(&tar.name),
+                Some(ToOffline::Blade) => cluster.cousins(&tar.name),
                 //target is related if ToOffline is Node or None
-                related.push(tar)
+                _ => vec![tar.name.clone()],
+            };
+            for t in group {
+                if !related.contains(&t) {
+                    related.push(t);
+                }
             }
         }
         related
@@ -124,7 +170,34 @@ impl Related<super::target::Entity> for Entity {
 
 impl ActiveModelBehavior for ActiveModel {}
 
-impl Entity {}
+impl Entity {
+    /// matches issues with the label
+    pub fn labelled(label: &str) -> Condition {
+        Condition::all().add(
+            Column::Id.in_subquery(
+                Query::select()
+                    .column(issue_label::Column::IssueId)
+                    .from(issue_label::Entity)
+                    .and_where(issue_label::Column::Label.eq(label))
+                    .to_owned(),
+            ),
+        )
+    }
+    /// matches issues against any of the given targets, as their target or an extra target
+    pub fn targeting(ids: Vec<i32>) -> Condition {
+        Condition::any()
+            .add(Column::TargetId.is_in(ids.clone()))
+            .add(
+                Column::Id.in_subquery(
+                    Query::select()
+                        .column(issue_target::Column::IssueId)
+                        .from(issue_target::Entity)
+                        .and_where(issue_target::Column::TargetId.is_in(ids))
+                        .to_owned(),
+                ),
+            )
+    }
+}
 
 #[derive(
     Copy,
@@ -171,3 +244,56 @@ pub enum ToOffline {
     #[sea_orm(string_value = "Blade")]
     Blade,
 }
+
+#[derive(
+    Copy,
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
+    EnumIter,
+    DeriveActiveEnum,
+    async_graphql::Enum,
+    Serialize,
+    Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "severity")]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[sea_orm(string_value = "Info")]
+    Info,
+    #[default]
+    #[sea_orm(string_value = "Minor")]
+    Minor,
+    #[sea_orm(string_value = "Major")]
+    Major,
+    #[sea_orm(string_value = "Critical")]
+    Critical,
+}
+
+#[derive(
+    Copy,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    EnumIter,
+    DeriveActiveEnum,
+    async_graphql::Enum,
+    Serialize,
+    Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "category")]
+#[serde(rename_all = "kebab-case")]
+pub enum Category {
+    #[sea_orm(string_value = "Hardware")]
+    Hardware,
+    #[sea_orm(string_value = "Software")]
+    Software,
+    #[sea_orm(string_value = "Network")]
+    Network,
+    /// opened by sync for a node the scheduler reported down
+    #[sea_orm(string_value = "SchedulerDetected")]
+    SchedulerDetected,
+}
@@ -0,0 +1,26 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// free-form labels on an issue, e.g. `dimm` or `vendor-case-open`
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "issue_label")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub issue_id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub label: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::issue::Entity",
+        from = "Column::IssueId",
+        to = "super::issue::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Issue,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,34 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// targets an issue covers besides its own `target_id`, e.g. every node behind a failed switch
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "issue_target")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub issue_id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub target_id: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::issue::Entity",
+        from = "Column::IssueId",
+        to = "super::issue::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Issue,
+    #[sea_orm(
+        belongs_to = "super::target::Entity",
+        from = "Column::TargetId",
+        to = "super::target::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Target,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,4 +1,11 @@
+pub mod alert;
+pub mod alert_node;
+pub mod api_key;
+pub mod audit_event;
 pub mod comment;
+pub mod comment_edit;
 pub mod issue;
+pub mod issue_label;
+pub mod issue_target;
 pub mod prelude;
 pub mod target;
@@ -1,4 +1,18 @@
 #[allow(unused_imports)]
+pub use super::alert::Entity as Alert;
+#[allow(unused_imports)]
+pub use super::alert_node::Entity as AlertNode;
+#[allow(unused_imports)]
+pub use super::api_key::Entity as ApiKey;
+#[allow(unused_imports)]
+pub use super::audit_event::Entity as AuditEvent;
+#[allow(unused_imports)]
 pub use super::comment::Entity as Comment;
+#[allow(unused_imports)]
+pub use super::comment_edit::Entity as CommentEdit;
 pub use super::issue::Entity as Issue;
+#[allow(unused_imports)]
+pub use super::issue_label::Entity as IssueLabel;
+#[allow(unused_imports)]
+pub use super::issue_target::Entity as IssueTarget;
 pub use super::target::Entity as Target;
@@ -1,15 +1,18 @@
-use super::issue;
+use super::issue::{self, IssueStatus};
+use super::issue_target;
 use crate::cluster::ClusterTrait;
 use crate::cluster::RegexCluster;
 use async_graphql::*;
 use sea_orm::entity::prelude::*;
-use sea_orm::{ActiveValue, QueryOrder};
+use sea_orm::sea_query::Query;
+use sea_orm::{ActiveValue, Condition, QueryOrder};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use tracing::{debug, info, instrument, warn};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, SimpleObject)]
 #[sea_orm(table_name = "target")]
-#[graphql(concrete(name = "Target", params()))]
+#[graphql(name = "Target", complex)]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = true)]
     #[graphql(skip)]
@@ -30,10 +33,64 @@ impl Related<super::issue::Entity> for Entity {
     }
 }
 
+#[ComplexObject]
 impl Model {
+    /// issues against this node, all but Closed ones unless a status is given
+    #[graphql(name = "issues")]
+    pub async fn gql_issues(
+        &self,
+        ctx: &Context<'_>,
+        issue_status: Option<IssueStatus>,
+    ) -> Vec<issue::Model> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let select = if let Some(status) = issue_status {
+            self.issues().filter(issue::Column::Status.eq(status))
+        } else {
+            self.issues()
+                .filter(issue::Column::Status.ne(IssueStatus::Closed))
+        };
+        select.all(db).await.unwrap()
+    }
+    pub async fn siblings(&self, ctx: &Context<'_>) -> Vec<Model> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        Entity::find_by_names(&cluster.siblings(&self.name), db).await
+    }
+    pub async fn cousins(&self, ctx: &Context<'_>) -> Vec<Model> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        Entity::find_by_names(&cluster.cousins(&self.name), db).await
+    }
+    /// state open issues require the node to be in
+    pub async fn desired_state(&self, ctx: &Context<'_>) -> TargetStatus {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        crate::sync::desired_state(&self.name, db, cluster).await.0
+    }
+    /// scheduler's comment on the node as of the last sync
+    pub async fn scheduler_comment(&self, ctx: &Context<'_>) -> Option<String> {
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        cluster.last_status(&self.name).map(|(_, c)| c)
+    }
+}
+
+impl Model {
+    /// issues with this node as their target or as one of their extra targets
     #[instrument]
     pub fn issues(&self) -> Select<issue::Entity> {
-        self.find_related(issue::Entity)
+        issue::Entity::find().filter(
+            Condition::any()
+                .add(issue::Column::TargetId.eq(self.id))
+                .add(
+                    issue::Column::Id.in_subquery(
+                        Query::select()
+                            .column(issue_target::Column::IssueId)
+                            .from(issue_target::Entity)
+                            .and_where(issue_target::Column::TargetId.eq(self.id))
+                            .to_owned(),
+                    ),
+                ),
+        )
     }
 }
 
@@ -44,10 +101,10 @@ impl Entity {
     pub fn all() -> Select<Entity> {
         Self::find().order_by_asc(Column::Name)
     }
-    #[instrument]
+    #[instrument(skip(db))]
     pub async fn from_name(
         name: &str,
-        db: &DatabaseConnection,
+        db: &impl ConnectionTrait,
         cluster: &RegexCluster,
     ) -> Option<Model> {
         if !cluster.real_node(name) {
@@ -68,11 +125,37 @@ impl Entity {
         }
     }
 
-    #[instrument]
+    /// a node ctt is already tracking, unlike `from_name` this never adds one
+    #[instrument(skip(db))]
+    pub async fn find_by_name(name: &str, db: &impl ConnectionTrait) -> Option<Model> {
+        Self::find()
+            .filter(Column::Name.eq(name))
+            .one(db)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Error getting target {} by name: {}", name, e);
+                None
+            })
+    }
+
+    /// the nodes in `names` ctt is already tracking, by name
+    #[instrument(skip(db, names))]
+    pub async fn find_by_names(names: &[String], db: &impl ConnectionTrait) -> Vec<Model> {
+        Self::all()
+            .filter(Column::Name.is_in(names))
+            .all(db)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Error getting targets by name: {}", e);
+                vec![]
+            })
+    }
+
+    #[instrument(skip(db))]
     async fn create_target(
         name: &str,
         state: TargetStatus,
-        db: &DatabaseConnection,
+        db: &impl ConnectionTrait,
         cluster: &RegexCluster,
     ) -> Option<Model> {
         if !cluster.real_node(name) {
@@ -105,6 +188,7 @@ impl Entity {
     Clone,
     PartialEq,
     Eq,
+    Hash,
     EnumIter,
     DeriveActiveEnum,
     async_graphql::Enum,
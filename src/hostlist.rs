@@ -0,0 +1,232 @@
+//! hostlist expressions, `gu[0001-0032,0040]` style node ranges
+use std::collections::{BTreeMap, BTreeSet};
+
+/// refuse to expand expressions naming more nodes than this
+const MAX_HOSTS: usize = 100_000;
+
+/// split on commas that aren't inside brackets
+fn split_top(expr: &str) -> Result<Vec<&str>, String> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in expr.char_indices() {
+        match c {
+            '[' if depth == 0 => depth += 1,
+            '[' => return Err(format!("nested brackets in {}", expr)),
+            ']' if depth == 1 => depth -= 1,
+            ']' => return Err(format!("unmatched ']' in {}", expr)),
+            ',' if depth == 0 => {
+                parts.push(&expr[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    if depth != 0 {
+        return Err(format!("unmatched '[' in {}", expr));
+    }
+    parts.push(&expr[start..]);
+    Ok(parts)
+}
+
+/// numbers in a bracket, `0001-0004,0010` -> ["0001", "0002", "0003", "0004", "0010"]
+///
+/// a range whose start has a leading zero is padded to the width of its start, fails if the
+/// ranges add up to more than `max` numbers
+fn expand_ranges(ranges: &str, max: usize) -> Result<Vec<String>, String> {
+    let mut nums = vec![];
+    for r in ranges.split(',') {
+        let r = r.trim();
+        let (lo, hi) = r.split_once('-').unwrap_or((r, r));
+        if lo.is_empty() || !lo.chars().chain(hi.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(format!("invalid range '{}'", r));
+        }
+        let start: u64 = lo
+            .parse()
+            .map_err(|e| format!("invalid range '{}': {}", r, e))?;
+        let end: u64 = hi
+            .parse()
+            .map_err(|e| format!("invalid range '{}': {}", r, e))?;
+        if end < start {
+            return Err(format!("invalid range '{}', end is before start", r));
+        }
+        if (nums.len() as u64).saturating_add(end - start) >= max as u64 {
+            return Err(format!("[{}] names too many nodes", ranges));
+        }
+        let width = if lo.starts_with('0') { lo.len() } else { 0 };
+        for n in start..=end {
+            nums.push(format!("{:0>width$}", n, width = width));
+        }
+    }
+    Ok(nums)
+}
+
+/// expand one name, which may contain several bracketed ranges
+fn expand_one(name: &str, max: usize) -> Result<Vec<String>, String> {
+    let Some(open) = name.find('[') else {
+        return Ok(vec![name.to_string()]);
+    };
+    let close = name[open..]
+        .find(']')
+        .map(|c| c + open)
+        .ok_or(format!("unmatched '[' in {}", name))?;
+    let prefix = &name[..open];
+    let rest = expand_one(&name[close + 1..], max)?;
+    let mut hosts = vec![];
+    for n in expand_ranges(&name[open + 1..close], max)? {
+        for r in &rest {
+            hosts.push(format!("{}{}{}", prefix, n, r));
+            if hosts.len() > max {
+                return Err(format!("{} names too many nodes", name));
+            }
+        }
+    }
+    Ok(hosts)
+}
+
+/// every node named by a hostlist expression, in the order given with duplicates removed
+///
+/// `gu[0001-0003,0010],guc0001` -> gu0001, gu0002, gu0003, gu0010, guc0001
+pub fn expand(expr: &str) -> Result<Vec<String>, String> {
+    expand_at_most(expr, MAX_HOSTS)
+}
+
+/// [expand], failing if `expr` names more than `max` nodes
+pub fn expand_at_most(expr: &str, max: usize) -> Result<Vec<String>, String> {
+    let mut seen = BTreeSet::new();
+    let mut hosts = vec![];
+    for part in split_top(expr.trim())? {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        for h in expand_one(part, max)? {
+            if seen.insert(h.clone()) {
+                hosts.push(h);
+            }
+            if hosts.len() > max {
+                return Err(format!("{} names too many nodes", expr));
+            }
+        }
+    }
+    if hosts.is_empty() {
+        return Err("empty hostlist".to_string());
+    }
+    Ok(hosts)
+}
+
+/// shortest hostlist expression for a set of nodes, the inverse of [expand]
+///
+/// nodes are grouped by prefix and the number of digits they end in, so zero padded names
+/// round trip
+pub fn compress<I, S>(hosts: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    // (prefix, digits) -> numbers
+    let mut groups: BTreeMap<(String, usize), BTreeSet<u64>> = BTreeMap::new();
+    let mut plain = BTreeSet::new();
+    for h in hosts {
+        let h = h.as_ref();
+        let prefix = h.trim_end_matches(|c: char| c.is_ascii_digit());
+        let digits = &h[prefix.len()..];
+        match digits.parse::<u64>() {
+            Ok(n) if !digits.is_empty() => {
+                groups
+                    .entry((prefix.to_string(), digits.len()))
+                    .or_default()
+                    .insert(n);
+            }
+            _ => {
+                plain.insert(h.to_string());
+            }
+        }
+    }
+    let mut out: Vec<String> = plain.into_iter().collect();
+    for ((prefix, width), nums) in groups {
+        let fmt = |n: u64| format!("{:0>width$}", n, width = width);
+        let mut ranges: Vec<(u64, u64)> = vec![];
+        for n in nums {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == n => *end = n,
+                _ => ranges.push((n, n)),
+            }
+        }
+        if let [(start, end)] = ranges[..]
+            && start == end
+        {
+            out.push(format!("{}{}", prefix, fmt(start)));
+            continue;
+        }
+        let ranges: Vec<String> = ranges
+            .into_iter()
+            .map(|(s, e)| {
+                if s == e {
+                    fmt(s)
+                } else {
+                    format!("{}-{}", fmt(s), fmt(e))
+                }
+            })
+            .collect();
+        out.push(format!("{}[{}]", prefix, ranges.join(",")));
+    }
+    out.join(",")
+}
+
+#[test]
+fn expand_expressions() {
+    assert_eq!(expand("gu0001").unwrap(), vec!["gu0001"]);
+    assert_eq!(
+        expand("gu[0001-0003,0010]").unwrap(),
+        vec!["gu0001", "gu0002", "gu0003", "gu0010"]
+    );
+    assert_eq!(
+        expand("gu[0009-0010],guc0001, gu0009").unwrap(),
+        vec!["gu0009", "gu0010", "guc0001"]
+    );
+    assert_eq!(expand("tn[8-10]").unwrap(), vec!["tn8", "tn9", "tn10"]);
+    assert_eq!(
+        expand("r[1-2]n[01-02]").unwrap(),
+        vec!["r1n01", "r1n02", "r2n01", "r2n02"]
+    );
+    for bad in [
+        "",
+        "gu[0001-0003",
+        "gu0001]",
+        "gu[0003-0001]",
+        "gu[a-b]",
+        "gu[[1-2]]",
+        "gu[0-9999999]",
+        // each range is small but together they're too many
+        "gu[0-99998,0-99998]",
+    ] {
+        assert!(expand(bad).is_err(), "{}", bad);
+    }
+    assert_eq!(expand_at_most("gu[1-4]", 4).unwrap().len(), 4);
+    assert!(expand_at_most("gu[1-4]", 3).is_err());
+    assert!(expand_at_most("gu[1-2,3-4]", 3).is_err());
+    assert!(expand_at_most("gu[1-2],gu[3-4]", 3).is_err());
+}
+
+#[test]
+fn compress_round_trips() {
+    assert_eq!(
+        compress(["gu0003", "gu0001", "gu0002", "gu0040", "guc0001"]),
+        "gu[0001-0003,0040],guc0001"
+    );
+    assert_eq!(
+        compress(["tn8", "tn9", "tn10", "login"]),
+        "login,tn[8-9],tn10"
+    );
+    assert_eq!(compress(Vec::<String>::new()), "");
+
+    // names built the way RegexCluster pads NodeType digits
+    let nodes: Vec<String> = (1..=32)
+        .chain([40, 128])
+        .map(|i| format!("{}{:0>width$}", "gu", i, width = 4))
+        .collect();
+    let expr = compress(&nodes);
+    assert_eq!(expr, "gu[0001-0032,0040,0128]");
+    assert_eq!(expand(&expr).unwrap(), nodes);
+}
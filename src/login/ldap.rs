@@ -0,0 +1,276 @@
+use super::{AuthRequest, Identity, LoginBackend, LoginError};
+use crate::auth::AuthMethod;
+use crate::conf::Ldap;
+use async_trait::async_trait;
+use ldap3::{dn_escape, ldap_escape, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// invalidCredentials, from rfc 4511
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// simple bind as the user, then search for the groups they're in
+pub struct LdapBackend {
+    conf: Ldap,
+}
+
+impl LdapBackend {
+    pub fn new(conf: Ldap) -> Self {
+        Self { conf }
+    }
+}
+
+fn unavailable(e: ldap3::LdapError) -> LoginError {
+    warn!("ldap error: {}", e);
+    LoginError::Unavailable("ldap unavailable".to_string())
+}
+
+#[async_trait]
+impl LoginBackend for LdapBackend {
+    async fn login(&self, request: &AuthRequest) -> Result<Option<Identity>, LoginError> {
+        let AuthRequest::Password { user, password } = request else {
+            return Ok(None);
+        };
+        // a bind with an empty password is an anonymous bind, which usually succeeds
+        if password.is_empty() {
+            return Err(LoginError::Denied);
+        }
+        let settings = LdapConnSettings::new().set_conn_timeout(Duration::from_secs(10));
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.conf.url)
+            .await
+            .map_err(unavailable)?;
+        ldap3::drive!(conn);
+        let dn = self
+            .conf
+            .user_dn
+            .replace("{user}", &dn_escape(user.as_str()));
+        let bind = ldap.simple_bind(&dn, password).await.map_err(unavailable)?;
+        if bind.rc == INVALID_CREDENTIALS {
+            info!("ldap bind failed for {}", dn);
+            return Err(LoginError::Denied);
+        }
+        bind.success().map_err(unavailable)?;
+        let filter = self
+            .conf
+            .group_filter
+            .replace("{user}", &ldap_escape(user.as_str()));
+        let (entries, _) = ldap
+            .search(
+                &self.conf.group_base,
+                Scope::Subtree,
+                &filter,
+                vec![self.conf.group_attr.as_str()],
+            )
+            .await
+            .and_then(|r| r.success())
+            .map_err(unavailable)?;
+        let _ = ldap.unbind().await;
+        let groups = entries
+            .into_iter()
+            .map(SearchEntry::construct)
+            .flat_map(|mut e| e.attrs.remove(&self.conf.group_attr).unwrap_or_default())
+            .collect();
+        Ok(Some(Identity {
+            user: user.clone(),
+            groups,
+            method: AuthMethod::Ldap,
+        }))
+    }
+}
+
+/// just enough of an ldap server for a simple bind and one equality filter group search.
+/// `users` maps a dn to its password, `groups` a group cn to its members. returns the url
+#[cfg(test)]
+pub(super) async fn stand_in(
+    users: std::collections::HashMap<String, String>,
+    groups: std::collections::HashMap<String, Vec<String>>,
+) -> String {
+    use ldap3::asn1::{parse_tag, write, StructureTag, TagClass, PL};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn prim(class: TagClass, id: u64, v: &[u8]) -> StructureTag {
+        StructureTag {
+            class,
+            id,
+            payload: PL::P(v.to_vec()),
+        }
+    }
+    fn cons(class: TagClass, id: u64, v: Vec<StructureTag>) -> StructureTag {
+        StructureTag {
+            class,
+            id,
+            payload: PL::C(v),
+        }
+    }
+    fn result(op: u64, rc: u8) -> StructureTag {
+        cons(
+            TagClass::Application,
+            op,
+            vec![
+                prim(TagClass::Universal, 10, &[rc]),
+                prim(TagClass::Universal, 4, b""),
+                prim(TagClass::Universal, 4, b""),
+            ],
+        )
+    }
+    fn bytes(t: &StructureTag) -> String {
+        match &t.payload {
+            PL::P(b) => String::from_utf8_lossy(b).to_string(),
+            PL::C(_) => String::new(),
+        }
+    }
+    /// value of the first equalityMatch in a filter
+    fn equality_value(t: &StructureTag) -> Option<String> {
+        match &t.payload {
+            PL::C(c) if t.class == TagClass::Context && t.id == 3 => c.get(1).map(bytes),
+            PL::C(c) => c.iter().find_map(equality_value),
+            PL::P(_) => None,
+        }
+    }
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ldap://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut sock, _)) = listener.accept().await else {
+                return;
+            };
+            let (users, groups) = (users.clone(), groups.clone());
+            tokio::spawn(async move {
+                let mut buf = vec![];
+                let mut chunk = [0; 4096];
+                loop {
+                    let (msg, used) = match parse_tag(&buf) {
+                        Ok((rest, msg)) => (msg, buf.len() - rest.len()),
+                        Err(_) => match sock.read(&mut chunk).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => {
+                                buf.extend_from_slice(&chunk[..n]);
+                                continue;
+                            }
+                        },
+                    };
+                    buf.drain(..used);
+                    let Some(mut parts) = msg.expect_constructed() else {
+                        return;
+                    };
+                    let op = parts.remove(1);
+                    let id = parts.remove(0);
+                    let replies = match (op.id, op.payload) {
+                        // bind
+                        (0, PL::C(req)) => {
+                            let ok = users.get(&bytes(&req[1])) == Some(&bytes(&req[2]));
+                            vec![result(1, if ok { 0 } else { 49 })]
+                        }
+                        // search
+                        (3, PL::C(req)) => {
+                            let member = equality_value(&req[6]).unwrap_or_default();
+                            let mut replies: Vec<StructureTag> = groups
+                                .iter()
+                                .filter(|(_, members)| members.contains(&member))
+                                .map(|(cn, _)| {
+                                    let attr = cons(
+                                        TagClass::Universal,
+                                        16,
+                                        vec![
+                                            prim(TagClass::Universal, 4, b"cn"),
+                                            cons(
+                                                TagClass::Universal,
+                                                17,
+                                                vec![prim(TagClass::Universal, 4, cn.as_bytes())],
+                                            ),
+                                        ],
+                                    );
+                                    cons(
+                                        TagClass::Application,
+                                        4,
+                                        vec![
+                                            prim(
+                                                TagClass::Universal,
+                                                4,
+                                                format!("cn={},ou=groups", cn).as_bytes(),
+                                            ),
+                                            cons(TagClass::Universal, 16, vec![attr]),
+                                        ],
+                                    )
+                                })
+                                .collect();
+                            replies.push(result(5, 0));
+                            replies
+                        }
+                        // unbind, or anything else
+                        _ => return,
+                    };
+                    for r in replies {
+                        let mut out = bytes::BytesMut::new();
+                        let msg = cons(TagClass::Universal, 16, vec![id.clone(), r]);
+                        write::encode_into(&mut out, msg).unwrap();
+                        if sock.write_all(&out).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    url
+}
+
+#[tokio::test]
+async fn ldap_bind_and_group_lookup() {
+    let url = stand_in(
+        [(
+            "uid=alice,ou=people,dc=example,dc=org".to_string(),
+            "hunter2".to_string(),
+        )]
+        .into(),
+        [
+            ("ssg".to_string(), vec!["alice".to_string()]),
+            (
+                "ncar".to_string(),
+                vec!["alice".to_string(), "bob".to_string()],
+            ),
+            ("csg".to_string(), vec!["bob".to_string()]),
+        ]
+        .into(),
+    )
+    .await;
+    let ldap = LdapBackend::new(Ldap {
+        url,
+        user_dn: "uid={user},ou=people,dc=example,dc=org".to_string(),
+        group_base: "ou=groups,dc=example,dc=org".to_string(),
+        group_filter: "(memberUid={user})".to_string(),
+        group_attr: "cn".to_string(),
+    });
+    let login = |user: &str, password: &str| {
+        let request = AuthRequest::Password {
+            user: user.to_string(),
+            password: password.to_string(),
+        };
+        let backend = &ldap;
+        async move { backend.login(&request).await }
+    };
+
+    let alice = login("alice", "hunter2").await.unwrap().unwrap();
+    assert_eq!(alice.method, AuthMethod::Ldap);
+    assert_eq!(
+        alice.groups,
+        ["ssg", "ncar"].into_iter().map(str::to_string).collect()
+    );
+    assert_eq!(login("alice", "wrong").await, Err(LoginError::Denied));
+    assert_eq!(login("alice", "").await, Err(LoginError::Denied));
+    assert_eq!(login("bob", "hunter2").await, Err(LoginError::Denied));
+
+    let down = LdapBackend::new(Ldap {
+        url: "ldap://127.0.0.1:1".to_string(),
+        ..ldap.conf.clone()
+    });
+    assert!(matches!(
+        down.login(&AuthRequest::Password {
+            user: "alice".to_string(),
+            password: "hunter2".to_string(),
+        })
+        .await,
+        Err(LoginError::Unavailable(_))
+    ));
+}
@@ -0,0 +1,181 @@
+use crate::auth::AuthMethod;
+use crate::conf::Auth;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt;
+
+mod ldap;
+mod munge;
+mod password_file;
+pub use ldap::LdapBackend;
+pub use munge::MungeBackend;
+pub use password_file::PasswordFile;
+
+/// body of a /login request
+#[derive(Deserialize, Clone)]
+pub enum AuthRequest {
+    // munge encrypted Json<UserLogin>
+    Munge(String),
+    /// checked against `auth.password_file`, then `auth.ldap`
+    Password {
+        user: String,
+        password: String,
+    },
+}
+
+impl AuthRequest {
+    /// user the caller claims to be, munge requests only say once they're decoded
+    pub fn user(&self) -> Option<&str> {
+        match self {
+            AuthRequest::Munge(_) => None,
+            AuthRequest::Password { user, .. } => Some(user),
+        }
+    }
+
+    pub fn method(&self) -> AuthMethod {
+        match self {
+            AuthRequest::Munge(_) => AuthMethod::Munge,
+            AuthRequest::Password { .. } => AuthMethod::Password,
+        }
+    }
+}
+
+impl fmt::Debug for AuthRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthRequest::Munge(_) => write!(f, "Munge"),
+            AuthRequest::Password { user, .. } => write!(f, "Password {{ user: {:?} }}", user),
+        }
+    }
+}
+
+/// who a backend says the caller is, their groups are mapped to a role by `Auth::role_for`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub user: String,
+    pub groups: HashSet<String>,
+    pub method: AuthMethod,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoginError {
+    /// the request couldn't be read
+    BadRequest(String),
+    /// wrong password, or no backend knows the user
+    Denied,
+    /// the backend itself failed, eg the ldap server is down
+    Unavailable(String),
+}
+
+#[async_trait]
+pub trait LoginBackend: Send + Sync {
+    /// `Ok(None)` if this backend doesn't handle `request` or doesn't know the user, so the
+    /// next backend gets a try
+    async fn login(&self, request: &AuthRequest) -> Result<Option<Identity>, LoginError>;
+
+    /// current groups of `user`, who logged in with `method`, so /refresh sees changes to them.
+    /// `Ok(None)` if this backend didn't log them in or can't look groups up without the
+    /// user's credentials
+    async fn groups(
+        &self,
+        _user: &str,
+        _method: AuthMethod,
+    ) -> Result<Option<HashSet<String>>, LoginError> {
+        Ok(None)
+    }
+}
+
+/// munge, then whichever of the password file and ldap are configured
+pub fn backends(auth: &Auth) -> Vec<Box<dyn LoginBackend>> {
+    let mut backends: Vec<Box<dyn LoginBackend>> = vec![Box::new(MungeBackend)];
+    if let Some(path) = &auth.password_file {
+        backends.push(Box::new(PasswordFile::new(path)));
+    }
+    if let Some(conf) = &auth.ldap {
+        backends.push(Box::new(LdapBackend::new(conf.clone())));
+    }
+    backends
+}
+
+/// the first backend that knows the caller decides
+pub async fn login(auth: &Auth, request: &AuthRequest) -> Result<Identity, LoginError> {
+    for backend in backends(auth) {
+        if let Some(id) = backend.login(request).await? {
+            return Ok(id);
+        }
+    }
+    Err(LoginError::Denied)
+}
+
+/// current groups of someone who already logged in, `Ok(None)` if no backend can say
+pub async fn groups(
+    auth: &Auth,
+    user: &str,
+    method: AuthMethod,
+) -> Result<Option<HashSet<String>>, LoginError> {
+    for backend in backends(auth) {
+        if let Some(groups) = backend.groups(user, method).await? {
+            return Ok(Some(groups));
+        }
+    }
+    Ok(None)
+}
+
+#[tokio::test]
+async fn password_file_then_ldap() {
+    use argon2::password_hash::{PasswordHasher, SaltString};
+    let salt = SaltString::encode_b64(b"not very random").unwrap();
+    let hash = argon2::Argon2::default()
+        .hash_password(b"hunter2", &salt)
+        .unwrap();
+    let path = std::env::temp_dir().join(format!("cttd-passwd-{}", rand::random::<u64>()));
+    std::fs::write(&path, format!("alice:{}:ncar\n", hash)).unwrap();
+    let url = ldap::stand_in(
+        [
+            (
+                "uid=alice,ou=people,dc=example,dc=org".to_string(),
+                "ldap-pw".to_string(),
+            ),
+            (
+                "uid=bob,ou=people,dc=example,dc=org".to_string(),
+                "ldap-pw".to_string(),
+            ),
+        ]
+        .into(),
+        [("ssg".to_string(), vec!["bob".to_string()])].into(),
+    )
+    .await;
+    let auth = Auth {
+        admin: vec!["ssg".to_string()],
+        guest: vec!["ncar".to_string()],
+        password_file: Some(path.to_str().unwrap().to_string()),
+        ldap: Some(crate::conf::Ldap {
+            url,
+            user_dn: "uid={user},ou=people,dc=example,dc=org".to_string(),
+            group_base: "ou=groups,dc=example,dc=org".to_string(),
+            group_filter: "(memberUid={user})".to_string(),
+            group_attr: "cn".to_string(),
+        }),
+        ..crate::auth::test_auth()
+    };
+    let try_login = |user: &str, password: &str| {
+        let request = AuthRequest::Password {
+            user: user.to_string(),
+            password: password.to_string(),
+        };
+        let auth = auth.clone();
+        async move { login(&auth, &request).await }
+    };
+
+    let alice = try_login("alice", "hunter2").await.unwrap();
+    assert_eq!(alice.method, AuthMethod::Password);
+    assert_eq!(auth.role_for(&alice.groups).unwrap().0, "Guest");
+    // the password file knows alice, so her ldap password isn't tried
+    assert_eq!(try_login("alice", "ldap-pw").await, Err(LoginError::Denied));
+
+    let bob = try_login("bob", "ldap-pw").await.unwrap();
+    assert_eq!(bob.method, AuthMethod::Ldap);
+    assert_eq!(auth.role_for(&bob.groups).unwrap().0, "Admin");
+    assert_eq!(try_login("carol", "ldap-pw").await, Err(LoginError::Denied));
+}
@@ -0,0 +1,74 @@
+use super::{AuthRequest, Identity, LoginBackend, LoginError};
+use crate::auth::AuthMethod;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashSet;
+use tracing::{debug, info, warn};
+
+#[derive(Deserialize, Debug)]
+pub struct UserLogin {
+    user: String,
+}
+
+/// a munge credential wrapping a `UserLogin`, groups come from the server's posix groups
+pub struct MungeBackend;
+
+#[async_trait]
+impl LoginBackend for MungeBackend {
+    async fn login(&self, request: &AuthRequest) -> Result<Option<Identity>, LoginError> {
+        let AuthRequest::Munge(payload) = request else {
+            return Ok(None);
+        };
+        let payload = munge_auth::unmunge(payload.to_string()).map_err(|e| {
+            warn!("unable to unmunge payload: {}", e);
+            LoginError::BadRequest("Unable to deserialize request".to_string())
+        })?;
+        info!("Login request: {:?}", payload);
+        let uid = payload.uid;
+        let login: UserLogin = serde_json::from_str(&payload.msg)
+            .map_err(|_| LoginError::BadRequest("Unable to deserialize request".to_string()))?;
+        info!("Login request: {:?}", login);
+        let groups = user_groups(&login.user, uid).ok_or(LoginError::Denied)?;
+        Ok(Some(Identity {
+            // using login.user is fine since its been authenticated via munge
+            user: login.user,
+            groups,
+            method: AuthMethod::Munge,
+        }))
+    }
+
+    async fn groups(
+        &self,
+        user: &str,
+        method: AuthMethod,
+    ) -> Result<Option<HashSet<String>>, LoginError> {
+        if method != AuthMethod::Munge {
+            return Ok(None);
+        }
+        let user = users::get_user_by_name(user).ok_or(LoginError::Denied)?;
+        posix_groups(&user).map(Some).ok_or(LoginError::Denied)
+    }
+}
+
+/// the posix groups of `usr`, if `uid` really is theirs
+fn user_groups(usr: &str, uid: u32) -> Option<HashSet<String>> {
+    let user = users::get_user_by_name(usr)?;
+    if user.uid() != uid {
+        debug!(
+            "UID does not match expected user: {:?} expected uid: {}",
+            usr, uid
+        );
+        return None;
+    }
+    posix_groups(&user)
+}
+
+fn posix_groups(user: &users::User) -> Option<HashSet<String>> {
+    Some(
+        user.groups()?
+            .iter()
+            .map(|g| g.name().to_os_string().into_string())
+            .filter_map(|x| x.ok())
+            .collect(),
+    )
+}
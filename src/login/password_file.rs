@@ -0,0 +1,146 @@
+use super::{AuthRequest, Identity, LoginBackend, LoginError};
+use crate::auth::AuthMethod;
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+/// local users for the web ui, one `user:argon2 hash[:group,group]` per line, `#` starts a
+/// comment. reread on every login so users can be added without a restart
+pub struct PasswordFile {
+    path: PathBuf,
+}
+
+impl PasswordFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// `user`'s hash and groups, None if they aren't in the file
+    async fn entry(&self, user: &str) -> Result<Option<(String, HashSet<String>)>, LoginError> {
+        let contents = tokio::fs::read_to_string(&self.path).await.map_err(|e| {
+            warn!("unable to read {}: {}", self.path.display(), e);
+            LoginError::Unavailable("password file unreadable".to_string())
+        })?;
+        Ok(contents
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| l.splitn(3, ':').collect::<Vec<_>>())
+            .find(|f| f[0] == user)
+            .and_then(|f| Some((f.get(1)?.to_string(), f.get(2).copied().unwrap_or(""))))
+            .map(|(hash, groups)| {
+                let groups = groups
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|g| !g.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                (hash, groups)
+            }))
+    }
+}
+
+#[async_trait]
+impl LoginBackend for PasswordFile {
+    async fn login(&self, request: &AuthRequest) -> Result<Option<Identity>, LoginError> {
+        let AuthRequest::Password { user, password } = request else {
+            return Ok(None);
+        };
+        let Some((hash, groups)) = self.entry(user).await? else {
+            return Ok(None);
+        };
+        // argon2 is slow on purpose, keep it off the async workers
+        let password = password.clone();
+        let ok = tokio::task::spawn_blocking(move || match PasswordHash::new(&hash) {
+            Ok(h) => Argon2::default()
+                .verify_password(password.as_bytes(), &h)
+                .is_ok(),
+            Err(e) => {
+                warn!("bad password hash: {}", e);
+                false
+            }
+        })
+        .await
+        .map_err(|e| LoginError::Unavailable(e.to_string()))?;
+        if !ok {
+            info!("wrong password for {}", user);
+            return Err(LoginError::Denied);
+        }
+        Ok(Some(Identity {
+            user: user.clone(),
+            groups,
+            method: AuthMethod::Password,
+        }))
+    }
+
+    async fn groups(
+        &self,
+        user: &str,
+        method: AuthMethod,
+    ) -> Result<Option<HashSet<String>>, LoginError> {
+        if method != AuthMethod::Password {
+            return Ok(None);
+        }
+        // removed from the file since logging in
+        let (_, groups) = self.entry(user).await?.ok_or(LoginError::Denied)?;
+        Ok(Some(groups))
+    }
+}
+
+#[tokio::test]
+async fn password_file_login() {
+    use argon2::password_hash::{PasswordHasher, SaltString};
+    let salt = SaltString::encode_b64(b"not very random").unwrap();
+    let hash = Argon2::default()
+        .hash_password(b"hunter2", &salt)
+        .unwrap()
+        .to_string();
+    let path = std::env::temp_dir().join(format!("cttd-passwd-{}", rand::random::<u64>()));
+    std::fs::write(
+        &path,
+        format!("# web ui users\nalice:{}:ncar, csg\nbob:{}\n", hash, hash),
+    )
+    .unwrap();
+    let file = PasswordFile::new(&path);
+    let login = |user: &str, password: &str| {
+        let request = AuthRequest::Password {
+            user: user.to_string(),
+            password: password.to_string(),
+        };
+        let backend = &file;
+        async move { backend.login(&request).await }
+    };
+
+    let alice = login("alice", "hunter2").await.unwrap().unwrap();
+    assert_eq!(alice.user, "alice");
+    assert_eq!(alice.method, AuthMethod::Password);
+    assert_eq!(
+        alice.groups,
+        ["ncar", "csg"].into_iter().map(str::to_string).collect()
+    );
+    assert!(login("bob", "hunter2")
+        .await
+        .unwrap()
+        .unwrap()
+        .groups
+        .is_empty());
+    assert_eq!(login("alice", "hunter3").await, Err(LoginError::Denied));
+    // left for the next backend
+    assert_eq!(login("carol", "hunter2").await, Ok(None));
+    assert_eq!(
+        file.login(&AuthRequest::Munge("x".to_string())).await,
+        Ok(None)
+    );
+
+    assert_eq!(
+        file.groups("alice", AuthMethod::Password).await,
+        Ok(Some(alice.groups))
+    );
+    assert_eq!(
+        file.groups("carol", AuthMethod::Password).await,
+        Err(LoginError::Denied)
+    );
+    assert_eq!(file.groups("alice", AuthMethod::Ldap).await, Ok(None));
+}
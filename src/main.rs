@@ -1,34 +1,42 @@
 #![feature(addr_parse_ascii)]
+mod audit;
 mod changelog;
 mod cluster;
 mod conf;
 mod entities;
+mod hostlist;
+mod login;
 mod migrator;
 mod setup;
 mod sync;
+mod tls;
 use crate::conf::Conf;
-use async_graphql::{extensions::Tracing, http::GraphiQLSource, EmptySubscription, Schema};
-use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use async_graphql::{
+    extensions::Tracing,
+    http::{GraphiQLSource, ALL_WEBSOCKET_PROTOCOLS},
+    Schema,
+};
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
 use axum::{
     error_handling::HandleErrorLayer,
-    extract::Extension,
-    response::{self, IntoResponse},
+    extract::{ConnectInfo, Extension, WebSocketUpgrade},
+    middleware,
+    response::{self, IntoResponse, Response},
     routing::{get, post},
     Router,
 };
-use axum_server::tls_rustls::RustlsConfig;
 use axum_server::Handle;
-use cluster::scheduler::PbsScheduler;
+use cluster::{scheduler, RegexCluster};
 use http::StatusCode;
+use metrics_exporter_prometheus::PrometheusBuilder;
 use setup::setup_and_connect;
 use std::env;
 use std::net::SocketAddr;
-use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::select;
 use tokio::signal::unix::{signal, SignalKind};
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
 use tokio::time::sleep;
 use tower::ServiceBuilder;
 use tower_http::validate_request::ValidateRequestHeaderLayer;
@@ -71,39 +79,86 @@ async fn main() {
     );
     tracing::subscriber::set_global_default(registry).unwrap();
 
+    auth::init_keys(&conf).expect("Error loading jwt signing keys");
+
     let (tx, rx): (mpsc::Sender<ChangeLogMsg>, mpsc::Receiver<ChangeLogMsg>) = mpsc::channel(10);
+    let (slack_tx, slack_rx) = mpsc::channel(10);
+    let (events, _) = broadcast::channel::<ChangeLogMsg>(100);
     let db = Arc::new(setup_and_connect(&conf.db).await.unwrap());
-    let schema = Schema::build(model::Query, model::Mutation, EmptySubscription)
+    auth::load_api_keys(&db)
+        .await
+        .expect("Error loading api keys");
+    let cluster = Arc::new(RegexCluster::new(
+        conf.node_types.clone(),
+        scheduler::new(&conf),
+    ));
+    let sync_status = Arc::new(sync::SyncStatus::default());
+    let schema = Schema::build(model::Query, model::Mutation, model::Subscription)
         .extension(Tracing)
         .data(db.clone())
+        .data(cluster.clone())
         .data(tx.clone())
+        .data(events.clone())
         .data(conf.clone())
+        .data(sync_status.clone())
         .finish();
 
     // get certificate and private key used by https
-    let keys = RustlsConfig::from_pem_file(
-        //PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-        PathBuf::from(conf.certs_dir.clone()).join("cert.pem"),
-        PathBuf::from(conf.certs_dir.clone()).join("key.pem"),
-    )
-    .await
-    .unwrap();
+    let keys = tls::rustls_config(&conf).await.unwrap();
+
+    let metrics = PrometheusBuilder::new().install_recorder().unwrap();
 
     let handle = Handle::new();
     tokio::spawn(graceful_shutdown(handle.clone()));
-    tokio::spawn(sync::cluster_sync(db.clone(), conf.clone(), tx));
-    tokio::spawn(changelog::slack_updater(rx, CONFIG.get().unwrap().clone()));
+    tokio::spawn(sync::cluster_sync(
+        db.clone(),
+        cluster,
+        conf.clone(),
+        tx,
+        sync_status,
+    ));
+    tokio::spawn(changelog::fan_out(rx, slack_tx, events));
+    tokio::spawn(auth::rotate_keys(conf.auth.clone()));
+    tokio::spawn(changelog::slack_updater(
+        slack_rx,
+        CONFIG.get().unwrap().clone(),
+    ));
 
+    let render_metrics = get(move || async move { metrics.render() });
     let app = Router::new()
         .route("/", get(graphiql))
         .route("/api", post(graphql_handler))
-        .route_layer(Extension(schema))
         .route("/api/schema", get(schema_handler))
+        .route("/refresh", post(auth::refresh_handler))
+        .route("/audit.jsonl", get(audit::export_handler));
+    // metrics need a token unless they have their own, unauthenticated, listener
+    let app = match &conf.metrics_addr {
+        None => app.route("/metrics", render_metrics),
+        Some(addr) => {
+            let addr = SocketAddr::parse_ascii(addr.as_bytes()).unwrap();
+            let metrics_app = Router::new().route("/metrics", render_metrics);
+            let handle = handle.clone();
+            tokio::spawn(async move {
+                axum_server::bind(addr)
+                    .handle(handle)
+                    .serve(metrics_app.into_make_service())
+                    .await
+                    .unwrap();
+            });
+            app
+        }
+    };
+    let app = app
         .route_layer(ValidateRequestHeaderLayer::custom(conf.auth.clone()))
         //login route can't be protected by auth
         .route("/login", post(auth::login_handler))
+        //websockets authenticate in connection_init instead of with a header
+        .route("/api/ws", get(graphql_ws_handler))
+        .layer(Extension(schema))
+        .layer(Extension(db.clone()))
         //add logging and timeout to all requests
         .layer(Extension(conf.clone()))
+        .layer(middleware::from_fn(audit::request_info))
         .layer(
             ServiceBuilder::new()
                 // `timeout` will produce an error if the handler takes
@@ -115,9 +170,10 @@ async fn main() {
 
     // run https server
     let addr = SocketAddr::parse_ascii(conf.server_addr.as_bytes()).unwrap();
-    axum_server::bind_rustls(addr, keys)
+    axum_server::bind(addr)
+        .acceptor(tls::ClientCertAcceptor::new(keys))
         .handle(handle)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
@@ -126,23 +182,56 @@ async fn main() {
 async fn graphql_handler(
     schema: Extension<model::CttSchema>,
     Extension(role): Extension<auth::RoleGuard>,
+    Extension(info): Extension<audit::RequestInfo>,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
     let mut req = req.into_inner();
-    req = req.data(role);
+    req = req.data(role).data(info);
     let resp = schema.execute(req).await;
     info!("{:?}", &resp);
     resp.into()
 }
 
+#[instrument(skip(schema, websocket))]
+async fn graphql_ws_handler(
+    Extension(schema): Extension<model::CttSchema>,
+    Extension(conf): Extension<Conf>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    cert: Option<Extension<tls::PeerCert>>,
+    Extension(info): Extension<audit::RequestInfo>,
+    protocol: GraphQLProtocol,
+    websocket: WebSocketUpgrade,
+) -> Response {
+    websocket
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| {
+            GraphQLWebSocket::new(stream, schema, protocol)
+                .on_connection_init(move |payload| async move {
+                    let mut data = conf
+                        .auth
+                        .ws_connection_init(payload, addr, cert.map(|c| c.0))
+                        .await?;
+                    // everything done over the connection shares one correlation id
+                    data.insert(info);
+                    Ok(data)
+                })
+                .serve()
+        })
+}
+
 #[instrument]
 async fn graphiql() -> impl IntoResponse {
-    response::Html(GraphiQLSource::build().endpoint("/api").finish())
+    response::Html(
+        GraphiQLSource::build()
+            .endpoint("/api")
+            .subscription_endpoint("/api/ws")
+            .finish(),
+    )
 }
 
 #[instrument]
 async fn schema_handler() -> impl IntoResponse {
-    let schema = Schema::new(model::Query, model::Mutation, EmptySubscription);
+    let schema = Schema::new(model::Query, model::Mutation, model::Subscription);
     schema.sdl()
 }
 
@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Alert::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Alert::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Alert::Title).string().not_null())
+                    .col(ColumnDef::new(Alert::Description).string().not_null())
+                    .col(
+                        ColumnDef::new(Alert::CreatedAt)
+                            .date_time()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Alert::AcknowledgedBy).string())
+                    .col(ColumnDef::new(Alert::AcknowledgedAt).date_time())
+                    .col(ColumnDef::new(Alert::Comment).string())
+                    .col(ColumnDef::new(Alert::ResumedAt).date_time())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Alert::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Alert {
+    Table,
+    Id,
+    Title,
+    Description,
+    CreatedAt,
+    AcknowledgedBy,
+    AcknowledgedAt,
+    Comment,
+    ResumedAt,
+}
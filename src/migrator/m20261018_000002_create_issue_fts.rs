@@ -0,0 +1,69 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// one row per issue, rowid is the issue id and comments holds every comment on the issue.
+// triggers keep it in step with the issue and comment tables so nothing in the api has to
+const UP: &str = "
+CREATE VIRTUAL TABLE issue_fts USING fts5(title, description, comments);
+
+INSERT INTO issue_fts(rowid, title, description, comments)
+    SELECT id, title, description,
+        coalesce((SELECT group_concat(comment, char(10)) FROM comment WHERE issue_id = issue.id), '')
+    FROM issue;
+
+CREATE TRIGGER issue_fts_insert AFTER INSERT ON issue BEGIN
+    INSERT INTO issue_fts(rowid, title, description, comments)
+        VALUES (new.id, new.title, new.description, '');
+END;
+
+CREATE TRIGGER issue_fts_update AFTER UPDATE OF title, description ON issue BEGIN
+    UPDATE issue_fts SET title = new.title, description = new.description WHERE rowid = new.id;
+END;
+
+CREATE TRIGGER issue_fts_delete AFTER DELETE ON issue BEGIN
+    DELETE FROM issue_fts WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER comment_fts_insert AFTER INSERT ON comment BEGIN
+    UPDATE issue_fts SET comments = coalesce(
+        (SELECT group_concat(comment, char(10)) FROM comment WHERE issue_id = new.issue_id), '')
+    WHERE rowid = new.issue_id;
+END;
+
+CREATE TRIGGER comment_fts_update AFTER UPDATE OF comment ON comment BEGIN
+    UPDATE issue_fts SET comments = coalesce(
+        (SELECT group_concat(comment, char(10)) FROM comment WHERE issue_id = new.issue_id), '')
+    WHERE rowid = new.issue_id;
+END;
+
+CREATE TRIGGER comment_fts_delete AFTER DELETE ON comment BEGIN
+    UPDATE issue_fts SET comments = coalesce(
+        (SELECT group_concat(comment, char(10)) FROM comment WHERE issue_id = old.issue_id), '')
+    WHERE rowid = old.issue_id;
+END;
+";
+
+const DOWN: &str = "
+DROP TRIGGER IF EXISTS comment_fts_delete;
+DROP TRIGGER IF EXISTS comment_fts_update;
+DROP TRIGGER IF EXISTS comment_fts_insert;
+DROP TRIGGER IF EXISTS issue_fts_delete;
+DROP TRIGGER IF EXISTS issue_fts_update;
+DROP TRIGGER IF EXISTS issue_fts_insert;
+DROP TABLE IF EXISTS issue_fts;
+";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(UP).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(DOWN).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+use sea_orm::{EnumIter, Iterable};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// deleted comments are kept for their history but drop out of the search index
+const UP_TRIGGERS: &str = "
+DROP TRIGGER IF EXISTS comment_fts_insert;
+DROP TRIGGER IF EXISTS comment_fts_update;
+DROP TRIGGER IF EXISTS comment_fts_delete;
+
+CREATE TRIGGER comment_fts_insert AFTER INSERT ON comment BEGIN
+    UPDATE issue_fts SET comments = coalesce((SELECT group_concat(comment, char(10)) FROM comment
+        WHERE issue_id = new.issue_id AND deleted_at IS NULL), '')
+    WHERE rowid = new.issue_id;
+END;
+
+CREATE TRIGGER comment_fts_update AFTER UPDATE OF comment, deleted_at ON comment BEGIN
+    UPDATE issue_fts SET comments = coalesce((SELECT group_concat(comment, char(10)) FROM comment
+        WHERE issue_id = new.issue_id AND deleted_at IS NULL), '')
+    WHERE rowid = new.issue_id;
+END;
+
+CREATE TRIGGER comment_fts_delete AFTER DELETE ON comment BEGIN
+    UPDATE issue_fts SET comments = coalesce((SELECT group_concat(comment, char(10)) FROM comment
+        WHERE issue_id = old.issue_id AND deleted_at IS NULL), '')
+    WHERE rowid = old.issue_id;
+END;
+";
+
+// triggers from m20261018_000002_create_issue_fts
+const DOWN_TRIGGERS: &str = "
+DROP TRIGGER IF EXISTS comment_fts_insert;
+DROP TRIGGER IF EXISTS comment_fts_update;
+DROP TRIGGER IF EXISTS comment_fts_delete;
+
+CREATE TRIGGER comment_fts_insert AFTER INSERT ON comment BEGIN
+    UPDATE issue_fts SET comments = coalesce(
+        (SELECT group_concat(comment, char(10)) FROM comment WHERE issue_id = new.issue_id), '')
+    WHERE rowid = new.issue_id;
+END;
+
+CREATE TRIGGER comment_fts_update AFTER UPDATE OF comment ON comment BEGIN
+    UPDATE issue_fts SET comments = coalesce(
+        (SELECT group_concat(comment, char(10)) FROM comment WHERE issue_id = new.issue_id), '')
+    WHERE rowid = new.issue_id;
+END;
+
+CREATE TRIGGER comment_fts_delete AFTER DELETE ON comment BEGIN
+    UPDATE issue_fts SET comments = coalesce(
+        (SELECT group_concat(comment, char(10)) FROM comment WHERE issue_id = old.issue_id), '')
+    WHERE rowid = old.issue_id;
+END;
+";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only takes one change per alter table
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Comment::Table)
+                    .add_column(
+                        ColumnDef::new(Comment::Kind)
+                            .enumeration(CommentKind::Table, CommentKind::iter().skip(1))
+                            .not_null()
+                            .default("System"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        for col in [Comment::EditedAt, Comment::DeletedAt] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Comment::Table)
+                        .add_column(ColumnDef::new(col).date_time())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Comment::Table)
+                    .add_column(ColumnDef::new(Comment::DeletedBy).string())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(CommentEdit::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CommentEdit::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(CommentEdit::CommentId).integer().not_null())
+                    .col(ColumnDef::new(CommentEdit::Comment).string().not_null())
+                    .col(ColumnDef::new(CommentEdit::EditedBy).string().not_null())
+                    .col(
+                        ColumnDef::new(CommentEdit::EditedAt)
+                            .date_time()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("comment")
+                            .from(CommentEdit::Table, CommentEdit::CommentId)
+                            .to(Comment::Table, Comment::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .get_connection()
+            .execute_unprepared(UP_TRIGGERS)
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(DOWN_TRIGGERS)
+            .await?;
+        manager
+            .drop_table(Table::drop().table(CommentEdit::Table).to_owned())
+            .await?;
+        for col in [
+            Comment::Kind,
+            Comment::EditedAt,
+            Comment::DeletedAt,
+            Comment::DeletedBy,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Comment::Table)
+                        .drop_column(col)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Comment {
+    Table,
+    Id,
+    Kind,
+    EditedAt,
+    DeletedAt,
+    DeletedBy,
+}
+
+#[derive(DeriveIden)]
+enum CommentEdit {
+    Table,
+    Id,
+    CommentId,
+    #[allow(clippy::enum_variant_names)]
+    Comment,
+    EditedBy,
+    EditedAt,
+}
+
+#[derive(Iden, EnumIter)]
+enum CommentKind {
+    Table,
+    System,
+    Note,
+}
@@ -0,0 +1,75 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(IssueTarget::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(IssueTarget::IssueId).integer().not_null())
+                    .col(ColumnDef::new(IssueTarget::TargetId).integer().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(IssueTarget::IssueId)
+                            .col(IssueTarget::TargetId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("issue")
+                            .from(IssueTarget::Table, IssueTarget::IssueId)
+                            .to(Issue::Table, Issue::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("target")
+                            .from(IssueTarget::Table, IssueTarget::TargetId)
+                            .to(Target::Table, Target::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("issue_target_target")
+                    .table(IssueTarget::Table)
+                    .col(IssueTarget::TargetId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(IssueTarget::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum IssueTarget {
+    Table,
+    IssueId,
+    TargetId,
+}
+
+#[derive(DeriveIden)]
+enum Issue {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Target {
+    Table,
+    Id,
+}
@@ -0,0 +1,119 @@
+use sea_orm::{EnumIter, Iterable};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only takes one change per alter table
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Issue::Table)
+                    .add_column(
+                        ColumnDef::new(Issue::Severity)
+                            .enumeration(Severity::Table, Severity::iter().skip(1))
+                            .not_null()
+                            .default("Minor"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // unset for issues opened before categories existed
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Issue::Table)
+                    .add_column(
+                        ColumnDef::new(Issue::Category)
+                            .enumeration(Category::Table, Category::iter().skip(1)),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(IssueLabel::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(IssueLabel::IssueId).integer().not_null())
+                    .col(ColumnDef::new(IssueLabel::Label).string().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(IssueLabel::IssueId)
+                            .col(IssueLabel::Label),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("issue")
+                            .from(IssueLabel::Table, IssueLabel::IssueId)
+                            .to(Issue::Table, Issue::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("issue_label_label")
+                    .table(IssueLabel::Table)
+                    .col(IssueLabel::Label)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(IssueLabel::Table).to_owned())
+            .await?;
+        for col in [Issue::Severity, Issue::Category] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Issue::Table)
+                        .drop_column(col)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Issue {
+    Table,
+    Id,
+    Severity,
+    Category,
+}
+
+#[derive(DeriveIden)]
+enum IssueLabel {
+    Table,
+    IssueId,
+    Label,
+}
+
+#[derive(Iden, EnumIter)]
+enum Severity {
+    Table,
+    Info,
+    Minor,
+    Major,
+    Critical,
+}
+
+#[derive(Iden, EnumIter)]
+enum Category {
+    Table,
+    Hardware,
+    Software,
+    Network,
+    SchedulerDetected,
+}
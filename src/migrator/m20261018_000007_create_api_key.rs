@@ -0,0 +1,78 @@
+use sea_orm::{EnumIter, Iterable};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiKey::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ApiKey::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ApiKey::Name)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ApiKey::KeyHash)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ApiKey::Role)
+                            .enumeration(Role::Table, Role::iter().skip(1))
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ApiKey::NodePrefixes).string())
+                    .col(ColumnDef::new(ApiKey::CreatedBy).string().not_null())
+                    .col(
+                        ColumnDef::new(ApiKey::CreatedAt)
+                            .date_time()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ApiKey::ExpiresAt).date_time())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ApiKey::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ApiKey {
+    Table,
+    Id,
+    Name,
+    KeyHash,
+    Role,
+    NodePrefixes,
+    CreatedBy,
+    CreatedAt,
+    ExpiresAt,
+}
+
+#[derive(Iden, EnumIter)]
+enum Role {
+    Table,
+    Admin,
+    Guest,
+}
@@ -0,0 +1,125 @@
+use sea_orm::{EnumIter, Iterable};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// the audit log is append-only, rows can only be removed by dropping the table
+const UP: &str = "
+CREATE TRIGGER audit_event_no_update BEFORE UPDATE ON audit_event BEGIN
+    SELECT RAISE(ABORT, 'audit_event is append-only');
+END;
+
+CREATE TRIGGER audit_event_no_delete BEFORE DELETE ON audit_event BEGIN
+    SELECT RAISE(ABORT, 'audit_event is append-only');
+END;
+";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditEvent::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditEvent::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AuditEvent::At)
+                            .date_time()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AuditEvent::Actor).string().not_null())
+                    .col(ColumnDef::new(AuditEvent::Method).string().not_null())
+                    .col(ColumnDef::new(AuditEvent::SourceIp).string())
+                    .col(
+                        ColumnDef::new(AuditEvent::Action)
+                            .enumeration(AuditAction::Table, AuditAction::iter().skip(1))
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AuditEvent::Entity).string().not_null())
+                    .col(ColumnDef::new(AuditEvent::EntityId).string().not_null())
+                    .col(ColumnDef::new(AuditEvent::Before).json())
+                    .col(ColumnDef::new(AuditEvent::After).json())
+                    .col(
+                        ColumnDef::new(AuditEvent::CorrelationId)
+                            .string()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("audit_event_at")
+                    .table(AuditEvent::Table)
+                    .col(AuditEvent::At)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("audit_event_correlation_id")
+                    .table(AuditEvent::Table)
+                    .col(AuditEvent::CorrelationId)
+                    .to_owned(),
+            )
+            .await?;
+        manager.get_connection().execute_unprepared(UP).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditEvent::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AuditEvent {
+    Table,
+    Id,
+    At,
+    Actor,
+    Method,
+    SourceIp,
+    Action,
+    Entity,
+    EntityId,
+    Before,
+    After,
+    CorrelationId,
+}
+
+#[derive(Iden, EnumIter)]
+enum AuditAction {
+    Table,
+    Open,
+    Update,
+    Close,
+    Reopen,
+    Comment,
+    EditComment,
+    DeleteComment,
+    AcknowledgeAlert,
+    CreateApiKey,
+    DeleteApiKey,
+    Login,
+    LoginFailed,
+    Offline,
+    Resume,
+    AutoOpen,
+    AutoReopen,
+    AutoClose,
+    RaiseAlert,
+}
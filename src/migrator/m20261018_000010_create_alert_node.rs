@@ -0,0 +1,83 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::ConnectionTrait;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AlertNode::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(AlertNode::AlertId).integer().not_null())
+                    .col(ColumnDef::new(AlertNode::Node).string().not_null())
+                    .primary_key(Index::create().col(AlertNode::AlertId).col(AlertNode::Node))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("alert")
+                            .from(AlertNode::Table, AlertNode::AlertId)
+                            .to(Alert::Table, Alert::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // alerts that haven't let a sync pass through yet still need their nodes, which used to
+        // live in the description as a hostlist
+        let db = manager.get_connection();
+        let pending = db
+            .query_all(
+                db.get_database_backend().build(
+                    Query::select()
+                        .columns([Alert::Id, Alert::Description])
+                        .from(Alert::Table)
+                        .and_where(Expr::col(Alert::ResumedAt).is_null()),
+                ),
+            )
+            .await?;
+        for row in pending {
+            let id: i32 = row.try_get("", "id")?;
+            let description: String = row.try_get("", "description")?;
+            let nodes = crate::hostlist::expand(&description)
+                .map_err(|e| DbErr::Migration(format!("alert {id}: {e}")))?;
+            if nodes.is_empty() {
+                continue;
+            }
+            let mut insert = Query::insert();
+            insert
+                .into_table(AlertNode::Table)
+                .columns([AlertNode::AlertId, AlertNode::Node]);
+            for n in nodes {
+                insert.values_panic([id.into(), n.into()]);
+            }
+            manager.exec_stmt(insert).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AlertNode::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AlertNode {
+    Table,
+    AlertId,
+    Node,
+}
+
+#[derive(DeriveIden)]
+enum Alert {
+    Table,
+    Id,
+    Description,
+    ResumedAt,
+}
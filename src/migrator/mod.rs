@@ -1,12 +1,34 @@
 use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20261018_000001_create_alert;
+mod m20261018_000002_create_issue_fts;
+mod m20261018_000003_comment_kind_and_history;
+mod m20261018_000004_issue_reopen_count;
+mod m20261018_000005_create_issue_target;
+mod m20261018_000006_issue_severity_category_labels;
+mod m20261018_000007_create_api_key;
+mod m20261018_000008_create_audit_event;
+mod m20261018_000009_audit_event_client_request_id;
+mod m20261018_000010_create_alert_node;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20261018_000001_create_alert::Migration),
+            Box::new(m20261018_000002_create_issue_fts::Migration),
+            Box::new(m20261018_000003_comment_kind_and_history::Migration),
+            Box::new(m20261018_000004_issue_reopen_count::Migration),
+            Box::new(m20261018_000005_create_issue_target::Migration),
+            Box::new(m20261018_000006_issue_severity_category_labels::Migration),
+            Box::new(m20261018_000007_create_api_key::Migration),
+            Box::new(m20261018_000008_create_audit_event::Migration),
+            Box::new(m20261018_000009_audit_event_client_request_id::Migration),
+            Box::new(m20261018_000010_create_alert_node::Migration),
+        ]
     }
 }
@@ -1,7 +1,44 @@
-use async_graphql::{EmptySubscription, Schema};
+use async_graphql::Schema;
 pub mod mutation;
 mod query;
+mod search;
+mod subscription;
+mod summary;
 pub use mutation::{Mutation, NewIssue};
 pub use query::Query;
+pub use subscription::Subscription;
 
-pub type CttSchema = Schema<query::Query, mutation::Mutation, EmptySubscription>;
+pub type CttSchema = Schema<query::Query, mutation::Mutation, subscription::Subscription>;
+
+/// schema with everything resolvers need from the context, except the Conf
+#[cfg(test)]
+pub fn test_schema(
+    db: sea_orm::DatabaseConnection,
+    cluster: crate::cluster::RegexCluster,
+) -> CttSchema {
+    let (tx, _) = tokio::sync::mpsc::channel::<crate::ChangeLogMsg>(100);
+    let (events, _) = tokio::sync::broadcast::channel::<crate::ChangeLogMsg>(100);
+    Schema::build(Query, Mutation, Subscription)
+        .data(std::sync::Arc::new(db))
+        .data(std::sync::Arc::new(cluster))
+        .data(tx)
+        .data(events)
+        .data(std::sync::Arc::new(crate::sync::SyncStatus::default()))
+        .finish()
+}
+
+/// run a request as `user` with the given role
+#[cfg(test)]
+pub async fn test_execute(
+    schema: &CttSchema,
+    role: crate::auth::Role,
+    user: &str,
+    request: impl Into<async_graphql::Request>,
+) -> async_graphql::Response {
+    let guard = crate::auth::RoleGuard::new(
+        role,
+        user.to_string(),
+        chrono::Utc::now().naive_utc() + chrono::Duration::minutes(60),
+    );
+    schema.execute(request.into().data(guard)).await
+}
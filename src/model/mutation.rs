@@ -1,17 +1,28 @@
-use crate::auth::{Role, RoleChecker, RoleGuard};
+use super::query::IssueFilter;
+use crate::audit::{self, Actor, RequestInfo};
+use crate::auth::{self, AuthMethod, Capability, CapabilityChecker, RoleGuard};
 use crate::cluster::{ClusterTrait, RegexCluster};
-use crate::conf::Conf;
+use crate::conf::{Conf, IssueTemplate};
+use crate::entities::alert;
+use crate::entities::api_key;
+use crate::entities::audit_event::AuditAction;
 use crate::entities::comment;
-use crate::entities::issue::{self, IssueStatus, ToOffline};
+use crate::entities::comment_edit;
+use crate::entities::issue::{self, Category, IssueStatus, Severity, ToOffline};
+use crate::entities::issue_label;
+use crate::entities::issue_target;
 use crate::entities::prelude::*;
 use crate::entities::target::TargetStatus;
+use crate::hostlist;
 use crate::ChangeLogMsg;
-use crate::PbsScheduler;
-use async_graphql::{Context, InputObject, Object, Result};
-use chrono::Utc;
+use async_graphql::{Context, InputObject, Object, Result, SimpleObject};
+use chrono::{NaiveDateTime, Utc};
 use sea_orm::entity::ActiveValue;
 use sea_orm::EntityTrait;
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, QueryFilter};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tracing::{info, instrument, warn};
@@ -24,18 +35,67 @@ pub struct UpdateIssue {
     to_offline: Option<issue::ToOffline>,
     id: i32,
     title: Option<String>,
+    severity: Option<Severity>,
+    category: Option<Category>,
+    /// replaces the issue's labels, an empty list removes them all
+    labels: Option<Vec<String>>,
 }
 
-#[derive(InputObject, Debug)]
+#[derive(InputObject, Clone, Debug)]
 pub struct NewIssue {
     assigned_to: Option<String>,
     description: String,
     to_offline: Option<issue::ToOffline>,
+    /// node or hostlist, e.g. `gu[0001-0004]`, the first node named is the issue's target
     target: String,
+    /// other nodes or hostlists the issue covers, e.g. everything behind a failed switch
+    targets: Option<Vec<String>>,
     title: String,
+    /// defaults to Minor
+    severity: Option<Severity>,
+    category: Option<Category>,
+    labels: Option<Vec<String>>,
 }
 
 impl NewIssue {
+    /// every node `target` and `targets` name, in order with duplicates removed
+    fn nodes(&self) -> Result<Vec<String>, String> {
+        let mut names = hostlist::expand(&self.target)?;
+        for t in self.targets.iter().flatten() {
+            names.extend(hostlist::expand(t)?);
+        }
+        dedup(&mut names);
+        Ok(names)
+    }
+    /// `nodes` plus the rest of their card or blade when `to_offline` takes those down too
+    fn affected_nodes(&self, cluster: &RegexCluster) -> Result<Vec<String>, String> {
+        let mut names = self.nodes()?;
+        for t in names.clone() {
+            names.extend(node_group(&t, self.to_offline, cluster));
+        }
+        dedup(&mut names);
+        Ok(names)
+    }
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = Some(severity);
+        self
+    }
+    pub fn with_category(mut self, category: Category) -> Self {
+        self.category = Some(category);
+        self
+    }
+    /// cover more nodes than `target`
+    #[cfg(test)]
+    pub fn with_targets(mut self, targets: Vec<String>) -> Self {
+        self.targets = Some(targets);
+        self
+    }
     #[instrument]
     pub fn new(
         assigned_to: Option<String>,
@@ -51,7 +111,11 @@ impl NewIssue {
                 description,
                 to_offline,
                 target,
+                targets: None,
                 title,
+                severity: None,
+                category: None,
+                labels: None,
             })
         } else {
             None
@@ -59,23 +123,132 @@ impl NewIssue {
     }
 }
 
+/// a value for a `{name}` placeholder in an issue template
+#[derive(InputObject, Clone, Debug)]
+pub struct TemplateVar {
+    name: String,
+    value: String,
+}
+
+impl NewIssue {
+    /// issue described by a template, `vars` fill its placeholders and `{target}` is `target`
+    fn from_template(
+        template: &IssueTemplate,
+        target: String,
+        vars: Vec<TemplateVar>,
+    ) -> Result<Self, String> {
+        let mut vars: Vec<(String, String)> = vars.into_iter().map(|v| (v.name, v.value)).collect();
+        vars.push(("target".to_string(), target.clone()));
+        let (title, description) = template.render(&vars)?;
+        Ok(Self {
+            assigned_to: template.assigned_to.clone(),
+            description,
+            to_offline: template.to_offline,
+            target,
+            targets: None,
+            title,
+            severity: Some(template.severity),
+            category: None,
+            labels: Some(template.labels.clone()),
+        })
+    }
+}
+
+/// the UpdateIssue fields applied to every issue matched by updateMany
+#[derive(InputObject, Clone, Debug)]
+pub struct IssuePatch {
+    assigned_to: Option<String>,
+    description: Option<String>,
+    enforce_down: Option<bool>,
+    to_offline: Option<issue::ToOffline>,
+    title: Option<String>,
+    severity: Option<Severity>,
+    category: Option<Category>,
+    labels: Option<Vec<String>>,
+}
+
+impl IssuePatch {
+    fn for_issue(&self, id: i32) -> UpdateIssue {
+        UpdateIssue {
+            assigned_to: self.assigned_to.clone(),
+            description: self.description.clone(),
+            enforce_down: self.enforce_down,
+            to_offline: self.to_offline,
+            id,
+            title: self.title.clone(),
+            severity: self.severity,
+            category: self.category,
+            labels: self.labels.clone(),
+        }
+    }
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct NewApiKey {
+    /// only shown here, ctt keeps just its hash
+    key: String,
+    api_key: api_key::Model,
+}
+
+/// what a bulk mutation did to one node or issue
+#[derive(SimpleObject, Debug)]
+pub struct BulkResult {
+    /// node the issue was opened on, or the targets of the closed or updated issue
+    target: String,
+    /// the issue after the change, None if it failed
+    issue: Option<issue::Model>,
+    error: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct Mutation;
 
-#[instrument(skip(ctx))]
+/// trimmed, sorted and deduplicated, labels can't be blank
+fn clean_labels(labels: &[String]) -> Result<Vec<String>, String> {
+    let labels: std::collections::BTreeSet<String> =
+        labels.iter().map(|l| l.trim().to_string()).collect();
+    if labels.contains("") {
+        return Err("labels can't be blank".to_string());
+    }
+    Ok(labels.into_iter().collect())
+}
+
+#[instrument(skip(db))]
+async fn set_labels(issue_id: i32, labels: &[String], db: &impl ConnectionTrait) {
+    IssueLabel::delete_many()
+        .filter(issue_label::Column::IssueId.eq(issue_id))
+        .exec(db)
+        .await
+        .unwrap();
+    IssueLabel::insert_many(labels.iter().map(|l| issue_label::ActiveModel {
+        issue_id: ActiveValue::Set(issue_id),
+        label: ActiveValue::Set(l.clone()),
+    }))
+    .on_empty_do_nothing()
+    .exec(db)
+    .await
+    .unwrap();
+}
+
+/// update an issue, returning it and the nodes it no longer holds down
+///
+/// the scheduler isn't touched so the update can be rolled back, the caller releases the nodes
+/// with [release_nodes] once it's committed
+#[instrument(skip(db, tx, cluster))]
 async fn issue_update(
-    mut i: UpdateIssue,
+    i: UpdateIssue,
     operator: &str,
-    ctx: &Context<'_>,
-) -> Result<issue::Model, String> {
-    let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
-    let conf = ctx.data::<Conf>().unwrap();
-    let tx = &ctx.data_opt::<mpsc::Sender<ChangeLogMsg>>().unwrap();
+    db: &impl ConnectionTrait,
+    tx: &mpsc::Sender<ChangeLogMsg>,
+    cluster: &RegexCluster,
+) -> Result<(issue::Model, Vec<String>), String> {
     let issue = Issue::find_by_id(i.id).one(db).await.unwrap();
     if issue.is_none() {
         return Err(format!("Issue {} not found", i.id));
     }
     let issue = issue.unwrap();
+    // everything that can be rejected is checked before anything is written
+    let labels = i.labels.as_deref().map(clean_labels).transpose()?;
     let mut updated_issue: issue::ActiveModel = issue.clone().into();
     if let Some(s) = &i.assigned_to
         && i.assigned_to != issue.assigned_to
@@ -85,62 +258,89 @@ async fn issue_update(
         } else {
             updated_issue.assigned_to = ActiveValue::Set(i.assigned_to.clone());
         }
-        let c = comment::ActiveModel {
-            created_by: ActiveValue::Set(operator.to_string()),
-            comment: ActiveValue::Set(format!(
+        let c = comment::ActiveModel::system(
+            issue.id,
+            operator,
+            format!(
                 "Updating assigned_to from {:?} to {:?}",
                 issue.assigned_to,
                 updated_issue.assigned_to.clone().unwrap()
-            )),
-            issue_id: ActiveValue::Set(issue.id),
-            ..Default::default()
-        };
+            ),
+        );
         c.insert(db).await.unwrap();
     }
     if let Some(d) = i.description.clone()
         && d != issue.description
     {
         updated_issue.description = ActiveValue::Set(d.clone());
-        let c = comment::ActiveModel {
-            created_by: ActiveValue::Set(operator.to_string()),
-            comment: ActiveValue::Set(format!(
+        let c = comment::ActiveModel::system(
+            issue.id,
+            operator,
+            format!(
                 "Updating description from {:?} to {:?}",
                 issue.description, d
-            )),
-            issue_id: ActiveValue::Set(issue.id),
-            ..Default::default()
-        };
+            ),
+        );
         c.insert(db).await.unwrap();
     }
     if let Some(t) = i.title.clone()
         && t != issue.title
     {
         updated_issue.title = ActiveValue::Set(t.to_string());
-        let c = comment::ActiveModel {
-            created_by: ActiveValue::Set(operator.to_string()),
-            comment: ActiveValue::Set(format!("Updating title from {:?} to {:?}", issue.title, t)),
-            issue_id: ActiveValue::Set(issue.id),
-            ..Default::default()
-        };
+        let c = comment::ActiveModel::system(
+            issue.id,
+            operator,
+            format!("Updating title from {:?} to {:?}", issue.title, t),
+        );
+        c.insert(db).await.unwrap();
+    }
+    if let Some(sev) = i.severity
+        && sev != issue.severity
+    {
+        updated_issue.severity = ActiveValue::Set(sev);
+        let c = comment::ActiveModel::system(
+            issue.id,
+            operator,
+            format!("Updating severity from {:?} to {:?}", issue.severity, sev),
+        );
         c.insert(db).await.unwrap();
     }
-    if issue.to_offline.is_none() && i.to_offline.is_none() {
-        i.to_offline = Some(ToOffline::Node);
+    if let Some(cat) = i.category
+        && Some(cat) != issue.category
+    {
+        updated_issue.category = ActiveValue::Set(Some(cat));
+        let c = comment::ActiveModel::system(
+            issue.id,
+            operator,
+            format!("Updating category from {:?} to {:?}", issue.category, cat),
+        );
+        c.insert(db).await.unwrap();
+    }
+    if let Some(labels) = labels {
+        let old = issue.get_labels(db).await;
+        if labels != old {
+            set_labels(issue.id, &labels, db).await;
+            let c = comment::ActiveModel::system(
+                issue.id,
+                operator,
+                format!("Updating labels from {:?} to {:?}", old, labels),
+            );
+            c.insert(db).await.unwrap();
+        }
     }
     if let Some(_) = i.to_offline
         && i.to_offline != issue.to_offline
     {
         info!("updating to_offline");
         updated_issue.to_offline = ActiveValue::Set(i.to_offline);
-        let c = comment::ActiveModel {
-            created_by: ActiveValue::Set(operator.to_string()),
-            comment: ActiveValue::Set(format!(
+        let c = comment::ActiveModel::system(
+            issue.id,
+            operator,
+            format!(
                 "Updating to_offline from {:?} to {:?}",
                 issue.to_offline, i.to_offline
-            )),
-            issue_id: ActiveValue::Set(issue.id),
-            ..Default::default()
-        };
+            ),
+        );
         c.insert(db).await.unwrap();
     }
     info!("Updating issue {}: {:?}", issue.id, updated_issue);
@@ -160,65 +360,65 @@ async fn issue_update(
     //sync code doesn't know a node was offline due to being a sibling, so it will
     //open a new ticket for the sibling instead of resuming it
     //resuming nodes here for now instead of the sync loop since its easier
+    let mut release = vec![];
     if let Some(t_o) = issue.to_offline
         && i.to_offline.is_some()
         && i.to_offline != issue.to_offline
     {
-        let mut cluster = RegexCluster::new(conf.node_types.clone(), PbsScheduler::new());
-
-        let target = issue.get_target(db).await.unwrap().name;
-        let cousins = cluster.cousins(&target);
-        let siblings = cluster.siblings(&target);
-
-        //t_o != i.to_offline therefore issue no longer enforces cousins being down
-        if t_o == issue::ToOffline::Blade {
-            //resume cousins (but not siblings)
-            for c in cousins {
-                if c == target || siblings.contains(&c) {
-                    continue;
-                }
-                let (desired_node_state, _) = crate::sync::desired_state(&c, db, &cluster).await;
-                if desired_node_state == TargetStatus::Online {
-                    //TODO add changelog msg
-                    if cluster.release_node(&c).is_err() {
-                        warn!("Error releasing node {}", c);
-                    } else {
-                        let _ = tx
-                            .send(ChangeLogMsg::Resume {
-                                target: c.to_string(),
-                                operator: operator.to_string(),
-                            })
-                            .await;
+        for target in issue.get_targets(db).await.into_iter().map(|t| t.name) {
+            let cousins = cluster.cousins(&target);
+            let siblings = cluster.siblings(&target);
+
+            //t_o != i.to_offline therefore issue no longer enforces cousins being down
+            if t_o == issue::ToOffline::Blade {
+                //resume cousins (but not siblings)
+                for c in cousins {
+                    if c == target || siblings.contains(&c) {
+                        continue;
+                    }
+                    let (desired_node_state, _) = crate::sync::desired_state(&c, db, cluster).await;
+                    if desired_node_state == TargetStatus::Online {
+                        release.push(c);
                     }
                 }
             }
-        }
 
-        //t_o is something, and != i.to_offline, so issue no longer enforces sibling being down
-        if i.to_offline.unwrap() == issue::ToOffline::Node {
-            for s in siblings {
-                if s == target {
-                    continue;
-                }
-                let (desired_node_state, _) = crate::sync::desired_state(&s, db, &cluster).await;
-                if desired_node_state == TargetStatus::Online {
-                    //TODO add changelog msg
-                    cluster.release_node(&s).unwrap();
-                    if cluster.release_node(&s).is_err() {
-                        warn!("Error releasing node {}", s);
-                    } else {
-                        let _ = tx
-                            .send(ChangeLogMsg::Resume {
-                                target: s.to_string(),
-                                operator: operator.to_string(),
-                            })
-                            .await;
+            //t_o is something, and != i.to_offline, so issue no longer enforces sibling being down
+            if i.to_offline.unwrap() == issue::ToOffline::Node {
+                for s in siblings {
+                    if s == target {
+                        continue;
+                    }
+                    let (desired_node_state, _) = crate::sync::desired_state(&s, db, cluster).await;
+                    if desired_node_state == TargetStatus::Online {
+                        release.push(s);
                     }
                 }
             }
         }
     }
-    Ok(Issue::find_by_id(i.id).one(db).await.unwrap().unwrap())
+    dedup(&mut release);
+    Ok((
+        Issue::find_by_id(i.id).one(db).await.unwrap().unwrap(),
+        release,
+    ))
+}
+
+/// resume nodes in the scheduler, returning a Resume message for each one released
+#[instrument(skip(cluster))]
+fn release_nodes(nodes: Vec<String>, operator: &str, cluster: &RegexCluster) -> Vec<ChangeLogMsg> {
+    let mut resumed = vec![];
+    for n in nodes {
+        if cluster.release_node(&n).is_err() {
+            warn!("Error releasing node {}", n);
+        } else {
+            resumed.push(ChangeLogMsg::Resume {
+                target: n,
+                operator: operator.to_string(),
+            });
+        }
+    }
+    resumed
 }
 
 #[instrument]
@@ -237,6 +437,7 @@ fn node_group(
     }
 }
 
+#[cfg(feature = "pbs")]
 #[instrument(skip(status))]
 fn to_offline(
     target: &str,
@@ -259,23 +460,32 @@ fn to_offline(
         .map(|n| n.name())
         .collect()
 }
-#[instrument]
+#[instrument(skip(db, tx, cluster))]
 pub async fn issue_open(
     i: &NewIssue,
     operator: &str,
-    db: &DatabaseConnection,
+    db: &impl ConnectionTrait,
     tx: &mpsc::Sender<ChangeLogMsg>,
     cluster: &RegexCluster,
 ) -> Result<issue::Model, String> {
-    if !cluster.real_node(&i.target) {
-        return Err(format!("{} is not a real node", &i.target));
-    }
-    let target = if let Some(t) = Target::from_name(&i.target, db, cluster).await {
-        t
-    } else {
-        warn!("Target {} not found", i.target);
-        return Err(format!("Node {} does not exist", i.target));
-    };
+    // target and targets are hostlists, the first node named is the issue's target
+    let names = i.nodes()?;
+    if let Some(t) = names.iter().find(|t| !cluster.real_node(t)) {
+        return Err(format!("{} is not a real node", t));
+    }
+    let labels = clean_labels(i.labels.as_deref().unwrap_or_default())?;
+    let mut targets = vec![];
+    for name in &names {
+        match Target::from_name(name, db, cluster).await {
+            Some(t) => targets.push(t),
+            None => {
+                warn!("Target {} not found", name);
+                return Err(format!("Node {} does not exist", name));
+            }
+        }
+    }
+    let extra_targets = targets.split_off(1);
+    let target = targets.remove(0);
     if let Some(i) = target
         .issues()
         .filter(issue::Column::Status.eq(IssueStatus::Open))
@@ -296,9 +506,22 @@ pub async fn issue_open(
         status: ActiveValue::Set(IssueStatus::Opening),
         target_id: ActiveValue::Set(target_id),
         title: ActiveValue::Set(i.title.clone()),
+        severity: ActiveValue::Set(i.severity.unwrap_or_default()),
+        category: ActiveValue::Set(i.category),
         ..Default::default()
     };
     let new_issue = new_issue.insert(db).await.unwrap();
+    set_labels(new_issue.id, &labels, db).await;
+    if !extra_targets.is_empty() {
+        IssueTarget::insert_many(extra_targets.iter().map(|t| issue_target::ActiveModel {
+            issue_id: ActiveValue::Set(new_issue.id),
+            target_id: ActiveValue::Set(t.id),
+        }))
+        .on_empty_do_nothing()
+        .exec(db)
+        .await
+        .unwrap();
+    }
     let _ = tx
         .send(ChangeLogMsg::Open {
             title: i.title.clone(),
@@ -306,25 +529,22 @@ pub async fn issue_open(
             operator: operator.to_string(),
         })
         .await;
-    let c = comment::ActiveModel {
-        created_by: ActiveValue::Set(operator.to_string()),
-        comment: ActiveValue::Set("Opening issue".to_string()),
-        issue_id: ActiveValue::Set(new_issue.id),
-        ..Default::default()
-    };
+    let c = comment::ActiveModel::system(new_issue.id, operator, "Opening issue".to_string());
     c.insert(db).await.unwrap();
     Ok(new_issue)
 }
 
-#[instrument(skip(ctx))]
+#[instrument(skip(db, tx))]
 async fn issue_close(
     cttissue: i32,
-    operator: String,
+    operator: &str,
     comment: String,
-    ctx: &Context<'_>,
+    db: &impl ConnectionTrait,
+    tx: &mpsc::Sender<ChangeLogMsg>,
 ) -> Result<String, String> {
-    let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
-    let issue = Issue::find_by_id(cttissue).one(db).await.unwrap().unwrap();
+    let Some(issue) = Issue::find_by_id(cttissue).one(db).await.unwrap() else {
+        return Err(format!("Issue {} not found", cttissue));
+    };
     let target = issue.get_target(db).await.unwrap();
     if issue.status == IssueStatus::Open || issue.status == IssueStatus::Opening {
         info!(
@@ -334,19 +554,14 @@ async fn issue_close(
         let title = issue.title.clone();
         let mut issue: issue::ActiveModel = issue.into();
         issue.status = ActiveValue::Set(IssueStatus::Closing);
+        issue.updated_at = ActiveValue::Set(Utc::now().naive_utc());
         issue.update(db).await.unwrap();
-        let c = comment::ActiveModel {
-            created_by: ActiveValue::Set(operator.clone()),
-            comment: ActiveValue::Set(comment.clone()),
-            issue_id: ActiveValue::Set(cttissue),
-            ..Default::default()
-        };
+        let c = comment::ActiveModel::note(cttissue, operator, comment.clone());
         c.insert(db).await.unwrap();
-        let tx = &ctx.data_opt::<mpsc::Sender<ChangeLogMsg>>().unwrap();
         let _ = tx
             .send(ChangeLogMsg::Close {
                 issue: cttissue,
-                operator: operator.clone(),
+                operator: operator.to_string(),
                 comment,
                 title,
             })
@@ -355,19 +570,328 @@ async fn issue_close(
     Ok(format!("closed {}", cttissue))
 }
 
+/// remove repeated names, keeping the first of each
+fn dedup(names: &mut Vec<String>) {
+    let mut seen = std::collections::BTreeSet::new();
+    names.retain(|n| seen.insert(n.clone()));
+}
+
+/// every node a change to issue `id` could offline or resume: its targets, the rest of their
+/// card or blade under the issue's current `to_offline`, and under `new_to_offline` if it's
+/// being changed
+#[instrument(skip(db, cluster))]
+async fn issue_nodes(
+    id: i32,
+    new_to_offline: Option<ToOffline>,
+    db: &impl ConnectionTrait,
+    cluster: &RegexCluster,
+) -> Result<Vec<String>, String> {
+    let Some(i) = Issue::find_by_id(id).one(db).await.unwrap() else {
+        return Err(format!("Issue {} not found", id));
+    };
+    let targets: Vec<String> = i
+        .get_targets(db)
+        .await
+        .into_iter()
+        .map(|t| t.name)
+        .collect();
+    let mut names = targets.clone();
+    for t in &targets {
+        names.extend(node_group(t, i.to_offline, cluster));
+        names.extend(node_group(t, new_to_offline, cluster));
+    }
+    dedup(&mut names);
+    Ok(names)
+}
+
+/// one change to an issue, made alone or as part of a bulk mutation
+#[derive(Debug)]
+enum IssueOp {
+    Open(NewIssue),
+    Close { id: i32, comment: String },
+    Update(UpdateIssue),
+    Reopen { id: i32, comment: String },
+}
+
+impl IssueOp {
+    async fn nodes(
+        &self,
+        db: &impl ConnectionTrait,
+        cluster: &RegexCluster,
+    ) -> Result<Vec<String>, String> {
+        match self {
+            IssueOp::Open(i) => i.affected_nodes(cluster),
+            IssueOp::Close { id, .. } | IssueOp::Reopen { id, .. } => {
+                issue_nodes(*id, None, db, cluster).await
+            }
+            IssueOp::Update(i) => issue_nodes(i.id, i.to_offline, db, cluster).await,
+        }
+    }
+
+    /// [IssueOp::stage] the change on its own, then release any nodes it freed
+    async fn apply(
+        self,
+        user: &RoleGuard,
+        actor: &Actor,
+        db: &impl ConnectionTrait,
+        tx: &mpsc::Sender<ChangeLogMsg>,
+        cluster: &RegexCluster,
+    ) -> Result<issue::Model, String> {
+        let (issue, release) = self.stage(user, actor, db, tx, cluster).await?;
+        for m in release_nodes(release, &user.user, cluster) {
+            let _ = tx.send(m).await;
+        }
+        Ok(issue)
+    }
+
+    /// check `user` may make the change, make it, and record it in the audit log
+    ///
+    /// returns the nodes to release in the scheduler once the change is committed
+    async fn stage(
+        self,
+        user: &RoleGuard,
+        actor: &Actor,
+        db: &impl ConnectionTrait,
+        tx: &mpsc::Sender<ChangeLogMsg>,
+        cluster: &RegexCluster,
+    ) -> Result<(issue::Model, Vec<String>), String> {
+        let (action, before) = match &self {
+            IssueOp::Open(_) => (AuditAction::Open, None),
+            IssueOp::Close { id, .. } => (AuditAction::Close, Some(*id)),
+            IssueOp::Update(i) => (AuditAction::Update, Some(i.id)),
+            IssueOp::Reopen { id, .. } => (AuditAction::Reopen, Some(*id)),
+        };
+        let before = match before {
+            Some(id) => Issue::find_by_id(id).one(db).await.unwrap(),
+            None => None,
+        };
+        user.check_to_offline(match &self {
+            IssueOp::Open(i) => i.to_offline,
+            IssueOp::Update(i) => i.to_offline,
+            // reopening takes the issue's nodes down again
+            IssueOp::Reopen { .. } => before.as_ref().and_then(|b| b.to_offline),
+            IssueOp::Close { .. } => None,
+        })?;
+        user.check_nodes(&self.nodes(db, cluster).await?)?;
+        let operator = user.user.as_str();
+        let mut release = vec![];
+        let after = match self {
+            IssueOp::Open(i) => issue_open(&i, operator, db, tx, cluster).await,
+            IssueOp::Close { id, comment } => {
+                issue_close(id, operator, comment, db, tx).await?;
+                Ok(Issue::find_by_id(id).one(db).await.unwrap().unwrap())
+            }
+            IssueOp::Update(i) => {
+                issue_update(i, operator, db, tx, cluster)
+                    .await
+                    .map(|(issue, r)| {
+                        release = r;
+                        issue
+                    })
+            }
+            IssueOp::Reopen { id, comment } => issue_reopen(id, operator, comment, db, tx).await,
+        }?;
+        let changed = match &before {
+            Some(b) => *b != after,
+            // issue_open hands back the Open issue with the same title instead of a new one
+            None => after.status == IssueStatus::Opening,
+        };
+        if changed {
+            audit::record(
+                db,
+                actor,
+                action,
+                "issue",
+                after.id,
+                before.as_ref().and_then(audit::json),
+                audit::json(&after),
+            )
+            .await;
+        }
+        Ok((after, release))
+    }
+}
+
+/// apply every op in one transaction, each in a savepoint so a failure only undoes that op.
+/// nodes freed by ops that succeeded are released after commit, and their changelog messages
+/// go out as one Batch
+#[instrument(skip(ops, db, tx, cluster))]
+async fn bulk(
+    ops: Vec<(String, IssueOp)>,
+    user: &RoleGuard,
+    actor: &Actor,
+    db: &DatabaseConnection,
+    tx: &mpsc::Sender<ChangeLogMsg>,
+    cluster: &RegexCluster,
+) -> Result<Vec<BulkResult>, String> {
+    let txn = db.begin().await.map_err(|e| e.to_string())?;
+    // drained after every op, one op sends at most a message per node on a blade
+    let (item_tx, mut item_rx) = mpsc::channel(1024);
+    let mut messages = vec![];
+    let mut release = vec![];
+    let mut results = Vec::with_capacity(ops.len());
+    for (target, op) in ops {
+        let savepoint = txn.begin().await.map_err(|e| e.to_string())?;
+        let res = op.stage(user, actor, &savepoint, &item_tx, cluster).await;
+        let mut sent = vec![];
+        while let Ok(m) = item_rx.try_recv() {
+            sent.push(m);
+        }
+        match res {
+            Ok((i, mut r)) => {
+                savepoint.commit().await.map_err(|e| e.to_string())?;
+                messages.append(&mut sent);
+                release.append(&mut r);
+                results.push(BulkResult {
+                    target,
+                    issue: Some(i),
+                    error: None,
+                });
+            }
+            Err(e) => {
+                warn!("bulk change to {} failed: {}", target, e);
+                savepoint.rollback().await.map_err(|e| e.to_string())?;
+                results.push(BulkResult {
+                    target,
+                    issue: None,
+                    error: Some(e),
+                });
+            }
+        }
+    }
+    txn.commit().await.map_err(|e| e.to_string())?;
+    dedup(&mut release);
+    messages.extend(release_nodes(release, &user.user, cluster));
+    if !messages.is_empty() {
+        let _ = tx
+            .send(ChangeLogMsg::Batch {
+                operator: user.user.clone(),
+                messages,
+            })
+            .await;
+    }
+    Ok(results)
+}
+
+/// issues matched by a bulk mutation's filter, labelled with their targets
+#[instrument(skip(db))]
+async fn bulk_issues(
+    filter: &IssueFilter,
+    db: &DatabaseConnection,
+) -> Result<Vec<(String, issue::Model)>, String> {
+    if filter.matches_everything() {
+        return Err("filter matches every open issue, set at least one field".to_string());
+    }
+    let issues = filter
+        .apply(Issue::find(), db)
+        .await?
+        .order_by_asc(issue::Column::Id)
+        .all(db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut labelled = Vec::with_capacity(issues.len());
+    for i in issues {
+        let targets = i.get_targets(db).await.into_iter().map(|t| t.name);
+        labelled.push((hostlist::compress(targets), i));
+    }
+    Ok(labelled)
+}
+
+/// move a Closed (or not yet Closed) issue back to Opening so sync enforces it again
+#[instrument(skip(db, tx))]
+pub async fn issue_reopen(
+    id: i32,
+    operator: &str,
+    comment: String,
+    db: &impl ConnectionTrait,
+    tx: &mpsc::Sender<ChangeLogMsg>,
+) -> Result<issue::Model, String> {
+    let issue = match Issue::find_by_id(id).one(db).await.unwrap() {
+        None => return Err(format!("Issue {} not found", id)),
+        Some(i) if !matches!(i.status, IssueStatus::Closed | IssueStatus::Closing) => {
+            return Err(format!("Issue {} is not closed", id))
+        }
+        Some(i) => i,
+    };
+    info!("Reopening issue {}: {}", id, comment);
+    let title = issue.title.clone();
+    let reopen_count = issue.reopen_count + 1;
+    let mut issue: issue::ActiveModel = issue.into();
+    issue.status = ActiveValue::Set(IssueStatus::Opening);
+    issue.reopen_count = ActiveValue::Set(reopen_count);
+    issue.updated_at = ActiveValue::Set(Utc::now().naive_utc());
+    let issue = issue.update(db).await.unwrap();
+    comment::ActiveModel::note(id, operator, comment.clone())
+        .insert(db)
+        .await
+        .unwrap();
+    let _ = tx
+        .send(ChangeLogMsg::Reopen {
+            issue: id,
+            title,
+            comment,
+            operator: operator.to_string(),
+        })
+        .await;
+    Ok(issue)
+}
+
+/// a note written by `operator` that hasn't been deleted, system comments can't be changed
+#[instrument(skip(db))]
+async fn own_note(
+    id: i32,
+    operator: &str,
+    db: &DatabaseConnection,
+) -> Result<comment::Model, String> {
+    match Comment::find_by_id(id).one(db).await.unwrap() {
+        Some(c) if c.deleted_at.is_some() => Err(format!("Comment {} not found", id)),
+        Some(c) if c.kind != comment::CommentKind::Note => Err(format!(
+            "Comment {} was written by ctt and can't be changed",
+            id
+        )),
+        Some(c) if c.created_by != operator => {
+            Err(format!("Comment {} belongs to {}", id, c.created_by))
+        }
+        Some(c) => Ok(c),
+        None => Err(format!("Comment {} not found", id)),
+    }
+}
+
+#[instrument(skip(db))]
+async fn record_edit(c: &comment::Model, operator: &str, db: &DatabaseConnection) {
+    comment_edit::ActiveModel {
+        comment_id: ActiveValue::Set(c.id),
+        comment: ActiveValue::Set(c.comment.clone()),
+        edited_by: ActiveValue::Set(operator.to_string()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .unwrap();
+}
+
+/// the caller, as recorded in the audit log
+fn actor(ctx: &Context<'_>) -> Actor {
+    Actor::new(
+        ctx.data_opt::<RoleGuard>().unwrap(),
+        ctx.data_opt::<RequestInfo>(),
+    )
+}
+
 #[Object]
 impl Mutation {
-    #[graphql(guard = "RoleChecker::new(Role::Admin)")]
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueOpen)")]
     #[instrument(skip(ctx))]
     async fn open<'a>(&self, ctx: &Context<'a>, issue: NewIssue) -> Result<issue::Model, String> {
-        let usr = &ctx.data_opt::<RoleGuard>().unwrap().user;
+        let user = ctx.data_opt::<RoleGuard>().unwrap();
         let tx = ctx.data_opt::<mpsc::Sender<ChangeLogMsg>>().unwrap();
         let db = ctx.data_opt::<Arc<DatabaseConnection>>().unwrap().as_ref();
-        let conf = ctx.data::<Conf>().unwrap();
-        let cluster = RegexCluster::new(conf.node_types.clone(), PbsScheduler::new());
-        issue_open(&issue, usr, db, tx, &cluster).await
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        IssueOp::Open(issue)
+            .apply(user, &actor(ctx), db, tx, cluster)
+            .await
     }
-    #[graphql(guard = "RoleChecker::new(Role::Admin)")]
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueClose)")]
     #[instrument(skip(ctx))]
     async fn close<'a>(
         &self,
@@ -375,19 +899,821 @@ impl Mutation {
         issue: i32,
         comment: String,
     ) -> Result<String, String> {
-        let usr: String = ctx.data_opt::<RoleGuard>().unwrap().user.clone();
-
-        issue_close(issue, usr, comment, ctx).await
+        let user = ctx.data_opt::<RoleGuard>().unwrap();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let tx = ctx.data_opt::<mpsc::Sender<ChangeLogMsg>>().unwrap();
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        IssueOp::Close { id: issue, comment }
+            .apply(user, &actor(ctx), db, tx, cluster)
+            .await?;
+        Ok(format!("closed {}", issue))
     }
-    #[graphql(guard = "RoleChecker::new(Role::Admin)")]
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueOpen)")]
     #[instrument(skip(ctx))]
     async fn update_issue<'a>(
         &self,
         ctx: &Context<'a>,
-        issue: UpdateIssue,
+        mut issue: UpdateIssue,
+    ) -> Result<issue::Model, String> {
+        let user = ctx.data_opt::<RoleGuard>().unwrap();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let tx = ctx.data_opt::<mpsc::Sender<ChangeLogMsg>>().unwrap();
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        // editing an issue that never set to_offline pins it to its node, updateMany patches
+        // leave it alone
+        if issue.to_offline.is_none()
+            && Issue::find_by_id(issue.id)
+                .one(db)
+                .await
+                .unwrap()
+                .is_some_and(|i| i.to_offline.is_none())
+        {
+            issue.to_offline = Some(ToOffline::Node);
+        }
+        IssueOp::Update(issue)
+            .apply(user, &actor(ctx), db, tx, cluster)
+            .await
+    }
+    /// open an issue from one of the templates in the config
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueOpen)")]
+    #[instrument(skip(ctx))]
+    async fn open_from_template<'a>(
+        &self,
+        ctx: &Context<'a>,
+        template: String,
+        target: String,
+        vars: Option<Vec<TemplateVar>>,
+    ) -> Result<issue::Model, String> {
+        let user = ctx.data_opt::<RoleGuard>().unwrap();
+        let tx = ctx.data_opt::<mpsc::Sender<ChangeLogMsg>>().unwrap();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        let conf = ctx.data::<Conf>().unwrap();
+        let Some(t) = conf.templates.iter().find(|t| t.name == template) else {
+            return Err(format!("no template named {}", template));
+        };
+        let issue = NewIssue::from_template(t, target, vars.unwrap_or_default())?;
+        IssueOp::Open(issue)
+            .apply(user, &actor(ctx), db, tx, cluster)
+            .await
+    }
+    /// open a copy of `issue` on every node `target` and `targets` name
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueOpen)")]
+    #[instrument(skip(ctx))]
+    async fn open_many<'a>(
+        &self,
+        ctx: &Context<'a>,
+        issue: NewIssue,
+    ) -> Result<Vec<BulkResult>, String> {
+        let user = ctx.data_opt::<RoleGuard>().unwrap();
+        let tx = ctx.data_opt::<mpsc::Sender<ChangeLogMsg>>().unwrap();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        let ops = issue
+            .nodes()?
+            .into_iter()
+            .map(|n| {
+                let i = NewIssue {
+                    target: n.clone(),
+                    targets: None,
+                    ..issue.clone()
+                };
+                (n, IssueOp::Open(i))
+            })
+            .collect();
+        bulk(ops, user, &actor(ctx), db, tx, cluster).await
+    }
+    /// close every issue matching `filter`
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueClose)")]
+    #[instrument(skip(ctx))]
+    async fn close_many<'a>(
+        &self,
+        ctx: &Context<'a>,
+        filter: IssueFilter,
+        comment: String,
+    ) -> Result<Vec<BulkResult>, String> {
+        let user = ctx.data_opt::<RoleGuard>().unwrap();
+        let tx = ctx.data_opt::<mpsc::Sender<ChangeLogMsg>>().unwrap();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        let ops = bulk_issues(&filter, db)
+            .await?
+            .into_iter()
+            .map(|(t, i)| {
+                let op = IssueOp::Close {
+                    id: i.id,
+                    comment: comment.clone(),
+                };
+                (t, op)
+            })
+            .collect();
+        bulk(ops, user, &actor(ctx), db, tx, cluster).await
+    }
+    /// apply `patch` to every issue matching `filter`
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueOpen)")]
+    #[instrument(skip(ctx))]
+    async fn update_many<'a>(
+        &self,
+        ctx: &Context<'a>,
+        filter: IssueFilter,
+        patch: IssuePatch,
+    ) -> Result<Vec<BulkResult>, String> {
+        let user = ctx.data_opt::<RoleGuard>().unwrap();
+        let tx = ctx.data_opt::<mpsc::Sender<ChangeLogMsg>>().unwrap();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        let ops = bulk_issues(&filter, db)
+            .await?
+            .into_iter()
+            .map(|(t, i)| (t, IssueOp::Update(patch.for_issue(i.id))))
+            .collect();
+        bulk(ops, user, &actor(ctx), db, tx, cluster).await
+    }
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueOpen)")]
+    #[instrument(skip(ctx))]
+    async fn reopen<'a>(
+        &self,
+        ctx: &Context<'a>,
+        issue: i32,
+        comment: String,
     ) -> Result<issue::Model, String> {
+        let user = ctx.data_opt::<RoleGuard>().unwrap();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let tx = ctx.data_opt::<mpsc::Sender<ChangeLogMsg>>().unwrap();
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        IssueOp::Reopen { id: issue, comment }
+            .apply(user, &actor(ctx), db, tx, cluster)
+            .await
+    }
+    /// leave a note on an issue without changing it
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueComment)")]
+    #[instrument(skip(ctx))]
+    async fn add_comment<'a>(
+        &self,
+        ctx: &Context<'a>,
+        issue: i32,
+        comment: String,
+    ) -> Result<comment::Model, String> {
+        let user = ctx.data_opt::<RoleGuard>().unwrap();
+        let usr = user.user.clone();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let Some(i) = Issue::find_by_id(issue).one(db).await.unwrap() else {
+            return Err(format!("Issue {} not found", issue));
+        };
+        let targets: Vec<String> = i
+            .get_targets(db)
+            .await
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        user.check_nodes(&targets)?;
+        info!("{} commented on issue {}", usr, issue);
+        let c = comment::ActiveModel::note(issue, &usr, comment)
+            .insert(db)
+            .await
+            .unwrap();
+        audit::record(
+            db,
+            &actor(ctx),
+            AuditAction::Comment,
+            "comment",
+            c.id,
+            None,
+            audit::json(&c),
+        )
+        .await;
+        Ok(c)
+    }
+    /// replace the text of one of your notes, the old text is kept in its history
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueComment)")]
+    #[instrument(skip(ctx))]
+    async fn edit_comment<'a>(
+        &self,
+        ctx: &Context<'a>,
+        comment: i32,
+        text: String,
+    ) -> Result<comment::Model, String> {
+        let usr: String = ctx.data_opt::<RoleGuard>().unwrap().user.clone();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let before = own_note(comment, &usr, db).await?;
+        record_edit(&before, &usr, db).await;
+        let mut c: comment::ActiveModel = before.clone().into();
+        c.comment = ActiveValue::Set(text);
+        c.edited_at = ActiveValue::Set(Some(Utc::now().naive_utc()));
+        let c = c.update(db).await.unwrap();
+        audit::record(
+            db,
+            &actor(ctx),
+            AuditAction::EditComment,
+            "comment",
+            c.id,
+            audit::json(&before),
+            audit::json(&c),
+        )
+        .await;
+        Ok(c)
+    }
+    /// hide one of your notes, its text is kept in its history
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueComment)")]
+    #[instrument(skip(ctx))]
+    async fn delete_comment<'a>(&self, ctx: &Context<'a>, comment: i32) -> Result<String, String> {
+        let usr: String = ctx.data_opt::<RoleGuard>().unwrap().user.clone();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let before = own_note(comment, &usr, db).await?;
+        record_edit(&before, &usr, db).await;
+        let mut c: comment::ActiveModel = before.clone().into();
+        c.deleted_at = ActiveValue::Set(Some(Utc::now().naive_utc()));
+        c.deleted_by = ActiveValue::Set(Some(usr));
+        let c = c.update(db).await.unwrap();
+        audit::record(
+            db,
+            &actor(ctx),
+            AuditAction::DeleteComment,
+            "comment",
+            c.id,
+            audit::json(&before),
+            audit::json(&c),
+        )
+        .await;
+        Ok(format!("deleted comment {}", comment))
+    }
+    #[graphql(guard = "CapabilityChecker::new(Capability::AdminSync)")]
+    #[instrument(skip(ctx))]
+    async fn acknowledge_alert<'a>(
+        &self,
+        ctx: &Context<'a>,
+        alert: i32,
+        comment: String,
+    ) -> Result<alert::Model, String> {
         let usr: String = ctx.data_opt::<RoleGuard>().unwrap().user.clone();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let a = Alert::find_by_id(alert).one(db).await.unwrap();
+        let a = match a {
+            None => return Err(format!("Alert {} not found", alert)),
+            Some(a) if a.acknowledged_by.is_some() => {
+                return Err(format!("Alert {} already acknowledged", alert))
+            }
+            Some(a) => a,
+        };
+        info!("{} acknowledged alert {}: {}", usr, alert, comment);
+        let before = a.clone();
+        let mut a: alert::ActiveModel = a.into();
+        a.acknowledged_by = ActiveValue::Set(Some(usr.clone()));
+        a.acknowledged_at = ActiveValue::Set(Some(Utc::now().naive_utc()));
+        a.comment = ActiveValue::Set(Some(comment));
+        let a = a.update(db).await.unwrap();
+        audit::record(
+            db,
+            &actor(ctx),
+            AuditAction::AcknowledgeAlert,
+            "alert",
+            a.id,
+            audit::json(&before),
+            audit::json(&a),
+        )
+        .await;
+        let tx = ctx.data_opt::<mpsc::Sender<ChangeLogMsg>>().unwrap();
+        let _ = tx
+            .send(ChangeLogMsg::AcknowledgeAlert {
+                alert,
+                operator: usr,
+            })
+            .await;
+        Ok(a)
+    }
+    /// key for a script or cron job, everything it does is recorded as `name`
+    #[graphql(guard = "CapabilityChecker::new(Capability::AdminKeys)")]
+    #[instrument(skip(ctx))]
+    async fn create_api_key<'a>(
+        &self,
+        ctx: &Context<'a>,
+        name: String,
+        role: String,
+        expires_at: Option<NaiveDateTime>,
+        nodes: Option<Vec<String>>,
+    ) -> Result<NewApiKey, String> {
+        let user = ctx.data_opt::<RoleGuard>().unwrap();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let conf = ctx.data::<Conf>().unwrap();
+        if user.method == AuthMethod::ApiKey {
+            return Err("api keys can't create api keys".to_string());
+        }
+        let Some((role, _)) = conf.auth.named_role(&role) else {
+            return Err(format!("no role named {}", role));
+        };
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err("api key name can't be blank".to_string());
+        }
+        if ApiKey::find()
+            .filter(api_key::Column::Name.eq(&name))
+            .one(db)
+            .await
+            .unwrap()
+            .is_some()
+        {
+            return Err(format!("api key {} already exists", name));
+        }
+        if let Some(n) = &nodes
+            && (n.is_empty() || n.iter().any(|p| p.is_empty() || p.contains(',')))
+        {
+            return Err("node prefixes can't be blank or contain ','".to_string());
+        }
+        let key = auth::generate_api_key();
+        let api_key = api_key::ActiveModel {
+            name: ActiveValue::Set(name),
+            key_hash: ActiveValue::Set(auth::hash_api_key(&key)),
+            role: ActiveValue::Set(role),
+            node_prefixes: ActiveValue::Set(nodes.map(|n| n.join(","))),
+            created_by: ActiveValue::Set(user.user.clone()),
+            expires_at: ActiveValue::Set(expires_at),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+        info!("{} created api key {}", user.user, api_key.name);
+        audit::record(
+            db,
+            &actor(ctx),
+            AuditAction::CreateApiKey,
+            "api_key",
+            &api_key.name,
+            None,
+            audit::json(&api_key),
+        )
+        .await;
+        auth::load_api_keys(db).await.unwrap();
+        Ok(NewApiKey { key, api_key })
+    }
+    #[graphql(guard = "CapabilityChecker::new(Capability::AdminKeys)")]
+    #[instrument(skip(ctx))]
+    async fn delete_api_key<'a>(&self, ctx: &Context<'a>, name: String) -> Result<String, String> {
+        let user = ctx.data_opt::<RoleGuard>().unwrap();
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        if user.method == AuthMethod::ApiKey {
+            return Err("api keys can't delete api keys".to_string());
+        }
+        let Some(key) = ApiKey::find()
+            .filter(api_key::Column::Name.eq(&name))
+            .one(db)
+            .await
+            .unwrap()
+        else {
+            return Err(format!("api key {} not found", name));
+        };
+        ApiKey::delete_by_id(key.id).exec(db).await.unwrap();
+        info!("{} deleted api key {}", user.user, name);
+        audit::record(
+            db,
+            &actor(ctx),
+            AuditAction::DeleteApiKey,
+            "api_key",
+            &name,
+            audit::json(&key),
+            None,
+        )
+        .await;
+        auth::load_api_keys(db).await.unwrap();
+        Ok(format!("deleted api key {}", name))
+    }
+}
+
+#[tokio::test]
+async fn comment_edit_and_delete() {
+    use crate::auth::Role;
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+    let new_issue = NewIssue::new(
+        None,
+        "bad dimm".to_string(),
+        "bad dimm".to_string(),
+        "gu0001".to_string(),
+        None,
+        &cluster,
+    )
+    .unwrap();
+    let issue = issue_open(&new_issue, "ctt", &db, &tx, &cluster)
+        .await
+        .unwrap();
+    let schema = super::test_schema(db, cluster);
+    let run = |user: &'static str, q: String| {
+        let schema = schema.clone();
+        async move { super::test_execute(&schema, Role::Admin, user, q).await }
+    };
+
+    let resp = run(
+        "alice",
+        format!(
+            "mutation {{ addComment(issue: {}, comment: \"replaced DIMM B2\") {{ id, kind }} }}",
+            issue.id
+        ),
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    let note = resp.data.into_json().unwrap()["addComment"]["id"]
+        .as_i64()
+        .unwrap();
+
+    // only the author can change a note, and never the system comments
+    let resp = run(
+        "bob",
+        format!("mutation {{ deleteComment(comment: {}) }}", note),
+    )
+    .await;
+    assert_eq!(resp.errors.len(), 1);
+    let resp = run(
+        "alice",
+        "mutation { deleteComment(comment: 1) }".to_string(),
+    )
+    .await;
+    assert_eq!(resp.errors.len(), 1);
+
+    let resp = run(
+        "alice",
+        format!(
+            "mutation {{ editComment(comment: {}, text: \"replaced DIMM B2, waiting on burn-in\") {{ comment, history {{ comment, editedBy }} }} }}",
+            note
+        ),
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"editComment": {
+            "comment": "replaced DIMM B2, waiting on burn-in",
+            "history": [{"comment": "replaced DIMM B2", "editedBy": "alice"}],
+        }})
+    );
+
+    let resp = run(
+        "alice",
+        format!("mutation {{ deleteComment(comment: {}) }}", note),
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    let resp = run(
+        "alice",
+        format!(
+            "{{ issue(issue: {}) {{ comments {{ comment, kind }} }} }}",
+            issue.id
+        ),
+    )
+    .await;
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"issue": {"comments": [{"comment": "Opening issue", "kind": "SYSTEM"}]}})
+    );
+}
+
+#[tokio::test]
+async fn bulk_ops_report_each_target_and_send_one_batch() {
+    use crate::auth::Role;
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let (tx, mut rx) = mpsc::channel(100);
+    let alice = RoleGuard::new(
+        Role::Admin,
+        "alice".to_string(),
+        Utc::now().naive_utc() + chrono::Duration::minutes(60),
+    );
+    let actor = Actor::new(&alice, None);
+    let issue = NewIssue {
+        assigned_to: None,
+        description: "fw 1.2 is bad".to_string(),
+        to_offline: None,
+        target: String::new(),
+        targets: None,
+        title: "firmware update".to_string(),
+        severity: None,
+        category: None,
+        labels: None,
+    };
+    let ops = ["gu0001", "gu0002", "gu0009"]
+        .into_iter()
+        .map(|n| {
+            let i = NewIssue {
+                target: n.to_string(),
+                ..issue.clone()
+            };
+            (n.to_string(), IssueOp::Open(i))
+        })
+        .collect();
+    let results = bulk(ops, &alice, &actor, &db, &tx, &cluster).await.unwrap();
+    assert_eq!(
+        results
+            .iter()
+            .map(|r| (r.target.as_str(), r.issue.is_some(), r.error.is_some()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("gu0001", true, false),
+            ("gu0002", true, false),
+            ("gu0009", false, true)
+        ]
+    );
+    let ChangeLogMsg::Batch { operator, messages } = rx.try_recv().unwrap() else {
+        panic!("expected a batch");
+    };
+    assert_eq!(operator, "alice");
+    assert_eq!(messages.len(), 2);
+    assert!(rx.try_recv().is_err());
+
+    // an empty filter would close everything
+    assert!(bulk_issues(&IssueFilter::default(), &db).await.is_err());
+    let filter = IssueFilter {
+        target: Some("gu[0001-0004]".to_string()),
+        ..Default::default()
+    };
+    let ops = bulk_issues(&filter, &db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(t, i)| {
+            let op = IssueOp::Close {
+                id: i.id,
+                comment: "fw rolled back".to_string(),
+            };
+            (t, op)
+        })
+        .collect();
+    let results = bulk(ops, &alice, &actor, &db, &tx, &cluster).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results
+        .iter()
+        .all(|r| r.issue.as_ref().unwrap().status == IssueStatus::Closing));
+    let ChangeLogMsg::Batch { messages, .. } = rx.try_recv().unwrap() else {
+        panic!("expected a batch");
+    };
+    assert!(messages
+        .iter()
+        .all(|m| matches!(m, ChangeLogMsg::Close { .. })));
+}
 
-        issue_update(issue, &usr, ctx).await
+#[tokio::test]
+async fn bulk_releases_nodes_once_after_commit() {
+    use crate::auth::Role;
+    use crate::cluster::scheduler::SchedulerCall;
+    let db = crate::setup::test_db().await;
+    let (cluster, sched) = crate::cluster::test_cluster();
+    let (tx, mut rx) = mpsc::channel(100);
+    let alice = RoleGuard::new(
+        Role::Admin,
+        "alice".to_string(),
+        Utc::now().naive_utc() + chrono::Duration::minutes(60),
+    );
+    let actor = Actor::new(&alice, None);
+    let new_issue = NewIssue::new(
+        None,
+        "bad card".to_string(),
+        "bad card".to_string(),
+        "gu0002".to_string(),
+        Some(ToOffline::Card),
+        &cluster,
+    )
+    .unwrap();
+    let issue = issue_open(&new_issue, "alice", &db, &tx, &cluster)
+        .await
+        .unwrap();
+    while rx.try_recv().is_ok() {}
+    let update = |id| UpdateIssue {
+        assigned_to: None,
+        description: None,
+        enforce_down: None,
+        to_offline: Some(ToOffline::Node),
+        id,
+        title: None,
+        severity: None,
+        category: None,
+        labels: None,
+    };
+
+    // only the card's other node is freed, and the missing issue doesn't stop the release
+    let ops = vec![
+        ("gu0002".to_string(), IssueOp::Update(update(issue.id))),
+        ("gu0009".to_string(), IssueOp::Update(update(99))),
+    ];
+    let results = bulk(ops, &alice, &actor, &db, &tx, &cluster).await.unwrap();
+    assert!(results[0].error.is_none() && results[1].error.is_some());
+    assert_eq!(
+        sched.calls(),
+        vec![SchedulerCall::Release {
+            target: "gu0001".to_string()
+        }]
+    );
+    let ChangeLogMsg::Batch { messages, .. } = rx.try_recv().unwrap() else {
+        panic!("expected a batch");
+    };
+    assert!(messages
+        .iter()
+        .any(|m| matches!(m, ChangeLogMsg::Resume { target, .. } if target == "gu0001")));
+}
+
+#[tokio::test]
+async fn update_many_only_changes_what_the_patch_sets() {
+    use crate::auth::Role;
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+    let new_issue = NewIssue::new(
+        None,
+        "fw 1.2 is bad".to_string(),
+        "firmware update".to_string(),
+        "gu0001".to_string(),
+        None,
+        &cluster,
+    )
+    .unwrap();
+    issue_open(&new_issue, "alice", &db, &tx, &cluster)
+        .await
+        .unwrap();
+    let schema = super::test_schema(db, cluster);
+
+    let resp = super::test_execute(
+        &schema,
+        Role::Admin,
+        "alice",
+        "mutation { updateMany(filter: {target: \"gu0001\"}, patch: {labels: [\"fw\"]}) { error, issue { toOffline, labels } } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"updateMany": [
+            {"error": null, "issue": {"toOffline": null, "labels": ["fw"]}},
+        ]})
+    );
+
+    // a direct edit still pins the issue to its node
+    let resp = super::test_execute(
+        &schema,
+        Role::Admin,
+        "alice",
+        "mutation { updateIssue(issue: {id: 1, title: \"fw 1.3 is bad\"}) { toOffline } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"updateIssue": {"toOffline": "NODE"}})
+    );
+}
+
+#[tokio::test]
+async fn open_from_template() {
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+    let template = IssueTemplate {
+        name: "ecc".to_string(),
+        title: "Memory ECC on {dimm}".to_string(),
+        description: "{target} logged uncorrectable errors on {dimm}".to_string(),
+        to_offline: Some(ToOffline::Node),
+        severity: Severity::Major,
+        labels: vec!["dimm".to_string()],
+        assigned_to: Some("fred".to_string()),
+    };
+    let vars = vec![TemplateVar {
+        name: "dimm".to_string(),
+        value: "B2".to_string(),
+    }];
+    let new_issue = NewIssue::from_template(&template, "gu0002".to_string(), vars).unwrap();
+    let issue = issue_open(&new_issue, "alice", &db, &tx, &cluster)
+        .await
+        .unwrap();
+    assert_eq!(issue.title, "Memory ECC on B2");
+    assert_eq!(
+        issue.description,
+        "gu0002 logged uncorrectable errors on B2"
+    );
+    assert_eq!(issue.to_offline, Some(ToOffline::Node));
+    assert_eq!(issue.severity, Severity::Major);
+    assert_eq!(issue.assigned_to.as_deref(), Some("fred"));
+    assert_eq!(issue.get_labels(&db).await, vec!["dimm"]);
+
+    assert!(NewIssue::from_template(&template, "gu0002".to_string(), vec![]).is_err());
+}
+
+#[tokio::test]
+async fn capabilities_limit_a_configured_role() {
+    use crate::auth::{Capability, Role};
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let schema = super::test_schema(db, cluster);
+    let triage = RoleGuard::named(
+        "triage".to_string(),
+        vec![
+            Capability::IssueRead,
+            Capability::IssueComment,
+            Capability::IssueOpen,
+        ],
+        "carol".to_string(),
+        Utc::now().naive_utc() + chrono::Duration::minutes(60),
+    );
+    let run = |q: &str| schema.execute(async_graphql::Request::new(q).data(triage.clone()));
+
+    let resp = run("{ me { user, role, capabilities, nodes } }").await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"me": {
+            "user": "carol",
+            "role": "triage",
+            "capabilities": ["ISSUE_READ", "ISSUE_COMMENT", "ISSUE_OPEN"],
+            "nodes": null,
+        }})
+    );
+
+    let resp = run(
+        "mutation { open(issue: {title: \"t\", description: \"d\", target: \"gu0001\"}) { id } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    let resp = run("mutation { addComment(issue: 1, comment: \"looking\") { id } }").await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+
+    // taking down a blade, closing and sync need capabilities triage doesn't have
+    let resp = run(
+        "mutation { open(issue: {title: \"t\", description: \"d\", target: \"gu0002\", toOffline: BLADE}) { id } }",
+    )
+    .await;
+    assert_eq!(
+        resp.errors[0].message,
+        "carol is missing issue:set_to_offline_blade"
+    );
+    let resp = run("mutation { close(issue: 1, comment: \"done\") }").await;
+    assert_eq!(resp.errors[0].message, "Insufficient Permission");
+    for q in [
+        "mutation { open(issue: {title: \"t\", description: \"d\", target: \"gu0003\", toOffline: BLADE}) { id } }",
+        "mutation { close(issue: 2, comment: \"done\") }",
+    ] {
+        let resp = super::test_execute(&schema, Role::Admin, "alice", q).await;
+        assert!(resp.errors.is_empty(), "{:?}", resp.errors);
     }
+    let resp = run("mutation { reopen(issue: 2, comment: \"back\") { id } }").await;
+    assert_eq!(
+        resp.errors[0].message,
+        "carol is missing issue:set_to_offline_blade"
+    );
+    let resp = run("{ syncPlan { node } }").await;
+    assert_eq!(resp.errors[0].message, "Insufficient Permission");
+}
+
+#[tokio::test]
+async fn acl_covers_every_node_to_offline_pulls_in() {
+    use crate::auth::{NodeScope, Role};
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let schema = super::test_schema(db, cluster);
+    // owns the first card, gu0001 and gu0002, but not the rest of the blade
+    let mut partner = RoleGuard::new(
+        Role::Admin,
+        "dave".to_string(),
+        Utc::now().naive_utc() + chrono::Duration::minutes(60),
+    );
+    partner.nodes = Some(vec![NodeScope::Hosts("gu000[1-2]".to_string())]);
+    let run = |q: &str| schema.execute(async_graphql::Request::new(q).data(partner.clone()));
+
+    let resp = run(
+        "mutation { open(issue: {title: \"t\", description: \"d\", target: \"gu0002\", toOffline: CARD}) { id } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    let resp = run(
+        "mutation { open(issue: {title: \"t\", description: \"d\", target: \"gu0001\", toOffline: BLADE}) { id } }",
+    )
+    .await;
+    assert_eq!(resp.errors[0].message, "dave may not act on gu0003");
+    let resp = run("mutation { updateIssue(issue: {id: 1, toOffline: BLADE}) { id } }").await;
+    assert_eq!(resp.errors[0].message, "dave may not act on gu0003");
+
+    let resp = super::test_execute(
+        &schema,
+        Role::Admin,
+        "alice",
+        "mutation { open(issue: {title: \"t\", description: \"d\", target: \"gu0003\"}) { id } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    let resp = run("mutation { close(issue: 2, comment: \"mine now\") }").await;
+    assert_eq!(resp.errors[0].message, "dave may not act on gu0003");
+    let resp = run("mutation { addComment(issue: 2, comment: \"mine now\") { id } }").await;
+    assert_eq!(resp.errors[0].message, "dave may not act on gu0003");
+    let resp = run("mutation { addComment(issue: 1, comment: \"reseated\") { id } }").await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+
+    let resp = run(
+        "mutation { openMany(issue: {title: \"t2\", description: \"d\", target: \"gu000[1-4]\"}) { target, error } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"openMany": [
+            {"target": "gu0001", "error": null},
+            {"target": "gu0002", "error": null},
+            {"target": "gu0003", "error": "dave may not act on gu0003"},
+            {"target": "gu0004", "error": "dave may not act on gu0004"},
+        ]})
+    );
 }
@@ -1,56 +1,654 @@
-use crate::auth::{Role, RoleChecker};
+use super::search::{self, SearchResult};
+use super::summary::{self, ClusterSummary};
+use crate::audit::AuditFilter;
+use crate::auth::{Capability, CapabilityChecker, RoleGuard};
+use crate::cluster::RegexCluster;
+use crate::conf::{Conf, IssueTemplate};
+use crate::entities::alert;
+use crate::entities::api_key;
+use crate::entities::audit_event;
 use crate::entities::issue::{self, IssueStatus};
+use crate::entities::issue_target;
 use crate::entities::prelude::*;
 use crate::entities::target;
-use async_graphql::{Context, Object};
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use crate::hostlist;
+use crate::sync::{self, PlanEntry, SyncStatus};
+use crate::ChangeLogMsg;
+use async_graphql::{Context, Enum, InputObject, Object, SimpleObject};
+use chrono::NaiveDateTime;
+use sea_orm::sea_query::Query as SeaQuery;
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait, JoinType, Order,
+    QueryFilter, QueryOrder, QuerySelect, RelationTrait, Select,
+};
 use std::sync::Arc;
+use tokio::sync::mpsc;
 use tracing::instrument;
 
+/// most nodes an issue filter's `target` can name, well under sqlite's limit on query parameters
+pub const MAX_FILTER_HOSTS: usize = 4096;
+
+/// issue filters shared by the issues query and bulk mutations, unset fields match everything
+#[derive(InputObject, Clone, Debug, Default)]
+pub struct IssueFilter {
+    /// defaults to every status but Closed
+    pub status: Option<IssueStatus>,
+    /// node or hostlist
+    pub target: Option<String>,
+    pub created_by: Option<String>,
+    pub assigned_to: Option<String>,
+    pub created_after: Option<NaiveDateTime>,
+    pub created_before: Option<NaiveDateTime>,
+    pub to_offline: Option<issue::ToOffline>,
+    pub severity: Option<issue::Severity>,
+    pub category: Option<issue::Category>,
+    /// issues carrying this label
+    pub label: Option<String>,
+}
+
+impl IssueFilter {
+    /// no field is set, so every issue that isn't Closed matches
+    pub fn matches_everything(&self) -> bool {
+        self.status.is_none()
+            && self.target.is_none()
+            && self.created_by.is_none()
+            && self.assigned_to.is_none()
+            && self.created_after.is_none()
+            && self.created_before.is_none()
+            && self.to_offline.is_none()
+            && self.severity.is_none()
+            && self.category.is_none()
+            && self.label.is_none()
+    }
+    pub async fn apply(
+        &self,
+        mut select: Select<issue::Entity>,
+        db: &impl ConnectionTrait,
+    ) -> Result<Select<issue::Entity>, String> {
+        select = match self.status {
+            Some(s) => select.filter(issue::Column::Status.eq(s)),
+            None => select.filter(issue::Column::Status.ne(IssueStatus::Closed)),
+        };
+        if let Some(t) = &self.target {
+            // only nodes ctt tracks can have issues
+            let names = hostlist::expand_at_most(t, MAX_FILTER_HOSTS)?;
+            let ids = Target::find()
+                .select_only()
+                .column(target::Column::Id)
+                .filter(target::Column::Name.is_in(names))
+                .into_tuple()
+                .all(db)
+                .await
+                .map_err(|e| e.to_string())?;
+            select = select.filter(issue::Entity::targeting(ids));
+        }
+        if let Some(c) = &self.created_by {
+            select = select.filter(issue::Column::CreatedBy.eq(c));
+        }
+        if let Some(a) = &self.assigned_to {
+            select = select.filter(issue::Column::AssignedTo.eq(a));
+        }
+        if let Some(after) = self.created_after {
+            select = select.filter(issue::Column::CreatedAt.gte(after));
+        }
+        if let Some(before) = self.created_before {
+            select = select.filter(issue::Column::CreatedAt.lt(before));
+        }
+        if let Some(o) = self.to_offline {
+            select = select.filter(issue::Column::ToOffline.eq(o));
+        }
+        if let Some(s) = self.severity {
+            select = select.filter(issue::Column::Severity.eq(s));
+        }
+        if let Some(c) = self.category {
+            select = select.filter(issue::Column::Category.eq(c));
+        }
+        if let Some(l) = &self.label {
+            select = select.filter(issue::Entity::labelled(l.trim()));
+        }
+        Ok(select)
+    }
+}
+
+/// issues returned when the issues query isn't given a limit
+pub const DEFAULT_PAGE_SIZE: u64 = 100;
+/// the most issues one issues query can return
+pub const MAX_PAGE_SIZE: u64 = 1000;
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IssueSort {
+    /// the target's name, the default
+    Target,
+    Id,
+    Created,
+    Updated,
+}
+
+/// the caller, as the server sees them
+#[derive(SimpleObject, Debug)]
+pub struct Me {
+    user: String,
+    role: String,
+    capabilities: Vec<Capability>,
+    /// hostlists, name prefixes (ending in `*`) and node types (`#` for each digit) the caller is
+    /// limited to, any node if null
+    nodes: Option<Vec<String>>,
+}
+
 #[derive(Debug)]
 pub struct Query;
 
 #[Object]
 impl Query {
-    #[graphql(guard = "RoleChecker::new(Role::Admin).or(RoleChecker::new(Role::Guest))")]
+    /// who the caller is logged in as and what they can do, needs no capabilities
+    #[instrument(skip(ctx))]
+    async fn me<'a>(&self, ctx: &Context<'a>) -> async_graphql::Result<Me> {
+        let user = ctx.data::<RoleGuard>()?;
+        Ok(Me {
+            user: user.user.clone(),
+            role: user.role().to_string(),
+            capabilities: user.capabilities().to_vec(),
+            nodes: user
+                .nodes
+                .as_ref()
+                .map(|n| n.iter().map(ToString::to_string).collect()),
+        })
+    }
+
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueRead)")]
     #[instrument(skip(ctx))]
     async fn issue<'a>(&self, ctx: &Context<'a>, issue: i32) -> Option<issue::Model> {
         let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
         Issue::find_by_id(issue).one(db).await.unwrap()
     }
 
-    #[graphql(guard = "RoleChecker::new(Role::Admin).or(RoleChecker::new(Role::Guest))")]
+    /// sorted by target name unless `sortBy` says otherwise, pages hold `DEFAULT_PAGE_SIZE`
+    /// issues by default and at most `MAX_PAGE_SIZE`
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueRead)")]
     #[instrument(skip(ctx))]
+    #[allow(clippy::too_many_arguments)]
     async fn issues<'a>(
         &self,
         ctx: &Context<'a>,
         issue_status: Option<issue::IssueStatus>,
         target: Option<String>,
-    ) -> Vec<issue::Model> {
+        filter: Option<IssueFilter>,
+        sort_by: Option<IssueSort>,
+        descending: Option<bool>,
+        limit: Option<u64>,
+        offset: Option<u64>,
+    ) -> Result<Vec<issue::Model>, String> {
         let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
-        let mut select = target::Entity::find().find_with_related(issue::Entity);
-        if let Some(status) = issue_status {
-            select =
-                select.filter(<issue::Entity as sea_orm::EntityTrait>::Column::Status.eq(status));
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if limit > MAX_PAGE_SIZE {
+            return Err(format!("limit can be at most {}", MAX_PAGE_SIZE));
+        }
+        let mut filter = filter.unwrap_or_default();
+        filter.status = issue_status.or(filter.status);
+        filter.target = target.or(filter.target);
+        let order = if descending.unwrap_or(false) {
+            Order::Desc
         } else {
-            select = select.filter(
-                <issue::Entity as sea_orm::EntityTrait>::Column::Status.ne(IssueStatus::Closed),
-            );
+            Order::Asc
+        };
+        let mut select = filter.apply(Issue::find(), db).await?;
+        select = match sort_by.unwrap_or(IssueSort::Target) {
+            IssueSort::Target => select
+                .join(JoinType::InnerJoin, issue::Relation::Target.def())
+                .order_by(target::Column::Name, order.clone()),
+            IssueSort::Id => select,
+            IssueSort::Created => select.order_by(issue::Column::CreatedAt, order.clone()),
+            IssueSort::Updated => select.order_by(issue::Column::UpdatedAt, order.clone()),
+        };
+        // id breaks ties so pages stay stable
+        select
+            .order_by(issue::Column::Id, order)
+            .offset(offset)
+            .limit(limit)
+            .all(db)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// full text search over issue titles, descriptions, and comments, best matches first
+    ///
+    /// `since` only matches issues updated after it
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueRead)")]
+    #[instrument(skip(ctx))]
+    async fn search_issues<'a>(
+        &self,
+        ctx: &Context<'a>,
+        text: String,
+        status: Option<IssueStatus>,
+        since: Option<NaiveDateTime>,
+    ) -> Result<Vec<SearchResult>, String> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        search::search_issues(db, &text, status, since)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueRead)")]
+    #[instrument(skip(ctx))]
+    async fn target<'a>(&self, ctx: &Context<'a>, name: String) -> Option<target::Model> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        Target::find_by_name(&name, db).await
+    }
+
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueRead)")]
+    #[instrument(skip(ctx))]
+    async fn targets<'a>(
+        &self,
+        ctx: &Context<'a>,
+        status: Option<target::TargetStatus>,
+        prefix: Option<String>,
+        has_open_issues: Option<bool>,
+    ) -> Vec<target::Model> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let mut select = Target::all();
+        if let Some(s) = status {
+            select = select.filter(target::Column::Status.eq(s));
         }
-        if let Some(t) = target {
-            select = select.filter(<target::Entity as sea_orm::EntityTrait>::Column::Name.eq(t));
+        if let Some(p) = prefix {
+            select = select.filter(target::Column::Name.starts_with(p));
         }
-        select
-            .order_by_asc(crate::entities::target::Column::Name)
+        if let Some(open) = has_open_issues {
+            let open_issues = SeaQuery::select()
+                .column(issue::Column::Id)
+                .from(issue::Entity)
+                .and_where(issue::Column::Status.is_in([IssueStatus::Open, IssueStatus::Opening]))
+                .to_owned();
+            let with_open = Condition::any()
+                .add(
+                    target::Column::Id.in_subquery(
+                        SeaQuery::select()
+                            .column(issue::Column::TargetId)
+                            .from(issue::Entity)
+                            .and_where(issue::Column::Id.in_subquery(open_issues.clone()))
+                            .to_owned(),
+                    ),
+                )
+                .add(
+                    target::Column::Id.in_subquery(
+                        SeaQuery::select()
+                            .column(issue_target::Column::TargetId)
+                            .from(issue_target::Entity)
+                            .and_where(issue_target::Column::IssueId.in_subquery(open_issues))
+                            .to_owned(),
+                    ),
+                );
+            select = if open {
+                select.filter(with_open)
+            } else {
+                select.filter(with_open.not())
+            };
+        }
+        select.all(db).await.unwrap()
+    }
+
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueRead)")]
+    #[instrument(skip(ctx))]
+    async fn alerts<'a>(&self, ctx: &Context<'a>, acknowledged: Option<bool>) -> Vec<alert::Model> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let mut select = Alert::find().order_by_desc(alert::Column::Id);
+        match acknowledged {
+            Some(true) => select = select.filter(alert::Column::AcknowledgedBy.is_not_null()),
+            Some(false) => select = select.filter(alert::Column::AcknowledgedBy.is_null()),
+            None => (),
+        }
+        select.all(db).await.unwrap()
+    }
+
+    /// target and issue counts, nodes out of their desired state, and the last sync pass
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueRead)")]
+    #[instrument(skip(ctx))]
+    async fn cluster_summary<'a>(&self, ctx: &Context<'a>) -> ClusterSummary {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        let status = ctx.data::<Arc<SyncStatus>>().unwrap().as_ref();
+        summary::cluster_summary(db, cluster, status).await
+    }
+
+    #[graphql(guard = "CapabilityChecker::new(Capability::AdminKeys)")]
+    #[instrument(skip(ctx))]
+    async fn api_keys<'a>(&self, ctx: &Context<'a>) -> Vec<api_key::Model> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        ApiKey::find()
+            .order_by_asc(api_key::Column::Name)
             .all(db)
             .await
             .unwrap()
-            .into_iter()
-            .map(|(_, i)| i)
-            .reduce(|mut acc, mut c| {
-                acc.append(&mut c);
-                acc
-            })
-            .unwrap_or(vec![])
     }
+
+    /// state changing actions, newest first
+    #[graphql(guard = "CapabilityChecker::new(Capability::AdminAudit)")]
+    #[instrument(skip(ctx))]
+    async fn audit_log<'a>(
+        &self,
+        ctx: &Context<'a>,
+        filter: Option<AuditFilter>,
+        limit: Option<u64>,
+        offset: Option<u64>,
+    ) -> Result<Vec<audit_event::Model>, String> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if limit > MAX_PAGE_SIZE {
+            return Err(format!("limit can be at most {}", MAX_PAGE_SIZE));
+        }
+        Ok(filter
+            .unwrap_or_default()
+            .apply(AuditEvent::find())
+            .order_by_desc(audit_event::Column::Id)
+            .offset(offset)
+            .limit(limit)
+            .all(db)
+            .await
+            .unwrap())
+    }
+
+    /// issue templates from the config, for openFromTemplate
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueRead)")]
+    #[instrument(skip(ctx))]
+    async fn templates<'a>(&self, ctx: &Context<'a>) -> Vec<IssueTemplate> {
+        ctx.data::<Conf>().unwrap().templates.clone()
+    }
+
+    /// run one sync pass in dry run mode, returning what it would have done
+    #[graphql(guard = "CapabilityChecker::new(Capability::AdminSync)")]
+    #[instrument(skip(ctx))]
+    async fn sync_plan<'a>(&self, ctx: &Context<'a>) -> Result<Vec<PlanEntry>, String> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().as_ref();
+        let cluster = ctx.data::<Arc<RegexCluster>>().unwrap().as_ref();
+        let tx = ctx.data::<mpsc::Sender<ChangeLogMsg>>().unwrap();
+        let conf = ctx.data::<Conf>().unwrap();
+        let opts = sync::SyncOptions {
+            dry_run: true,
+            ..conf.into()
+        };
+        sync::sync_once(db, cluster, tx, &opts).await
+    }
+}
+
+#[tokio::test]
+async fn targets_with_open_issues() {
+    use crate::auth::Role;
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+    let new_issue = super::NewIssue::new(
+        None,
+        "bad card".to_string(),
+        "bad card".to_string(),
+        "gu0002".to_string(),
+        Some(issue::ToOffline::Card),
+        &cluster,
+    )
+    .unwrap();
+    super::mutation::issue_open(&new_issue, "tester", &db, &tx, &cluster)
+        .await
+        .unwrap();
+    let schema = super::test_schema(db.clone(), cluster);
+
+    let resp = super::test_execute(
+        &schema,
+        Role::Guest,
+        "tester",
+        "{ targets(hasOpenIssues: true) { name, desiredState, issues { title }, siblings { name } } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"targets": [{
+            "name": "gu0002",
+            "desiredState": "OFFLINE",
+            "issues": [{"title": "bad card"}],
+            "siblings": [{"name": "gu0002"}],
+        }]})
+    );
+
+    // reads never add targets, gu0001 is only tracked once sync offlines it for gu0002's card
+    // wide issue
+    let query = "{ targets(hasOpenIssues: false, prefix: \"gu\") { name, desiredState } }";
+    let resp = super::test_execute(&schema, Role::Guest, "tester", query).await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"targets": []})
+    );
+    let (cluster, _) = crate::cluster::test_cluster();
+    sync::sync_once(&db, &cluster, &tx, &Default::default())
+        .await
+        .unwrap();
+    let resp = super::test_execute(&schema, Role::Guest, "tester", query).await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"targets": [{"name": "gu0001", "desiredState": "OFFLINE"}]})
+    );
+}
+
+#[tokio::test]
+async fn issues_paginate_and_filter() {
+    use crate::auth::Role;
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+    for (target, operator) in [("gu0001", "alice"), ("gu0002", "bob"), ("gu0003", "alice")] {
+        let new_issue = super::NewIssue::new(
+            None,
+            format!("{} broken", target),
+            format!("{} broken", target),
+            target.to_string(),
+            None,
+            &cluster,
+        )
+        .unwrap();
+        super::mutation::issue_open(&new_issue, operator, &db, &tx, &cluster)
+            .await
+            .unwrap();
+    }
+    let schema = super::test_schema(db, cluster);
+
+    let resp = super::test_execute(
+        &schema,
+        Role::Guest,
+        "tester",
+        "{ issues(sortBy: CREATED, descending: true, limit: 2, offset: 1) { id } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"issues": [{"id": 2}, {"id": 1}]})
+    );
+
+    let resp = super::test_execute(
+        &schema,
+        Role::Guest,
+        "tester",
+        "{ issues(filter: {createdBy: \"alice\", target: \"gu0003\", createdAfter: \"2000-01-01T00:00:00\"}) { id } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"issues": [{"id": 3}]})
+    );
+
+    let resp = super::test_execute(
+        &schema,
+        Role::Guest,
+        "tester",
+        "{ issues(filter: {createdBefore: \"2000-01-01T00:00:00\"}) { id } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"issues": []})
+    );
+
+    let resp = super::test_execute(
+        &schema,
+        Role::Guest,
+        "tester",
+        "{ issues(target: \"gu[0001-0002]\") { id, hostlist } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"issues": [{"id": 1, "hostlist": "gu0001"}, {"id": 2, "hostlist": "gu0002"}]})
+    );
+
+    // a hostlist target opens one issue covering every node in it
+    let resp = super::test_execute(
+        &schema,
+        Role::Admin,
+        "tester",
+        "mutation { open(issue: {title: \"pdu\", description: \"pdu\", target: \"gu[0002-0004]\"}) { target { name }, hostlist } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"open": {"target": {"name": "gu0002"}, "hostlist": "gu[0002-0004]"}})
+    );
+    let resp = super::test_execute(
+        &schema,
+        Role::Admin,
+        "tester",
+        "mutation { open(issue: {title: \"pdu\", description: \"pdu\", target: \"gu[0004-0002]\"}) { id } }",
+    )
+    .await;
+    assert_eq!(resp.errors.len(), 1);
+}
+
+#[tokio::test]
+async fn issues_have_severity_category_and_labels() {
+    use crate::auth::Role;
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+    let new_issue = super::NewIssue::new(
+        None,
+        "gu0002 broken".to_string(),
+        "gu0002 broken".to_string(),
+        "gu0002".to_string(),
+        None,
+        &cluster,
+    )
+    .unwrap();
+    super::mutation::issue_open(&new_issue, "tester", &db, &tx, &cluster)
+        .await
+        .unwrap();
+    let schema = super::test_schema(db, cluster);
+
+    let resp = super::test_execute(
+        &schema,
+        Role::Admin,
+        "tester",
+        "mutation { open(issue: {title: \"ib\", description: \"ib\", target: \"gu0001\", severity: CRITICAL, category: NETWORK, labels: [\"switch\", \" ib \", \"ib\"]}) { id, severity, category, labels } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"open": {"id": 2, "severity": "CRITICAL", "category": "NETWORK", "labels": ["ib", "switch"]}})
+    );
+    let resp = super::test_execute(
+        &schema,
+        Role::Guest,
+        "tester",
+        "{ critical: issues(filter: {severity: CRITICAL}) { id }, labelled: issues(filter: {label: \"switch\"}) { id }, minor: issues(filter: {severity: MINOR, category: NETWORK}) { id } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"critical": [{"id": 2}], "labelled": [{"id": 2}], "minor": []})
+    );
+    // a bad label rejects the whole update, nothing before it is written
+    let resp = super::test_execute(
+        &schema,
+        Role::Admin,
+        "tester",
+        "mutation { updateIssue(issue: {id: 2, severity: MINOR, labels: [\" \"]}) { id } }",
+    )
+    .await;
+    assert_eq!(resp.errors.len(), 1);
+    let resp = super::test_execute(
+        &schema,
+        Role::Guest,
+        "tester",
+        "{ issue(issue: 2) { severity, comments { comment } } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"issue": {"severity": "CRITICAL", "comments": [{"comment": "Opening issue"}]}})
+    );
+    let resp = super::test_execute(
+        &schema,
+        Role::Admin,
+        "tester",
+        "mutation { updateIssue(issue: {id: 2, severity: MAJOR, labels: []}) { severity, labels } }",
+    )
+    .await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"updateIssue": {"severity": "MAJOR", "labels": []}})
+    );
+}
+
+#[tokio::test]
+async fn issues_default_to_target_order_and_bounded_pages() {
+    use crate::auth::Role;
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+    for target in ["gu0003", "gu0001"] {
+        let new_issue = super::NewIssue::new(
+            None,
+            format!("{} broken", target),
+            format!("{} broken", target),
+            target.to_string(),
+            None,
+            &cluster,
+        )
+        .unwrap();
+        super::mutation::issue_open(&new_issue, "tester", &db, &tx, &cluster)
+            .await
+            .unwrap();
+    }
+    let schema = super::test_schema(db, cluster);
+
+    let resp = super::test_execute(&schema, Role::Guest, "tester", "{ issues { id } }").await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"issues": [{"id": 2}, {"id": 1}]})
+    );
+
+    let query = format!("{{ issues(limit: {}) {{ id }} }}", MAX_PAGE_SIZE + 1);
+    let resp = super::test_execute(&schema, Role::Guest, "tester", query).await;
+    assert_eq!(resp.errors.len(), 1);
+
+    // a target filter only matches tracked nodes, and can't name more than MAX_FILTER_HOSTS
+    let query = format!(
+        "{{ issues(target: \"gu[1-{}]\") {{ id }} }}",
+        MAX_FILTER_HOSTS
+    );
+    let resp = super::test_execute(&schema, Role::Guest, "tester", query).await;
+    assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+    assert_eq!(
+        resp.data.into_json().unwrap(),
+        serde_json::json!({"issues": []})
+    );
+    let query = "{ issues(target: \"gu[00001-20000]\") { id } }";
+    let resp = super::test_execute(&schema, Role::Guest, "tester", query).await;
+    assert_eq!(resp.errors.len(), 1);
 }
@@ -0,0 +1,193 @@
+use crate::entities::issue::{self, IssueStatus};
+use crate::entities::prelude::*;
+use async_graphql::SimpleObject;
+use chrono::NaiveDateTime;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, DbErr, EntityTrait, QueryFilter,
+    Statement, Value,
+};
+use std::collections::HashMap;
+use tracing::instrument;
+
+const MAX_RESULTS: u64 = 50;
+// fts5 wraps matches in these, they're swapped for <b></b> once the snippet is escaped
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+#[derive(Clone, Debug, PartialEq, SimpleObject)]
+pub struct SearchResult {
+    pub issue: issue::Model,
+    /// best matching fragment of the title, description, or comments, html escaped with matches
+    /// in <b></b>
+    pub snippet: String,
+    /// bm25 score, lower is a better match
+    pub rank: f64,
+}
+
+/// html escape issue text from an fts5 snippet then mark its matches with <b></b>
+fn highlight(snippet: &str) -> String {
+    let mut out = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            MATCH_START => out.push_str("<b>"),
+            MATCH_END => out.push_str("</b>"),
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// turn free text into an fts5 query matching every word
+///
+/// each word is quoted so punctuation in node names and error messages isn't read as fts5
+/// syntax
+fn fts_query(text: &str) -> String {
+    text.split_whitespace()
+        .map(|w| format!("\"{}\"", w.replace('"', "\"\"")))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[instrument(skip(db))]
+pub async fn search_issues(
+    db: &DatabaseConnection,
+    text: &str,
+    status: Option<IssueStatus>,
+    since: Option<NaiveDateTime>,
+) -> Result<Vec<SearchResult>, DbErr> {
+    let query = fts_query(text);
+    if query.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut sql = "SELECT issue.id AS id, \
+        snippet(issue_fts, -1, ?, ?, '...', 16) AS snippet, \
+        bm25(issue_fts) AS rank \
+        FROM issue_fts JOIN issue ON issue.id = issue_fts.rowid \
+        WHERE issue_fts MATCH ?"
+        .to_string();
+    let mut values: Vec<Value> = vec![
+        MATCH_START.to_string().into(),
+        MATCH_END.to_string().into(),
+        query.into(),
+    ];
+    if let Some(s) = status {
+        sql.push_str(" AND issue.status = ?");
+        values.push(s.into());
+    }
+    if let Some(since) = since {
+        sql.push_str(" AND issue.updated_at >= ?");
+        values.push(since.into());
+    }
+    sql.push_str(&format!(" ORDER BY rank LIMIT {}", MAX_RESULTS));
+
+    let hits = db
+        .query_all(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            sql,
+            values,
+        ))
+        .await?
+        .into_iter()
+        .map(|r| {
+            Ok((
+                r.try_get::<i32>("", "id")?,
+                highlight(&r.try_get::<String>("", "snippet")?),
+                r.try_get::<f64>("", "rank")?,
+            ))
+        })
+        .collect::<Result<Vec<_>, DbErr>>()?;
+    let mut issues: HashMap<i32, issue::Model> = Issue::find()
+        .filter(issue::Column::Id.is_in(hits.iter().map(|(id, _, _)| *id)))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|i| (i.id, i))
+        .collect();
+    Ok(hits
+        .into_iter()
+        .filter_map(|(id, snippet, rank)| {
+            issues.remove(&id).map(|issue| SearchResult {
+                issue,
+                snippet,
+                rank,
+            })
+        })
+        .collect())
+}
+
+#[tokio::test]
+async fn search_tracks_issues_and_comments() {
+    use sea_orm::{ActiveModelTrait, ActiveValue};
+    use tokio::sync::mpsc;
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+    for (target, title) in [
+        ("gu0001", "DIMM error"),
+        ("gu0002", "hca flapping"),
+        ("gu0003", "<script>alert(1)</script>"),
+    ] {
+        let new_issue = super::NewIssue::new(
+            None,
+            format!("{} on {}", title, target),
+            title.to_string(),
+            target.to_string(),
+            None,
+            &cluster,
+        )
+        .unwrap();
+        super::mutation::issue_open(&new_issue, "tester", &db, &tx, &cluster)
+            .await
+            .unwrap();
+    }
+
+    let hits = search_issues(&db, "dimm", None, None).await.unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].issue.title, "DIMM error");
+    assert!(
+        hits[0].snippet.contains("<b>DIMM</b>"),
+        "{}",
+        hits[0].snippet
+    );
+
+    // issue text is escaped, only the match markers are markup
+    let hits = search_issues(&db, "script", None, None).await.unwrap();
+    assert_eq!(hits.len(), 1);
+    assert!(
+        hits[0]
+            .snippet
+            .contains("&lt;<b>script</b>&gt;alert(1)&lt;/<b>script</b>&gt;"),
+        "{}",
+        hits[0].snippet
+    );
+
+    // comments are indexed as they are added
+    crate::entities::comment::ActiveModel {
+        created_by: ActiveValue::Set("tester".to_string()),
+        comment: ActiveValue::Set("reseated cable on gu0002-ib0".to_string()),
+        issue_id: ActiveValue::Set(2),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await
+    .unwrap();
+    let hits = search_issues(&db, "gu0002-ib0 reseated", None, None)
+        .await
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].issue.id, 2);
+
+    assert!(search_issues(&db, "dimm", Some(IssueStatus::Closed), None)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(search_issues(&db, "  ", None, None)
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,227 @@
+use crate::auth::{Capability, CapabilityChecker};
+use crate::entities::issue;
+use crate::entities::prelude::*;
+use crate::entities::target::{self, TargetStatus};
+use crate::ChangeLogMsg;
+use async_graphql::{Context, Enum, SimpleObject, Subscription as GqlSubscription};
+use futures_util::stream::{self, Stream, StreamExt};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{instrument, warn};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Enum)]
+pub enum ChangeLogKind {
+    Offline,
+    Resume,
+    Close,
+    Open,
+    Reopen,
+    Update,
+    Alert,
+    AcknowledgeAlert,
+    TargetStatus,
+    Batch,
+}
+
+/// flattened ChangeLogMsg, fields that don't apply to a kind are null
+#[derive(Clone, Debug, PartialEq, Eq, SimpleObject)]
+pub struct ChangeLogEvent {
+    pub kind: ChangeLogKind,
+    pub issue: Option<i32>,
+    pub alert: Option<i32>,
+    pub target: Option<String>,
+    pub status: Option<TargetStatus>,
+    pub title: Option<String>,
+    pub comment: Option<String>,
+    pub operator: Option<String>,
+    /// the changes a bulk mutation made, only set on Batch
+    pub batch: Option<Vec<ChangeLogEvent>>,
+}
+
+impl ChangeLogEvent {
+    fn new(kind: ChangeLogKind) -> Self {
+        Self {
+            kind,
+            issue: None,
+            alert: None,
+            target: None,
+            status: None,
+            title: None,
+            comment: None,
+            operator: None,
+            batch: None,
+        }
+    }
+}
+
+impl From<ChangeLogMsg> for ChangeLogEvent {
+    fn from(m: ChangeLogMsg) -> Self {
+        match m {
+            ChangeLogMsg::Offline { target, operator } => Self {
+                target: Some(target),
+                operator: Some(operator),
+                ..Self::new(ChangeLogKind::Offline)
+            },
+            ChangeLogMsg::Resume { target, operator } => Self {
+                target: Some(target),
+                operator: Some(operator),
+                ..Self::new(ChangeLogKind::Resume)
+            },
+            ChangeLogMsg::Close {
+                issue,
+                title,
+                comment,
+                operator,
+            } => Self {
+                issue: Some(issue),
+                title: Some(title),
+                comment: Some(comment),
+                operator: Some(operator),
+                ..Self::new(ChangeLogKind::Close)
+            },
+            ChangeLogMsg::Open {
+                issue,
+                title,
+                operator,
+            } => Self {
+                issue: Some(issue),
+                title: Some(title),
+                operator: Some(operator),
+                ..Self::new(ChangeLogKind::Open)
+            },
+            ChangeLogMsg::Reopen {
+                issue,
+                title,
+                comment,
+                operator,
+            } => Self {
+                issue: Some(issue),
+                title: Some(title),
+                comment: Some(comment),
+                operator: Some(operator),
+                ..Self::new(ChangeLogKind::Reopen)
+            },
+            ChangeLogMsg::Update {
+                issue,
+                title,
+                operator,
+            } => Self {
+                issue: Some(issue),
+                title: Some(title),
+                operator: Some(operator),
+                ..Self::new(ChangeLogKind::Update)
+            },
+            ChangeLogMsg::Alert { alert, title } => Self {
+                alert: Some(alert),
+                title: Some(title),
+                ..Self::new(ChangeLogKind::Alert)
+            },
+            ChangeLogMsg::AcknowledgeAlert { alert, operator } => Self {
+                alert: Some(alert),
+                operator: Some(operator),
+                ..Self::new(ChangeLogKind::AcknowledgeAlert)
+            },
+            ChangeLogMsg::TargetStatus { target, status } => Self {
+                target: Some(target),
+                status: Some(status),
+                ..Self::new(ChangeLogKind::TargetStatus)
+            },
+            ChangeLogMsg::Batch { operator, messages } => Self {
+                operator: Some(operator),
+                batch: Some(messages.into_iter().map(Self::from).collect()),
+                ..Self::new(ChangeLogKind::Batch)
+            },
+        }
+    }
+}
+
+/// every changelog message sent after subscribing
+fn events(ctx: &Context<'_>) -> impl Stream<Item = ChangeLogMsg> + use<> {
+    let rx = ctx
+        .data::<broadcast::Sender<ChangeLogMsg>>()
+        .unwrap()
+        .subscribe();
+    stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(m) => return Some((m, rx)),
+                Err(RecvError::Lagged(n)) => {
+                    warn!("subscriber fell behind, skipped {} messages", n);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+/// like [events] but with batches split into the messages they hold
+fn flat_events(ctx: &Context<'_>) -> impl Stream<Item = ChangeLogMsg> + use<> {
+    events(ctx).flat_map(|m| stream::iter(m.flatten()))
+}
+
+#[derive(Debug)]
+pub struct Subscription;
+
+#[GqlSubscription]
+impl Subscription {
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueRead)")]
+    #[instrument(skip(ctx))]
+    async fn changelog<'a>(&self, ctx: &Context<'a>) -> impl Stream<Item = ChangeLogEvent> + use<> {
+        events(ctx).map(ChangeLogEvent::from)
+    }
+
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueRead)")]
+    #[instrument(skip(ctx))]
+    async fn issue_changed<'a>(
+        &self,
+        ctx: &Context<'a>,
+        issue: Option<i32>,
+    ) -> impl Stream<Item = issue::Model> + use<> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().clone();
+        flat_events(ctx).filter_map(move |m| {
+            let db = db.clone();
+            async move {
+                let id = match m {
+                    ChangeLogMsg::Open { issue: i, .. }
+                    | ChangeLogMsg::Close { issue: i, .. }
+                    | ChangeLogMsg::Reopen { issue: i, .. }
+                    | ChangeLogMsg::Update { issue: i, .. } => i,
+                    _ => return None,
+                };
+                if issue.is_some_and(|i| i != id) {
+                    return None;
+                }
+                Issue::find_by_id(id).one(db.as_ref()).await.ok().flatten()
+            }
+        })
+    }
+
+    #[graphql(guard = "CapabilityChecker::new(Capability::IssueRead)")]
+    #[instrument(skip(ctx))]
+    async fn target_status_changed<'a>(
+        &self,
+        ctx: &Context<'a>,
+        target: Option<String>,
+    ) -> impl Stream<Item = target::Model> + use<> {
+        let db = ctx.data::<Arc<DatabaseConnection>>().unwrap().clone();
+        flat_events(ctx).filter_map(move |m| {
+            let db = db.clone();
+            let filter = target.clone();
+            async move {
+                let ChangeLogMsg::TargetStatus { target: t, .. } = m else {
+                    return None;
+                };
+                if filter.is_some_and(|f| f != t) {
+                    return None;
+                }
+                Target::find()
+                    .filter(target::Column::Name.eq(t))
+                    .one(db.as_ref())
+                    .await
+                    .ok()
+                    .flatten()
+            }
+        })
+    }
+}
@@ -0,0 +1,195 @@
+use crate::cluster::RegexCluster;
+use crate::entities::issue::{self, IssueStatus, ToOffline};
+use crate::entities::prelude::*;
+use crate::entities::target::{self, TargetStatus};
+use crate::sync::{self, StateMismatch, SyncPass, SyncStatus};
+use async_graphql::SimpleObject;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, Iterable, QueryFilter, QuerySelect};
+use std::collections::HashMap;
+use tracing::instrument;
+
+#[derive(Clone, Debug, PartialEq, Eq, SimpleObject)]
+pub struct TargetStatusCount {
+    pub status: TargetStatus,
+    pub count: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, SimpleObject)]
+pub struct IssueStatusCount {
+    pub status: IssueStatus,
+    pub count: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, SimpleObject)]
+pub struct ToOfflineCount {
+    /// None counts issues that don't offline anything
+    pub to_offline: Option<ToOffline>,
+    pub count: i64,
+}
+
+/// everything a dashboard needs in one call
+#[derive(Clone, Debug, PartialEq, Eq, SimpleObject)]
+pub struct ClusterSummary {
+    pub targets: Vec<TargetStatusCount>,
+    pub issues: Vec<IssueStatusCount>,
+    /// counts of issues that aren't Closed
+    pub to_offline: Vec<ToOfflineCount>,
+    pub mismatched: Vec<StateMismatch>,
+    /// None until the sync loop completes a pass
+    pub last_sync: Option<SyncPass>,
+}
+
+#[instrument(skip(db, cluster, status))]
+pub async fn cluster_summary(
+    db: &DatabaseConnection,
+    cluster: &RegexCluster,
+    status: &SyncStatus,
+) -> ClusterSummary {
+    // group in the db, every status is listed even if nothing is in it
+    let targets: HashMap<TargetStatus, i64> = Target::find()
+        .select_only()
+        .column(target::Column::Status)
+        .column_as(target::Column::Id.count(), "count")
+        .group_by(target::Column::Status)
+        .into_tuple()
+        .all(db)
+        .await
+        .unwrap()
+        .into_iter()
+        .collect();
+    let issues: Vec<(IssueStatus, i64)> = Issue::find()
+        .select_only()
+        .column(issue::Column::Status)
+        .column_as(issue::Column::Id.count(), "count")
+        .group_by(issue::Column::Status)
+        .into_tuple()
+        .all(db)
+        .await
+        .unwrap();
+    let to_offline: Vec<(Option<ToOffline>, i64)> = Issue::find()
+        .select_only()
+        .column(issue::Column::ToOffline)
+        .column_as(issue::Column::Id.count(), "count")
+        .filter(issue::Column::Status.ne(IssueStatus::Closed))
+        .group_by(issue::Column::ToOffline)
+        .into_tuple()
+        .all(db)
+        .await
+        .unwrap();
+
+    ClusterSummary {
+        targets: TargetStatus::iter()
+            .map(|s| TargetStatusCount {
+                status: s,
+                count: targets.get(&s).copied().unwrap_or(0),
+            })
+            .collect(),
+        issues: IssueStatus::iter()
+            .map(|s| IssueStatusCount {
+                status: s,
+                count: issues.iter().find(|(i, _)| *i == s).map_or(0, |(_, c)| *c),
+            })
+            .collect(),
+        to_offline: ToOffline::iter()
+            .map(Some)
+            .chain([None])
+            .map(|t| ToOfflineCount {
+                to_offline: t,
+                count: to_offline
+                    .iter()
+                    .find(|(o, _)| *o == t)
+                    .map_or(0, |(_, c)| *c),
+            })
+            .collect(),
+        mismatched: sync::state_mismatches(db, cluster).await,
+        last_sync: status.last_pass(),
+    }
+}
+
+#[tokio::test]
+async fn summary_counts() {
+    use tokio::sync::mpsc;
+    let db = crate::setup::test_db().await;
+    let (cluster, _) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+    for (target, to_offline) in [("gu0001", Some(ToOffline::Node)), ("gu0003", None)] {
+        let new_issue = super::NewIssue::new(
+            None,
+            "broken".to_string(),
+            "broken".to_string(),
+            target.to_string(),
+            to_offline,
+            &cluster,
+        )
+        .unwrap();
+        super::mutation::issue_open(&new_issue, "tester", &db, &tx, &cluster)
+            .await
+            .unwrap();
+    }
+
+    let summary = cluster_summary(&db, &cluster, &SyncStatus::default()).await;
+    assert_eq!(
+        summary.targets,
+        vec![
+            TargetStatusCount {
+                status: TargetStatus::Online,
+                count: 2
+            },
+            TargetStatusCount {
+                status: TargetStatus::Draining,
+                count: 0
+            },
+            TargetStatusCount {
+                status: TargetStatus::Offline,
+                count: 0
+            },
+            TargetStatusCount {
+                status: TargetStatus::Down,
+                count: 0
+            },
+        ]
+    );
+    assert_eq!(
+        summary
+            .issues
+            .iter()
+            .find(|c| c.status == IssueStatus::Opening)
+            .unwrap()
+            .count,
+        2
+    );
+    assert_eq!(
+        summary.to_offline,
+        vec![
+            ToOfflineCount {
+                to_offline: Some(ToOffline::Node),
+                count: 1
+            },
+            ToOfflineCount {
+                to_offline: Some(ToOffline::Card),
+                count: 0
+            },
+            ToOfflineCount {
+                to_offline: Some(ToOffline::Blade),
+                count: 0
+            },
+            ToOfflineCount {
+                to_offline: None,
+                count: 1
+            },
+        ]
+    );
+    // gu0003's issue doesn't offline it, but an open issue still expects the node down
+    assert_eq!(
+        summary
+            .mismatched
+            .iter()
+            .map(|m| (m.node.as_str(), m.desired_state))
+            .collect::<Vec<_>>(),
+        vec![
+            ("gu0001", TargetStatus::Offline),
+            ("gu0003", TargetStatus::Down)
+        ]
+    );
+    assert_eq!(summary.last_sync, None);
+}
@@ -13,18 +13,41 @@ pub async fn setup_and_connect(db_url: &str) -> Result<DatabaseConnection, DbErr
         .acquire_timeout(Duration::from_secs(10))
         .max_lifetime(Duration::from_secs(120));
     let db = Database::connect(opt).await.unwrap();
+    migrate(&db).await?;
+    Ok(db)
+}
 
-    let schema_manager = SchemaManager::new(&db);
+async fn migrate(db: &DatabaseConnection) -> Result<(), DbErr> {
+    let schema_manager = SchemaManager::new(db);
 
     if !schema_manager.has_table("issue").await?
         || !schema_manager.has_table("comment").await?
         || !schema_manager.has_table("target").await?
     {
-        Migrator::refresh(&db).await?;
+        Migrator::refresh(db).await?;
     }
+    // bring existing dbs up to date
+    Migrator::up(db, None).await?;
     assert!(schema_manager.has_table("issue").await?);
     assert!(schema_manager.has_table("comment").await?);
     assert!(schema_manager.has_table("target").await?);
+    assert!(schema_manager.has_table("alert").await?);
+    assert!(schema_manager.has_table("issue_fts").await?);
+    assert!(schema_manager.has_table("comment_edit").await?);
+    assert!(schema_manager.has_table("issue_target").await?);
+    assert!(schema_manager.has_table("issue_label").await?);
+    assert!(schema_manager.has_table("api_key").await?);
+    assert!(schema_manager.has_table("audit_event").await?);
+    Ok(())
+}
 
-    Ok(db)
+/// throwaway in memory db for tests, gone once the connection is dropped
+#[cfg(test)]
+pub async fn test_db() -> DatabaseConnection {
+    // every connection to :memory: gets its own empty db, so the pool holds exactly one
+    let mut opt = ConnectOptions::new("sqlite::memory:");
+    opt.max_connections(1).min_connections(1);
+    let db = Database::connect(opt).await.unwrap();
+    migrate(&db).await.unwrap();
+    db
 }
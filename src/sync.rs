@@ -1,30 +1,36 @@
-use crate::cluster::scheduler::PbsScheduler;
+use crate::audit::{self, Actor};
 use crate::cluster::ClusterTrait;
 use crate::cluster::RegexCluster;
-use crate::conf::Conf;
+use crate::conf::{Conf, SyncLimit};
 use crate::entities;
-use crate::entities::issue::IssueStatus;
+use crate::entities::audit_event::AuditAction;
 use crate::entities::issue::ToOffline;
+use crate::entities::issue::{Category, IssueStatus, Severity};
 use crate::entities::target::TargetStatus;
 use crate::model::mutation;
 use crate::ChangeLogMsg;
+use async_graphql::SimpleObject;
+use chrono::{NaiveDateTime, Utc};
 use sea_orm::prelude::Expr;
 use sea_orm::Condition;
 use sea_orm::EntityTrait;
-use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, QueryFilter, QuerySelect};
-use std::collections::HashMap;
-use std::sync::Arc;
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, QueryFilter, QueryOrder, QuerySelect};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 
-use sea_orm::DatabaseConnection;
-use std::time::Duration;
+use sea_orm::{ConnectionTrait, DatabaseConnection, TransactionTrait};
+use std::time::{Duration, Instant};
 use tokio::time;
 use tracing::{debug, info, instrument, trace, warn};
 
+/// expected state of every node with an open issue, and the issue responsible for it
 async fn get_expected_state(
     db: &DatabaseConnection,
     cluster: &RegexCluster,
-) -> HashMap<String, TargetStatus> {
+) -> HashMap<String, (TargetStatus, String)> {
     let mut des_state = HashMap::new();
 
     let open_issues = entities::issue::Entity::find()
@@ -37,14 +43,17 @@ async fn get_expected_state(
         .await
         .unwrap();
     for iss in open_issues {
-        let targets = iss.get_related(db, cluster).await;
+        let targets = iss.related_names(db, cluster).await;
+        let reason = format!("issue {}: {}", iss.id, iss.title);
         if iss.to_offline.is_some() {
             for t in targets {
-                des_state.insert(t.name, TargetStatus::Offline);
+                des_state.insert(t, (TargetStatus::Offline, reason.clone()));
             }
         } else {
             for t in targets {
-                des_state.entry(t.name).or_insert(TargetStatus::Down);
+                des_state
+                    .entry(t)
+                    .or_insert((TargetStatus::Down, reason.clone()));
             }
         };
     }
@@ -52,97 +61,449 @@ async fn get_expected_state(
     des_state
 }
 
-#[instrument(skip(db, conf))]
-pub async fn cluster_sync(db: Arc<DatabaseConnection>, conf: Conf, tx: mpsc::Sender<ChangeLogMsg>) {
+#[instrument(skip(db, cluster, conf))]
+pub async fn cluster_sync(
+    db: Arc<DatabaseConnection>,
+    cluster: Arc<RegexCluster>,
+    conf: Conf,
+    tx: mpsc::Sender<ChangeLogMsg>,
+    status: Arc<SyncStatus>,
+) {
+    let opts = SyncOptions::from(&conf);
     let mut interval = time::interval(Duration::from_secs(conf.poll_interval));
-    let mut cluster = RegexCluster::new(conf.node_types.clone(), PbsScheduler::new());
     // don't let ticks stack up if a sync takes longer than interval
     interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
     loop {
         interval.tick().await;
-        // don't want multiple ctt threads messing with scheduler concurrently
-        let db = db.as_ref();
-        info!("performing sync with pbs");
-
-        let to_open = entities::issue::Entity::find()
-            .filter(entities::issue::Column::Status.eq(IssueStatus::Opening))
-            .all(db)
-            .await
-            .unwrap();
-        let to_close = entities::issue::Entity::find()
-            .filter(entities::issue::Column::Status.eq(IssueStatus::Closing))
-            .all(db)
-            .await
-            .unwrap();
-
-        let pbs_node_state = cluster.nodes_status();
-        // TODO: Come up with expected state for all nodes instead of doing it for each node to
-        // improve perf
-        // assume nodes are online, then iter through all !closed issues setting nodes to
-        // offline/down for each issue, this should be faster since there are way less open issues
-        // than nodes + node siblings + node cousins
-        if let Err(e) = pbs_node_state {
-            warn!("could not get node state from cluster: {}", e);
-            continue;
+        let start = Instant::now();
+        if let Ok(plan) = sync_once(db.as_ref(), cluster.as_ref(), &tx, &opts).await {
+            if opts.dry_run {
+                for p in plan {
+                    info!("dry run: {:?}", p);
+                }
+            } else {
+                status.record(start);
+            }
         }
-        let pbs_node_state = pbs_node_state.unwrap();
-        let mut ctt_node_state = get_ctt_nodes(db).await;
-        let desired_state = get_expected_state(db, &cluster).await;
-
-        //add any pbs nodes not in ctt into ctt for tracking
-        pbs_node_state
-            .keys()
-            .filter(|t| !ctt_node_state.contains_key(*t))
-            .filter(|t| cluster.real_node(t))
-            .collect::<Vec<&String>>()
-            .iter()
-            .for_each(|t| {
-                ctt_node_state.insert(t.to_string(), TargetStatus::Online);
-            });
+    }
+}
 
-        // sync ctt and pbs
-        for (target, old_state) in &ctt_node_state {
-            if let Some((new_state, pbs_comment)) = pbs_node_state.get(target) {
-                handle_transition(
-                    target,
-                    pbs_comment,
-                    old_state,
-                    desired_state.get(target),
-                    new_state,
-                    db,
-                    &tx,
-                    &mut cluster,
-                )
-                .await;
-            } else {
-                warn!("{} not found in pbs", target);
-                if let Some(new_issue) = crate::model::NewIssue::new(
-                    None,
-                    "Node not found in pbs".to_string(),
-                    "Node not found in pbs".to_string(),
-                    target.to_string(),
-                    None,
-                    &cluster,
-                ) {
-                    mutation::issue_open(&new_issue, "ctt", db, &tx, &cluster)
-                        .await
-                        .unwrap();
+/// when the last successful sync pass finished and how long it took, dry runs don't count
+#[derive(Copy, Clone, Debug, PartialEq, Eq, SimpleObject)]
+pub struct SyncPass {
+    pub finished_at: NaiveDateTime,
+    pub duration_ms: u64,
+}
+
+/// sync loop progress, shared with the api
+#[derive(Debug, Default)]
+pub struct SyncStatus {
+    last: Mutex<Option<SyncPass>>,
+}
+
+impl SyncStatus {
+    pub fn last_pass(&self) -> Option<SyncPass> {
+        *self.last.lock().unwrap()
+    }
+
+    fn record(&self, start: Instant) {
+        *self.last.lock().unwrap() = Some(SyncPass {
+            finished_at: Utc::now().naive_utc(),
+            duration_ms: start.elapsed().as_millis() as u64,
+        });
+    }
+}
+
+/// a node whose current state doesn't satisfy what its open issues require
+#[derive(Clone, Debug, PartialEq, Eq, SimpleObject)]
+pub struct StateMismatch {
+    pub node: String,
+    pub actual_state: TargetStatus,
+    pub desired_state: TargetStatus,
+    /// issue requiring the desired state, None if the node should just be online
+    pub reason: Option<String>,
+}
+
+/// nodes where the state ctt last saw differs from the one open issues require
+///
+/// draining counts as offline, and any state but online satisfies a down node
+#[instrument(skip(db, cluster))]
+pub async fn state_mismatches(
+    db: &DatabaseConnection,
+    cluster: &RegexCluster,
+) -> Vec<StateMismatch> {
+    let mut expected = get_expected_state(db, cluster).await;
+    let mut mismatches: Vec<StateMismatch> = get_ctt_nodes(db)
+        .await
+        .into_iter()
+        .filter_map(|(node, actual)| {
+            let (desired, reason) = match expected.remove(&node) {
+                Some((s, r)) => (s, Some(r)),
+                None => (TargetStatus::Online, None),
+            };
+            let satisfied = match desired {
+                TargetStatus::Online => actual == TargetStatus::Online,
+                TargetStatus::Offline | TargetStatus::Draining => {
+                    matches!(actual, TargetStatus::Offline | TargetStatus::Draining)
                 }
+                TargetStatus::Down => actual != TargetStatus::Online,
+            };
+            (!satisfied).then_some(StateMismatch {
+                node,
+                actual_state: actual,
+                desired_state: desired,
+                reason,
+            })
+        })
+        .collect();
+    mismatches.sort_by(|a, b| a.node.cmp(&b.node));
+    mismatches
+}
+
+/// what a sync pass did (or would do in a dry run) to a node
+#[derive(Copy, Clone, Debug, PartialEq, Eq, async_graphql::Enum, Serialize, Deserialize)]
+pub enum SyncAction {
+    Offline,
+    Resume,
+    OpenIssue,
+    CloseIssues,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, SimpleObject, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub node: String,
+    /// state reported by the scheduler, None if the scheduler doesn't know the node
+    pub current_state: Option<TargetStatus>,
+    /// state required by open issues, None if nothing requires the node to be down
+    pub desired_state: Option<TargetStatus>,
+    pub action: SyncAction,
+    pub reason: String,
+}
+
+/// how a sync pass behaves, normally taken from the config
+#[derive(Clone, Debug, Default)]
+pub struct SyncOptions {
+    /// leave the scheduler, issues, and node states untouched and only return the plan
+    pub dry_run: bool,
+    pub limit: SyncLimit,
+    /// see `Conf::reopen_window`
+    pub reopen_window: Option<u64>,
+    /// severity of issues opened for nodes the scheduler reports down
+    pub severity: Severity,
+}
+
+impl From<&Conf> for SyncOptions {
+    fn from(conf: &Conf) -> Self {
+        SyncOptions {
+            dry_run: conf.dry_run,
+            limit: conf.sync_limit.clone(),
+            reopen_window: conf.reopen_window,
+            severity: conf.auto_open_severity,
+        }
+    }
+}
+
+/// a single reconcile pass between ctt and the scheduler
+///
+/// when `opts.dry_run` is set the scheduler, issues, and node states are left untouched and the
+/// returned plan is all that happens
+///
+/// if the pass would offline or open issues on more nodes than `opts.limit` allows nothing is
+/// done, an alert is raised instead and sync stays paused until it is acknowledged
+#[instrument(skip(db, cluster, tx))]
+pub async fn sync_once(
+    db: &DatabaseConnection,
+    cluster: &RegexCluster,
+    tx: &mpsc::Sender<ChangeLogMsg>,
+    opts: &SyncOptions,
+) -> Result<Vec<PlanEntry>, String> {
+    let SyncOptions {
+        dry_run,
+        ref limit,
+        reopen_window,
+        severity,
+    } = *opts;
+    if !dry_run
+        && let Some(alert) = entities::alert::Entity::unacknowledged()
+            .one(db)
+            .await
+            .unwrap()
+    {
+        warn!("sync paused until alert {} is acknowledged", alert.id);
+        return Err(format!(
+            "sync paused until alert {} is acknowledged",
+            alert.id
+        ));
+    }
+    info!("performing sync with pbs");
+    let mut plan = vec![];
+
+    let to_open = entities::issue::Entity::find()
+        .filter(entities::issue::Column::Status.eq(IssueStatus::Opening))
+        .all(db)
+        .await
+        .unwrap();
+    let to_close = entities::issue::Entity::find()
+        .filter(entities::issue::Column::Status.eq(IssueStatus::Closing))
+        .all(db)
+        .await
+        .unwrap();
+
+    // a dry run mustn't change what the next real pass sees
+    let pbs_node_state = if dry_run {
+        cluster.peek_status()
+    } else {
+        cluster.nodes_status()
+    };
+    // TODO: Come up with expected state for all nodes instead of doing it for each node to
+    // improve perf
+    // assume nodes are online, then iter through all !closed issues setting nodes to
+    // offline/down for each issue, this should be faster since there are way less open issues
+    // than nodes + node siblings + node cousins
+    if let Err(e) = pbs_node_state {
+        warn!("could not get node state from cluster: {}", e);
+        return Err(e);
+    }
+    let pbs_node_state = pbs_node_state.unwrap();
+    let mut ctt_node_state = get_ctt_nodes(db).await;
+    let desired_state = get_expected_state(db, cluster).await;
+
+    //add any pbs nodes not in ctt into ctt for tracking
+    pbs_node_state
+        .keys()
+        .filter(|t| !ctt_node_state.contains_key(*t))
+        .filter(|t| cluster.real_node(t))
+        .collect::<Vec<&String>>()
+        .iter()
+        .for_each(|t| {
+            ctt_node_state.insert(t.to_string(), TargetStatus::Online);
+        });
+
+    // sync ctt and pbs
+    // work out everything the pass will do before doing any of it so the whole pass can be
+    // checked against the sync limit
+    let mut transitions = vec![];
+    for (target, old_state) in &ctt_node_state {
+        let expected_state = desired_state.get(target);
+        if let Some((new_state, pbs_comment)) = pbs_node_state.get(target) {
+            let (action, final_state) =
+                plan_transition(target, pbs_comment, expected_state, new_state, db, cluster).await;
+            if let Some((action, reason)) = &action {
+                plan.push(PlanEntry {
+                    node: target.to_string(),
+                    current_state: Some(*new_state),
+                    desired_state: expected_state.map(|(s, _)| *s),
+                    action: *action,
+                    reason: reason.to_string(),
+                });
             }
+            transitions.push((
+                target,
+                pbs_comment,
+                old_state,
+                new_state,
+                final_state,
+                action.map(|(a, _)| a),
+            ));
+        } else {
+            warn!("{} not found in pbs", target);
+            plan.push(PlanEntry {
+                node: target.to_string(),
+                current_state: None,
+                desired_state: expected_state.map(|(s, _)| *s),
+                action: SyncAction::OpenIssue,
+                reason: "Node not found in pbs".to_string(),
+            });
         }
+    }
+
+    if dry_run {
+        info!("pbs dry run complete");
+        return Ok(plan);
+    }
+    // everything the pass changes shares one correlation id in the audit log
+    let actor = Actor::sync();
+    check_limit(&plan, ctt_node_state.len(), limit, &actor, db, tx).await?;
 
-        for iss in to_open {
-            let mut i: entities::issue::ActiveModel = iss.into();
-            i.status = sea_orm::ActiveValue::Set(IssueStatus::Open);
-            i.update(db).await.unwrap();
+    for (target, pbs_comment, old_state, new_state, final_state, action) in transitions {
+        handle_transition(
+            target,
+            pbs_comment,
+            old_state,
+            new_state,
+            final_state,
+            action,
+            reopen_window,
+            severity,
+            &actor,
+            db,
+            tx,
+            cluster,
+        )
+        .await;
+    }
+    for p in plan.iter().filter(|p| p.current_state.is_none()) {
+        if let Some(new_issue) = detected_issue(&p.node, &p.reason, severity, cluster) {
+            open_or_reopen(&new_issue, reopen_window, &actor, db, tx, cluster).await;
         }
-        for iss in to_close {
-            let mut i: entities::issue::ActiveModel = iss.into();
-            i.status = sea_orm::ActiveValue::Set(IssueStatus::Closed);
-            i.update(db).await.unwrap();
+    }
+
+    // only promote issues nothing else moved during the pass, an Opening issue closed because
+    // its node came back up has to stay Closed
+    promote(
+        to_open.iter().map(|i| i.id).collect(),
+        IssueStatus::Opening,
+        IssueStatus::Open,
+        &actor,
+        db,
+    )
+    .await;
+    promote(
+        to_close.iter().map(|i| i.id).collect(),
+        IssueStatus::Closing,
+        IssueStatus::Closed,
+        &actor,
+        db,
+    )
+    .await;
+    info!("pbs sync complete");
+    Ok(plan)
+}
+
+/// move the issues in `ids` still in `from` to `to`, recording each one in the audit log
+#[instrument(skip(ids, actor, db))]
+async fn promote(
+    ids: Vec<i32>,
+    from: IssueStatus,
+    to: IssueStatus,
+    actor: &Actor,
+    db: &DatabaseConnection,
+) {
+    let txn = db.begin().await.unwrap();
+    let issues = entities::issue::Entity::find()
+        .filter(entities::issue::Column::Id.is_in(ids))
+        .filter(entities::issue::Column::Status.eq(from))
+        .all(&txn)
+        .await
+        .unwrap();
+    let mut update = entities::issue::Entity::update_many()
+        .col_expr(entities::issue::Column::Status, Expr::value(to))
+        .filter(entities::issue::Column::Id.is_in(issues.iter().map(|i| i.id)));
+    // closing is a change worth sorting by, opening isn't
+    let now = Utc::now().naive_utc();
+    if to == IssueStatus::Closed {
+        update = update.col_expr(entities::issue::Column::UpdatedAt, Expr::value(now));
+    }
+    update.exec(&txn).await.unwrap();
+    for issue in issues {
+        let before = audit::json(&issue);
+        let after = entities::issue::Model {
+            status: to,
+            updated_at: if to == IssueStatus::Closed {
+                now
+            } else {
+                issue.updated_at
+            },
+            ..issue
+        };
+        audit::record(
+            &txn,
+            actor,
+            AuditAction::Promote,
+            "issue",
+            after.id,
+            before,
+            audit::json(&after),
+        )
+        .await;
+    }
+    txn.commit().await.unwrap();
+}
+
+/// trip the circuit breaker if a pass would offline or open issues on too many nodes
+#[instrument(skip(db, tx, plan))]
+async fn check_limit(
+    plan: &[PlanEntry],
+    total: usize,
+    limit: &SyncLimit,
+    actor: &Actor,
+    db: &DatabaseConnection,
+    tx: &mpsc::Sender<ChangeLogMsg>,
+) -> Result<(), String> {
+    let nodes: BTreeSet<&str> = plan
+        .iter()
+        .filter(|p| matches!(p.action, SyncAction::Offline | SyncAction::OpenIssue))
+        .map(|p| p.node.as_str())
+        .collect();
+    // acknowledging an alert lets through the nodes it listed, everything else still counts
+    // towards the limit
+    let acked = entities::alert::Entity::find()
+        .filter(entities::alert::Column::AcknowledgedBy.is_not_null())
+        .filter(entities::alert::Column::ResumedAt.is_null())
+        .all(db)
+        .await
+        .unwrap();
+    let allowed: BTreeSet<String> = entities::alert_node::Entity::find()
+        .filter(entities::alert_node::Column::AlertId.is_in(acked.iter().map(|a| a.id)))
+        .all(db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|n| n.node)
+        .collect();
+    let nodes: BTreeSet<&str> = nodes
+        .into_iter()
+        .filter(|n| !allowed.contains(*n))
+        .collect();
+    if !limit.exceeded(nodes.len(), total) {
+        for a in acked {
+            info!("resuming sync after alert {}", a.id);
+            let mut a: entities::alert::ActiveModel = a.into();
+            a.resumed_at = ActiveValue::Set(Some(Utc::now().naive_utc()));
+            a.update(db).await.unwrap();
         }
-        info!("pbs sync complete");
+        return Ok(());
     }
+    let title = format!(
+        "Sync paused: {} of {} nodes would be offlined or have issues opened",
+        nodes.len(),
+        total
+    );
+    warn!("{}", title);
+    let alert = entities::alert::ActiveModel {
+        title: ActiveValue::Set(title.clone()),
+        description: ActiveValue::Set(crate::hostlist::compress(&nodes)),
+        ..Default::default()
+    };
+    let txn = db.begin().await.unwrap();
+    let alert = alert.insert(&txn).await.unwrap();
+    entities::alert_node::Entity::insert_many(nodes.iter().map(|n| {
+        entities::alert_node::ActiveModel {
+            alert_id: ActiveValue::Set(alert.id),
+            node: ActiveValue::Set(n.to_string()),
+        }
+    }))
+    .exec(&txn)
+    .await
+    .unwrap();
+    audit::record(
+        &txn,
+        actor,
+        AuditAction::RaiseAlert,
+        "alert",
+        alert.id,
+        None,
+        audit::json(&alert),
+    )
+    .await;
+    txn.commit().await.unwrap();
+    let _ = tx
+        .send(ChangeLogMsg::Alert {
+            alert: alert.id,
+            title: title.clone(),
+        })
+        .await;
+    Err(title)
 }
 
 #[instrument(skip(db))]
@@ -240,7 +601,12 @@ pub async fn related_closing(
 }
 
 #[instrument(skip(db))]
-pub async fn close_open_issues(target: &str, db: &DatabaseConnection, cluster: &RegexCluster) {
+pub async fn close_open_issues(
+    target: &str,
+    actor: &Actor,
+    db: &DatabaseConnection,
+    cluster: &RegexCluster,
+) {
     for issue in entities::target::Entity::from_name(target, db, cluster)
         .await
         .unwrap()
@@ -251,110 +617,268 @@ pub async fn close_open_issues(target: &str, db: &DatabaseConnection, cluster: &
         .unwrap()
     {
         let id = issue.id;
+        let before = audit::json(&issue);
         let mut i: entities::issue::ActiveModel = issue.into();
         i.status = ActiveValue::Set(IssueStatus::Closed);
-        i.update(db).await.unwrap();
-        let c = entities::comment::ActiveModel {
-            created_by: ActiveValue::Set("ctt".to_string()),
-            comment: ActiveValue::Set("node found up, assuming issue is resolved".to_string()),
-            issue_id: ActiveValue::Set(id),
-            ..Default::default()
-        };
+        i.updated_at = ActiveValue::Set(Utc::now().naive_utc());
+        let i = i.update(db).await.unwrap();
+        audit::record(
+            db,
+            actor,
+            AuditAction::AutoClose,
+            "issue",
+            id,
+            before,
+            audit::json(&i),
+        )
+        .await;
+        let c = entities::comment::ActiveModel::system(
+            id,
+            "ctt",
+            "node found up, assuming issue is resolved".to_string(),
+        );
         c.insert(db).await.unwrap();
     }
 }
 
-#[instrument(skip(db, tx))]
-#[allow(clippy::too_many_arguments)]
-async fn handle_transition(
+/// work out what needs to happen to a node, and the state it will end up in, without doing it
+#[instrument(skip(db))]
+async fn plan_transition(
     target: &str,
     new_comment: &str,
-    old_state: &TargetStatus,
-    expected_state: Option<&TargetStatus>,
+    expected_state: Option<&(TargetStatus, String)>,
     new_state: &TargetStatus,
     db: &DatabaseConnection,
-    tx: &mpsc::Sender<ChangeLogMsg>,
-    cluster: &mut RegexCluster,
-) {
+    cluster: &RegexCluster,
+) -> (Option<(SyncAction, String)>, TargetStatus) {
     //let (expected_state, comment) = desired_state(target, db, cluster).await;
 
     //dont use old_state to figure out how to handle nodes
     //things could have changed between when it was collected and now, so only consider
     //the current state (new_state) and the expected_state
-    let final_state = match expected_state {
-        Some(TargetStatus::Draining) => panic!("Expected state is never Draining"),
-        Some(TargetStatus::Online) | None => {
+    match expected_state {
+        Some((TargetStatus::Draining, _)) => panic!("Expected state is never Draining"),
+        Some((TargetStatus::Online, _)) | None => {
             if *new_state == TargetStatus::Online {
-                TargetStatus::Online
+                (None, TargetStatus::Online)
             } else if !related_closing(target, db, cluster).await.is_empty() {
-                info!("resuming {}, all open issues are Closing", target);
-                cluster.release_node(target).unwrap();
-
-                let _ = tx
-                    .send(ChangeLogMsg::Resume {
-                        target: target.to_string(),
-                        operator: "ctt".to_string(),
-                    })
-                    .await;
-                TargetStatus::Online
+                (
+                    Some((
+                        SyncAction::Resume,
+                        "all open issues are Closing".to_string(),
+                    )),
+                    TargetStatus::Online,
+                )
             } else {
                 // expected node to be online, but it wasn't so open an issue
                 // we know no issues are currently open since expected state
                 // would not be online if there were
-                if let Some(new_issue) = crate::model::NewIssue::new(
-                    None,
-                    new_comment.to_string(),
-                    new_comment.to_string(),
-                    target.to_string(),
-                    None,
-                    cluster,
-                ) {
-                    info!("opening issue for {}: {}", target, new_comment);
-                    mutation::issue_open(&new_issue, "ctt", db, tx, cluster)
-                        .await
-                        .unwrap();
-                }
-                *new_state
+                (
+                    Some((SyncAction::OpenIssue, new_comment.to_string())),
+                    *new_state,
+                )
             }
         }
-        Some(TargetStatus::Offline) => match new_state {
-            TargetStatus::Draining => TargetStatus::Draining,
-            TargetStatus::Offline => TargetStatus::Offline,
+        Some((TargetStatus::Offline, reason)) => match new_state {
+            TargetStatus::Draining => (None, TargetStatus::Draining),
+            TargetStatus::Offline => (None, TargetStatus::Offline),
             state => {
-                info!("{} found in state {:?}, expected offline", target, state);
-                cluster.offline_node(target, new_comment).unwrap();
-                let _ = tx
-                    .send(ChangeLogMsg::Offline {
-                        target: target.to_string(),
-                        operator: "ctt".to_string(),
-                    })
-                    .await;
+                let action = Some((SyncAction::Offline, reason.to_string()));
                 if *state == TargetStatus::Down {
-                    TargetStatus::Offline
+                    (action, TargetStatus::Offline)
                 } else {
                     // node was online, might have running jobs
-                    TargetStatus::Draining
+                    (action, TargetStatus::Draining)
                 }
             }
         },
-        Some(TargetStatus::Down) => match new_state {
-            TargetStatus::Draining => TargetStatus::Draining,
-            TargetStatus::Down => TargetStatus::Down,
-            TargetStatus::Offline => TargetStatus::Offline,
-            TargetStatus::Online => {
-                info!("closing open issues for {}", target);
-                // know it is safe to simply close all issue open against the node because
-                // expected status would be Offline if there were any issues with ToOffline set
-                close_open_issues(target, db, cluster).await;
-                TargetStatus::Online
-            }
+        Some((TargetStatus::Down, _)) => match new_state {
+            TargetStatus::Draining => (None, TargetStatus::Draining),
+            TargetStatus::Down => (None, TargetStatus::Down),
+            TargetStatus::Offline => (None, TargetStatus::Offline),
+            TargetStatus::Online => (
+                Some((
+                    SyncAction::CloseIssues,
+                    "node found up, assuming issue is resolved".to_string(),
+                )),
+                TargetStatus::Online,
+            ),
         },
-    };
+    }
+}
+
+/// issue for a node the scheduler reported down, titled with the scheduler's reason
+fn detected_issue(
+    target: &str,
+    reason: &str,
+    severity: Severity,
+    cluster: &RegexCluster,
+) -> Option<crate::model::NewIssue> {
+    crate::model::NewIssue::new(
+        None,
+        reason.to_string(),
+        reason.to_string(),
+        target.to_string(),
+        None,
+        cluster,
+    )
+    .map(|i| {
+        i.with_category(Category::SchedulerDetected)
+            .with_severity(severity)
+    })
+}
+
+/// open an issue for a node, or reopen the last one with the same title if it was closed
+/// within `reopen_window` seconds
+#[instrument(skip(db, tx, cluster))]
+async fn open_or_reopen(
+    new_issue: &crate::model::NewIssue,
+    reopen_window: Option<u64>,
+    actor: &Actor,
+    db: &DatabaseConnection,
+    tx: &mpsc::Sender<ChangeLogMsg>,
+    cluster: &RegexCluster,
+) {
+    if let Some(window) = reopen_window
+        && let Some(target) =
+            entities::target::Entity::from_name(new_issue.target(), db, cluster).await
+        && let Some(recent) = target
+            .issues()
+            .filter(entities::issue::Column::Status.eq(IssueStatus::Closed))
+            .filter(entities::issue::Column::Title.eq(new_issue.title()))
+            .filter(
+                entities::issue::Column::UpdatedAt
+                    .gte(Utc::now().naive_utc() - chrono::Duration::seconds(window as i64)),
+            )
+            .order_by_desc(entities::issue::Column::UpdatedAt)
+            .one(db)
+            .await
+            .unwrap()
+    {
+        info!("reopening issue {} for {}", recent.id, target.name);
+        let reopened = mutation::issue_reopen(
+            recent.id,
+            "ctt",
+            format!(
+                "{} seen again within {}s of closing",
+                new_issue.title(),
+                window
+            ),
+            db,
+            tx,
+        )
+        .await
+        .unwrap();
+        audit::record(
+            db,
+            actor,
+            AuditAction::AutoReopen,
+            "issue",
+            reopened.id,
+            audit::json(&recent),
+            audit::json(&reopened),
+        )
+        .await;
+        return;
+    }
+    let opened = mutation::issue_open(new_issue, "ctt", db, tx, cluster)
+        .await
+        .unwrap();
+    // issue_open hands back the Open issue with the same title instead of a new one
+    if opened.status == IssueStatus::Opening {
+        audit::record(
+            db,
+            actor,
+            AuditAction::AutoOpen,
+            "issue",
+            opened.id,
+            None,
+            audit::json(&opened),
+        )
+        .await;
+    }
+}
+
+#[instrument(skip(db, tx))]
+#[allow(clippy::too_many_arguments)]
+async fn handle_transition(
+    target: &str,
+    new_comment: &str,
+    old_state: &TargetStatus,
+    new_state: &TargetStatus,
+    final_state: TargetStatus,
+    action: Option<SyncAction>,
+    reopen_window: Option<u64>,
+    severity: Severity,
+    actor: &Actor,
+    db: &DatabaseConnection,
+    tx: &mpsc::Sender<ChangeLogMsg>,
+    cluster: &RegexCluster,
+) {
+    match action {
+        None => (),
+        Some(SyncAction::Resume) => {
+            info!("resuming {}, all open issues are Closing", target);
+            cluster.release_node(target).unwrap();
+            audit::record(
+                db,
+                actor,
+                AuditAction::Resume,
+                "node",
+                target,
+                Some(json!({ "state": old_state })),
+                Some(json!({ "state": final_state })),
+            )
+            .await;
+
+            let _ = tx
+                .send(ChangeLogMsg::Resume {
+                    target: target.to_string(),
+                    operator: "ctt".to_string(),
+                })
+                .await;
+        }
+        Some(SyncAction::OpenIssue) => {
+            if let Some(new_issue) = detected_issue(target, new_comment, severity, cluster) {
+                info!("opening issue for {}: {}", target, new_comment);
+                open_or_reopen(&new_issue, reopen_window, actor, db, tx, cluster).await;
+            }
+        }
+        Some(SyncAction::Offline) => {
+            info!(
+                "{} found in state {:?}, expected offline, will be {:?}",
+                target, new_state, final_state
+            );
+            cluster.offline_node(target, new_comment).unwrap();
+            audit::record(
+                db,
+                actor,
+                AuditAction::Offline,
+                "node",
+                target,
+                Some(json!({ "state": old_state })),
+                Some(json!({ "state": final_state, "comment": new_comment })),
+            )
+            .await;
+            let _ = tx
+                .send(ChangeLogMsg::Offline {
+                    target: target.to_string(),
+                    operator: "ctt".to_string(),
+                })
+                .await;
+        }
+        Some(SyncAction::CloseIssues) => {
+            info!("closing open issues for {}", target);
+            // know it is safe to simply close all issue open against the node because
+            // expected status would be Offline if there were any issues with ToOffline set
+            close_open_issues(target, actor, db, cluster).await;
+        }
+    }
     //dont update state if it hasn't changed
     if *old_state != final_state {
         debug!(
-            "{}: current: {:?}, expected: {:?}, final: {:?}",
-            target, new_state, expected_state, final_state
+            "{}: old: {:?}, action: {:?}, final: {:?}",
+            target, old_state, action, final_state
         );
         let node = if let Some(tmp) = entities::target::Entity::from_name(target, db, cluster).await
         {
@@ -367,86 +891,67 @@ async fn handle_transition(
         let mut updated_target: entities::target::ActiveModel = node.into();
         updated_target.status = ActiveValue::Set(final_state);
         updated_target.update(db).await.unwrap();
+        let _ = tx
+            .send(ChangeLogMsg::TargetStatus {
+                target: target.to_string(),
+                status: final_state,
+            })
+            .await;
     }
 }
 
 //needed for issue to_offline mutation api calls
+/// only looks nodes up, so it is safe to call from queries, a node ctt isn't tracking yet has no
+/// issues of its own
 #[instrument(skip(db))]
 pub async fn desired_state(
     target: &str,
-    db: &DatabaseConnection,
+    db: &impl ConnectionTrait,
     cluster: &RegexCluster,
 ) -> (TargetStatus, String) {
-    let t = entities::target::Entity::from_name(target, db, cluster).await;
-    let t = match t {
-        None => return (TargetStatus::Offline, "Not a real node".to_string()),
-        Some(t) => {
-            if let Some(iss) = t
-                .issues()
-                .filter(
-                    entities::issue::Column::Status
-                        .is_in([IssueStatus::Open, IssueStatus::Opening]),
-                )
-                .filter(Expr::col(entities::issue::Column::ToOffline).is_not_null())
+    if !cluster.real_node(target) {
+        return (TargetStatus::Offline, "Not a real node".to_string());
+    }
+    let open = [IssueStatus::Open, IssueStatus::Opening];
+    let t = entities::target::Entity::find_by_name(target, db).await;
+    if let Some(t) = &t
+        && let Some(iss) = t
+            .issues()
+            .filter(entities::issue::Column::Status.is_in(open))
+            .filter(Expr::col(entities::issue::Column::ToOffline).is_not_null())
+            .one(db)
+            .await
+            .unwrap()
+    {
+        trace!("Offline due to node ticket");
+        return (TargetStatus::Offline, iss.title);
+    }
+    for (group, to_offline) in [
+        (cluster.siblings(target), ToOffline::Card),
+        (cluster.cousins(target), ToOffline::Blade),
+    ] {
+        for t in entities::target::Entity::find_by_names(&group, db).await {
+            if t.issues()
+                .filter(entities::issue::Column::Status.is_in(open))
+                .filter(entities::issue::Column::ToOffline.eq(Some(to_offline)))
                 .one(db)
                 .await
                 .unwrap()
+                .is_some()
             {
-                trace!("Offline due to node ticket");
-                return (TargetStatus::Offline, iss.title);
+                trace!("Offline due to {:?} wide ticket", to_offline);
+                return (TargetStatus::Offline, format!("{} sibling", target));
             }
-            t
         }
-    };
-    for c in cluster.siblings(target) {
-        match entities::target::Entity::from_name(&c, db, cluster).await {
-            None => warn!("expected sibling {} doesn't exist", c),
-            Some(t) => {
-                if t.issues()
-                    .filter(
-                        entities::issue::Column::Status
-                            .is_in([IssueStatus::Open, IssueStatus::Opening]),
-                    )
-                    .filter(entities::issue::Column::ToOffline.eq(Some(ToOffline::Card)))
-                    .one(db)
-                    .await
-                    .unwrap()
-                    .is_some()
-                {
-                    trace!("Offline due to card wide ticket");
-                    return (TargetStatus::Offline, format!("{} sibling", &target));
-                }
-            }
-        };
     }
-    for c in cluster.cousins(target) {
-        match entities::target::Entity::from_name(&c, db, cluster).await {
-            None => warn!("expected sibling {} doesn't exist", c),
-            Some(t) => {
-                if t.issues()
-                    .filter(
-                        entities::issue::Column::Status
-                            .is_in([IssueStatus::Open, IssueStatus::Opening]),
-                    )
-                    .filter(entities::issue::Column::ToOffline.eq(Some(ToOffline::Blade)))
-                    .one(db)
-                    .await
-                    .unwrap()
-                    .is_some()
-                {
-                    trace!("Offline due to blade wide ticket");
-                    return (TargetStatus::Offline, format!("{} sibling", &target));
-                }
-            }
-        };
-    }
-    if let Some(iss) = t
-        .issues()
-        .filter(entities::issue::Column::Status.is_in([IssueStatus::Open, IssueStatus::Opening]))
-        .filter(Expr::col(entities::issue::Column::ToOffline).is_null())
-        .one(db)
-        .await
-        .unwrap()
+    if let Some(t) = &t
+        && let Some(iss) = t
+            .issues()
+            .filter(entities::issue::Column::Status.is_in(open))
+            .filter(Expr::col(entities::issue::Column::ToOffline).is_null())
+            .one(db)
+            .await
+            .unwrap()
     {
         trace!("Down due to node ticket");
         return (TargetStatus::Down, iss.title);
@@ -454,3 +959,440 @@ pub async fn desired_state(
     trace!("Online due to no related tickets");
     (TargetStatus::Online, "".to_string())
 }
+
+/// a sync pass with the default options
+#[cfg(test)]
+async fn sync_pass(
+    db: &DatabaseConnection,
+    cluster: &RegexCluster,
+    tx: &mpsc::Sender<ChangeLogMsg>,
+) -> Result<Vec<PlanEntry>, String> {
+    sync_once(db, cluster, tx, &SyncOptions::default()).await
+}
+
+#[tokio::test]
+async fn down_node_opens_and_closes_issue() {
+    let db = crate::setup::test_db().await;
+    let (cluster, sched) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+
+    sync_pass(&db, &cluster, &tx).await.unwrap();
+    sched.set_node("gu0003", TargetStatus::Down, "node down");
+    sync_once(
+        &db,
+        &cluster,
+        &tx,
+        &SyncOptions {
+            severity: Severity::Major,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let issues = entities::issue::Entity::find().all(&db).await.unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].title, "node down");
+    assert_eq!(issues[0].status, IssueStatus::Opening);
+    assert_eq!(issues[0].severity, Severity::Major);
+    assert_eq!(issues[0].category, Some(Category::SchedulerDetected));
+    assert_eq!(get_ctt_nodes(&db).await["gu0003"], TargetStatus::Down);
+
+    sync_pass(&db, &cluster, &tx).await.unwrap();
+    let iss = entities::issue::Entity::find_by_id(issues[0].id)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(iss.status, IssueStatus::Open);
+
+    sched.set_node("gu0003", TargetStatus::Online, "");
+    sync_pass(&db, &cluster, &tx).await.unwrap();
+    let iss = entities::issue::Entity::find_by_id(issues[0].id)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(iss.status, IssueStatus::Closed);
+    assert_eq!(get_ctt_nodes(&db).await["gu0003"], TargetStatus::Online);
+    // ctt never needed to touch the scheduler
+    assert!(sched.calls().is_empty());
+
+    let events = entities::audit_event::Entity::find()
+        .all(&db)
+        .await
+        .unwrap();
+    assert_eq!(
+        events
+            .iter()
+            .map(|e| (e.action, e.actor.as_str(), e.method.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (AuditAction::AutoOpen, "ctt", "Sync"),
+            (AuditAction::Promote, "ctt", "Sync"),
+            (AuditAction::AutoClose, "ctt", "Sync")
+        ]
+    );
+    // the pass after opening promotes the issue from Opening to Open
+    assert_eq!(events[1].before.as_ref().unwrap()["status"], "Opening");
+    assert_eq!(events[1].after.as_ref().unwrap()["status"], "Open");
+    assert_eq!(events[2].before.as_ref().unwrap()["status"], "Open");
+    assert_eq!(events[2].after.as_ref().unwrap()["status"], "Closed");
+    // each pass gets its own correlation id
+    assert_ne!(events[0].correlation_id, events[1].correlation_id);
+    assert_ne!(events[1].correlation_id, events[2].correlation_id);
+}
+
+#[tokio::test]
+async fn recurring_failure_reopens_issue() {
+    let db = crate::setup::test_db().await;
+    let (cluster, sched) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+    let opts = SyncOptions {
+        reopen_window: Some(3600),
+        ..Default::default()
+    };
+
+    sync_once(&db, &cluster, &tx, &opts).await.unwrap();
+    sched.set_node("gu0003", TargetStatus::Down, "node down");
+    sync_once(&db, &cluster, &tx, &opts).await.unwrap();
+    sched.set_node("gu0003", TargetStatus::Online, "");
+    sync_once(&db, &cluster, &tx, &opts).await.unwrap();
+    sched.set_node("gu0003", TargetStatus::Down, "node down");
+    sync_once(&db, &cluster, &tx, &opts).await.unwrap();
+
+    let issues = entities::issue::Entity::find().all(&db).await.unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].status, IssueStatus::Opening);
+    assert_eq!(issues[0].reopen_count, 1);
+}
+
+#[tokio::test]
+async fn multi_target_issue_offlines_every_target() {
+    use crate::cluster::scheduler::SchedulerCall;
+    let db = crate::setup::test_db().await;
+    let (cluster, sched) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+
+    let new_issue = crate::model::NewIssue::new(
+        None,
+        "switch down".to_string(),
+        "switch down".to_string(),
+        "gu0001".to_string(),
+        Some(ToOffline::Node),
+        &cluster,
+    )
+    .unwrap()
+    .with_targets(vec!["gu0003".to_string(), "gu0004".to_string()]);
+    let iss = mutation::issue_open(&new_issue, "tester", &db, &tx, &cluster)
+        .await
+        .unwrap();
+    assert_eq!(
+        iss.get_targets(&db)
+            .await
+            .into_iter()
+            .map(|t| t.name)
+            .collect::<Vec<_>>(),
+        vec!["gu0001", "gu0003", "gu0004"]
+    );
+    assert_eq!(
+        desired_state("gu0004", &db, &cluster).await.0,
+        TargetStatus::Offline
+    );
+    assert_eq!(
+        desired_state("gu0002", &db, &cluster).await.0,
+        TargetStatus::Online
+    );
+
+    sync_pass(&db, &cluster, &tx).await.unwrap();
+    let mut calls = sched.calls();
+    calls.sort_by_key(|c| format!("{:?}", c));
+    assert_eq!(
+        calls,
+        ["gu0001", "gu0003", "gu0004"]
+            .into_iter()
+            .map(|t| SchedulerCall::Offline {
+                target: t.to_string(),
+                comment: "".to_string()
+            })
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn card_issue_offlines_and_resumes_siblings() {
+    use crate::cluster::scheduler::SchedulerCall;
+    let db = crate::setup::test_db().await;
+    let (cluster, sched) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+
+    let new_issue = crate::model::NewIssue::new(
+        None,
+        "bad card".to_string(),
+        "bad card".to_string(),
+        "gu0001".to_string(),
+        Some(ToOffline::Card),
+        &cluster,
+    )
+    .unwrap();
+    let iss = mutation::issue_open(&new_issue, "tester", &db, &tx, &cluster)
+        .await
+        .unwrap();
+    sync_pass(&db, &cluster, &tx).await.unwrap();
+    let mut calls = sched.calls();
+    calls.sort_by_key(|c| format!("{:?}", c));
+    assert_eq!(
+        calls,
+        vec![
+            SchedulerCall::Offline {
+                target: "gu0001".to_string(),
+                comment: "".to_string()
+            },
+            SchedulerCall::Offline {
+                target: "gu0002".to_string(),
+                comment: "".to_string()
+            },
+        ]
+    );
+    let ctt_nodes = get_ctt_nodes(&db).await;
+    assert_eq!(ctt_nodes["gu0001"], TargetStatus::Draining);
+    assert_eq!(ctt_nodes["gu0002"], TargetStatus::Draining);
+
+    let mut closing: entities::issue::ActiveModel = iss.into();
+    closing.status = ActiveValue::Set(IssueStatus::Closing);
+    closing.update(&db).await.unwrap();
+    sync_pass(&db, &cluster, &tx).await.unwrap();
+    let mut calls = sched.calls().split_off(2);
+    calls.sort_by_key(|c| format!("{:?}", c));
+    assert_eq!(
+        calls,
+        vec![
+            SchedulerCall::Release {
+                target: "gu0001".to_string()
+            },
+            SchedulerCall::Release {
+                target: "gu0002".to_string()
+            },
+        ]
+    );
+    let ctt_nodes = get_ctt_nodes(&db).await;
+    assert_eq!(ctt_nodes["gu0001"], TargetStatus::Online);
+    assert_eq!(ctt_nodes["gu0002"], TargetStatus::Online);
+
+    // one event per node, all the nodes a pass touched share its correlation id
+    let events = entities::audit_event::Entity::find()
+        .all(&db)
+        .await
+        .unwrap();
+    for (action, state) in [
+        (AuditAction::Offline, TargetStatus::Draining),
+        (AuditAction::Resume, TargetStatus::Online),
+    ] {
+        let mut nodes = events.iter().filter(|e| e.action == action);
+        let (a, b) = (nodes.next().unwrap(), nodes.next().unwrap());
+        assert!(nodes.next().is_none());
+        assert_eq!(a.correlation_id, b.correlation_id);
+        assert_eq!(
+            BTreeSet::from([a.entity_id.as_str(), b.entity_id.as_str()]),
+            BTreeSet::from(["gu0001", "gu0002"])
+        );
+        assert_eq!(a.after.as_ref().unwrap()["state"], json!(state));
+    }
+}
+
+#[tokio::test]
+async fn dry_run_only_plans() {
+    let db = crate::setup::test_db().await;
+    let (cluster, sched) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+
+    let new_issue = crate::model::NewIssue::new(
+        None,
+        "bad card".to_string(),
+        "bad card".to_string(),
+        "gu0003".to_string(),
+        Some(ToOffline::Card),
+        &cluster,
+    )
+    .unwrap();
+    let iss = mutation::issue_open(&new_issue, "tester", &db, &tx, &cluster)
+        .await
+        .unwrap();
+    sched.set_node("gu0001", TargetStatus::Down, "node down");
+    let mut plan = sync_once(
+        &db,
+        &cluster,
+        &tx,
+        &SyncOptions {
+            dry_run: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    plan.sort_by(|a, b| a.node.cmp(&b.node));
+    let reason = format!("issue {}: bad card", iss.id);
+    assert_eq!(
+        plan,
+        vec![
+            PlanEntry {
+                node: "gu0001".to_string(),
+                current_state: Some(TargetStatus::Down),
+                desired_state: None,
+                action: SyncAction::OpenIssue,
+                reason: "node down".to_string(),
+            },
+            PlanEntry {
+                node: "gu0003".to_string(),
+                current_state: Some(TargetStatus::Online),
+                desired_state: Some(TargetStatus::Offline),
+                action: SyncAction::Offline,
+                reason: reason.clone(),
+            },
+            PlanEntry {
+                node: "gu0004".to_string(),
+                current_state: Some(TargetStatus::Online),
+                desired_state: Some(TargetStatus::Offline),
+                action: SyncAction::Offline,
+                reason,
+            },
+        ]
+    );
+    assert!(sched.calls().is_empty());
+    assert_eq!(cluster.last_status("gu0001"), None);
+    let issues = entities::issue::Entity::find().all(&db).await.unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].status, IssueStatus::Opening);
+}
+
+#[tokio::test]
+async fn sync_limit_pauses_sync() {
+    let db = crate::setup::test_db().await;
+    let (cluster, sched) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+    let opts = SyncOptions {
+        limit: SyncLimit {
+            max_nodes: Some(2),
+            max_percent: None,
+        },
+        ..Default::default()
+    };
+
+    for n in ["gu0001", "gu0002", "gu0003"] {
+        sched.set_node(n, TargetStatus::Down, "rack lost power");
+    }
+    assert!(sync_once(&db, &cluster, &tx, &opts).await.is_err());
+    let alert = entities::alert::Entity::unacknowledged()
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(entities::issue::Entity::find()
+        .all(&db)
+        .await
+        .unwrap()
+        .is_empty());
+    // stays paused until acknowledged
+    assert!(sync_once(&db, &cluster, &tx, &opts).await.is_err());
+
+    let mut acked: entities::alert::ActiveModel = alert.into();
+    acked.acknowledged_by = ActiveValue::Set(Some("tester".to_string()));
+    let acked = acked.update(&db).await.unwrap();
+    sync_once(&db, &cluster, &tx, &opts).await.unwrap();
+    assert_eq!(
+        entities::issue::Entity::find()
+            .all(&db)
+            .await
+            .unwrap()
+            .len(),
+        3
+    );
+    let acked = entities::alert::Entity::find_by_id(acked.id)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(acked.resumed_at.is_some());
+}
+
+#[tokio::test]
+async fn acknowledging_only_lets_through_the_listed_nodes() {
+    use sea_orm::ModelTrait;
+
+    let db = crate::setup::test_db().await;
+    let (cluster, sched) = crate::cluster::test_cluster();
+    let (tx, _rx) = mpsc::channel(100);
+    let opts = SyncOptions {
+        limit: SyncLimit {
+            max_nodes: Some(1),
+            max_percent: None,
+        },
+        ..Default::default()
+    };
+    let pass = || sync_once(&db, &cluster, &tx, &opts);
+    let ack = |alert: entities::alert::Model| {
+        let mut acked: entities::alert::ActiveModel = alert.into();
+        acked.acknowledged_by = ActiveValue::Set(Some("tester".to_string()));
+        acked.update(&db)
+    };
+
+    for n in ["gu0001", "gu0002"] {
+        sched.set_node(n, TargetStatus::Down, "rack lost power");
+    }
+    assert!(pass().await.is_err());
+    let first = entities::alert::Entity::unacknowledged()
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(first.description, "gu[0001-0002]");
+    assert_eq!(
+        first
+            .find_related(entities::alert_node::Entity)
+            .all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|n| n.node)
+            .collect::<Vec<_>>(),
+        ["gu0001", "gu0002"]
+    );
+    // the description is only for people, rewording it doesn't change what the ack covers
+    let mut first: entities::alert::ActiveModel = first.into();
+    first.description = ActiveValue::Set("rack r12 lost power".to_string());
+    let first = first.update(&db).await.unwrap();
+    ack(first).await.unwrap();
+
+    // the rest of the rack went down before the next pass, that still needs its own ack
+    for n in ["gu0003", "gu0004"] {
+        sched.set_node(n, TargetStatus::Down, "rack lost power");
+    }
+    assert!(pass().await.is_err());
+    let second = entities::alert::Entity::unacknowledged()
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(second.description, "gu[0003-0004]");
+    assert!(entities::issue::Entity::find()
+        .all(&db)
+        .await
+        .unwrap()
+        .is_empty());
+
+    ack(second).await.unwrap();
+    pass().await.unwrap();
+    assert_eq!(
+        entities::issue::Entity::find()
+            .all(&db)
+            .await
+            .unwrap()
+            .len(),
+        4
+    );
+    assert!(entities::alert::Entity::find()
+        .all(&db)
+        .await
+        .unwrap()
+        .iter()
+        .all(|a| a.resumed_at.is_some()));
+}
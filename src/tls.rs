@@ -0,0 +1,234 @@
+use crate::conf::Conf;
+use axum::middleware::AddExtension;
+use axum::Extension;
+use axum_server::accept::Accept;
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use futures_util::future::BoxFuture;
+use rustls::pki_types::CertificateDer;
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tower::Layer;
+use tracing::info;
+use x509_parser::extensions::GeneralName;
+
+/// certificate and key from `certs_dir`, asking clients for a certificate signed by
+/// `auth.client_ca` if it's set
+pub async fn rustls_config(conf: &Conf) -> io::Result<RustlsConfig> {
+    let dir = PathBuf::from(&conf.certs_dir);
+    match &conf.auth.client_ca {
+        None => RustlsConfig::from_pem_file(dir.join("cert.pem"), dir.join("key.pem")).await,
+        Some(ca) => {
+            info!("verifying client certificates against {}", ca);
+            let config = server_config(&dir, &dir.join(ca))?;
+            Ok(RustlsConfig::from_config(Arc::new(config)))
+        }
+    }
+}
+
+fn server_config(dir: &Path, ca: &Path) -> io::Result<ServerConfig> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(dir.join("cert.pem"))?))
+        .collect::<Result<Vec<_>, _>>()?;
+    let key =
+        rustls_pemfile::private_key(&mut BufReader::new(File::open(dir.join("key.pem"))?))?.ok_or(
+            io::Error::new(io::ErrorKind::InvalidData, "no private key in key.pem"),
+        )?;
+    let mut roots = RootCertStore::empty();
+    for c in rustls_pemfile::certs(&mut BufReader::new(File::open(ca)?)) {
+        roots.add(c?).map_err(io::Error::other)?;
+    }
+    // clients without a certificate still log in with munge or an api key
+    let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+        .allow_unauthenticated()
+        .build()
+        .map_err(io::Error::other)?;
+    let mut config = ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(certs, key)
+        .map_err(io::Error::other)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// certificate the client presented during the handshake, already verified against
+/// `auth.client_ca`, set on every request made over the connection
+#[derive(Clone, Debug, Default)]
+pub struct PeerCert(pub Option<CertificateDer<'static>>);
+
+/// names a client certificate can be matched by, and when it stops being valid
+#[derive(Debug, PartialEq, Eq)]
+pub struct CertIdentity {
+    /// subject common names, then dns, email and uri subject alt names
+    pub names: Vec<String>,
+    /// unix timestamp
+    pub not_after: i64,
+}
+
+pub fn identity(cert: &CertificateDer) -> Result<CertIdentity, String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert).map_err(|e| e.to_string())?;
+    let mut names: Vec<String> = cert
+        .subject()
+        .iter_common_name()
+        .filter_map(|cn| cn.as_str().ok())
+        .map(str::to_string)
+        .collect();
+    if let Some(san) = cert.subject_alternative_name().map_err(|e| e.to_string())? {
+        for n in &san.value.general_names {
+            match n {
+                GeneralName::DNSName(s) | GeneralName::RFC822Name(s) | GeneralName::URI(s) => {
+                    names.push(s.to_string())
+                }
+                _ => (),
+            }
+        }
+    }
+    Ok(CertIdentity {
+        names,
+        not_after: cert.validity().not_after.timestamp(),
+    })
+}
+
+/// tls acceptor that adds the client's certificate to each request as a `PeerCert`
+#[derive(Clone, Debug)]
+pub struct ClientCertAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl ClientCertAcceptor {
+    pub fn new(config: RustlsConfig) -> Self {
+        Self {
+            inner: RustlsAcceptor::new(config),
+        }
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientCertAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, PeerCert>;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let acceptor = self.inner.clone();
+        Box::pin(async move {
+            let (stream, service) = acceptor.accept(stream, service).await?;
+            let cert = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|c| c.first())
+                .map(|c| c.clone().into_owned());
+            Ok((stream, Extension(PeerCert(cert)).layer(service)))
+        })
+    }
+}
+
+#[tokio::test]
+async fn client_certs_are_verified_against_the_ca() {
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+        SanType,
+    };
+    use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+    let ca_key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(vec![]).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "ctt test ca");
+    let ca = params.self_signed(&ca_key).unwrap();
+    let params_for = |names: Vec<String>, cn: &str, usage| {
+        let mut params = CertificateParams::new(names).unwrap();
+        params.distinguished_name.push(DnType::CommonName, cn);
+        params.extended_key_usages = vec![usage];
+        params
+    };
+
+    let dir = std::env::temp_dir().join(format!("cttd-tls-{}", rand::random::<u64>()));
+    std::fs::create_dir(&dir).unwrap();
+    let server_key = KeyPair::generate().unwrap();
+    let server = params_for(
+        vec!["localhost".to_string()],
+        "localhost",
+        ExtendedKeyUsagePurpose::ServerAuth,
+    )
+    .signed_by(&server_key, &ca, &ca_key)
+    .unwrap();
+    std::fs::write(dir.join("cert.pem"), server.pem()).unwrap();
+    std::fs::write(dir.join("key.pem"), server_key.serialize_pem()).unwrap();
+    std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+    let server = TlsAcceptor::from(Arc::new(server_config(&dir, &dir.join("ca.pem")).unwrap()));
+
+    let client_key = KeyPair::generate().unwrap();
+    let mut params = params_for(
+        vec!["auto01.example.com".to_string()],
+        "healthcheck",
+        ExtendedKeyUsagePurpose::ClientAuth,
+    );
+    params
+        .subject_alt_names
+        .push(SanType::Rfc822Name("ops@example.com".try_into().unwrap()));
+    let client = params.signed_by(&client_key, &ca, &ca_key).unwrap();
+    let rogue_key = KeyPair::generate().unwrap();
+    let rogue = params_for(
+        vec!["auto01.example.com".to_string()],
+        "healthcheck",
+        ExtendedKeyUsagePurpose::ClientAuth,
+    )
+    .self_signed(&rogue_key)
+    .unwrap();
+
+    // handshake over an in memory pipe, returning the certificate the server saw
+    let connect = |cert: Option<(&rcgen::Certificate, &KeyPair)>| {
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        let builder = rustls::ClientConfig::builder().with_root_certificates(roots);
+        let config = match cert {
+            Some((cert, key)) => builder
+                .with_client_auth_cert(
+                    vec![cert.der().clone()],
+                    rustls::pki_types::PrivateKeyDer::try_from(key.serialize_der()).unwrap(),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+        let connector = TlsConnector::from(Arc::new(config));
+        let server = server.clone();
+        async move {
+            let (a, b) = tokio::io::duplex(16 * 1024);
+            let name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+            let (client, server) = tokio::join!(connector.connect(name, a), server.accept(b));
+            client?;
+            let server = server?;
+            Ok::<_, io::Error>(
+                server
+                    .get_ref()
+                    .1
+                    .peer_certificates()
+                    .and_then(|c| c.first())
+                    .map(|c| c.clone().into_owned()),
+            )
+        }
+    };
+
+    let seen = connect(Some((&client, &client_key)))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        identity(&seen).unwrap().names,
+        vec!["healthcheck", "auto01.example.com", "ops@example.com"]
+    );
+    // no certificate is fine, the client still needs a token
+    assert_eq!(connect(None).await.unwrap(), None);
+    assert!(connect(Some((&rogue, &rogue_key))).await.is_err());
+}